@@ -0,0 +1,219 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Preload library reporting the descriptors a process opens and closes to the
+//! supervisor that spawned it with `fdlimit::spawn_with_preload`.
+//!
+//! Records are sent as datagrams to the socket named by `FDLIMIT_PRELOAD_FD`,
+//! 20 native-endian bytes: the process ID (u32), the descriptor (i32), the
+//! event (u32, 1 when opened, 0 when closed) and the sequence number of the
+//! record in the process (u64, from 0). The variable, socket and library are
+//! inherited so the whole process tree reports to the same supervisor.
+//! Reporting stops if the process closes the socket and reuses its descriptor,
+//! and records are dropped rather than blocking when the socket is full, which
+//! the supervisor notices from the gaps in the sequence numbers.
+//!
+//! Only calls going through the dynamic symbols are seen, descriptors opened by
+//! libc internally or with raw system calls aren't.
+
+#![cfg(target_os = "linux")]
+// The hooks have the safety requirements of the libc functions they replace
+#![allow(clippy::missing_safety_doc)]
+
+use libc::{c_char, c_int, mode_t, sockaddr, socklen_t};
+use std::ffi::CStr;
+use std::sync::atomic::{AtomicI32, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+
+const UNINIT: c_int = -2;
+const DISABLED: c_int = -1;
+
+static REPORT_FD: AtomicI32 = AtomicI32::new(UNINIT);
+/// Device and inode of the socket, to notice the descriptor was closed and its
+/// number reused by the process
+static REPORT_DEV: AtomicU64 = AtomicU64::new(0);
+static REPORT_INO: AtomicU64 = AtomicU64::new(0);
+/// Sequence number of the next record and the process it counts for, forked
+/// children start again from 0
+static SEQ: AtomicU64 = AtomicU64::new(0);
+static SEQ_PID: AtomicU32 = AtomicU32::new(0);
+
+fn report_fd() -> c_int {
+	let fd = REPORT_FD.load(Ordering::Acquire);
+	if fd != UNINIT {
+		return fd;
+	}
+
+	let value = unsafe { libc::getenv(c"FDLIMIT_PRELOAD_FD".as_ptr()) };
+	let fd = match value.is_null() {
+		true => DISABLED,
+		false => unsafe { CStr::from_ptr(value) }
+			.to_str()
+			.ok()
+			.and_then(|value| value.parse().ok())
+			.unwrap_or(DISABLED),
+	};
+	let fd = match identity(fd) {
+		Some((dev, ino)) => {
+			REPORT_DEV.store(dev, Ordering::Relaxed);
+			REPORT_INO.store(ino, Ordering::Relaxed);
+			fd
+		}
+		None => DISABLED,
+	};
+	REPORT_FD.store(fd, Ordering::Release);
+	fd
+}
+
+/// Device and inode of the descriptor, if it's a socket
+fn identity(fd: c_int) -> Option<(u64, u64)> {
+	if fd < 0 {
+		return None;
+	}
+	let mut stat = unsafe { std::mem::zeroed::<libc::stat>() };
+	if unsafe { libc::fstat(fd, &mut stat) } != 0 || stat.st_mode & libc::S_IFMT != libc::S_IFSOCK {
+		return None;
+	}
+	Some((stat.st_dev, stat.st_ino))
+}
+
+fn report(fd: c_int, opened: bool) {
+	let report = report_fd();
+	if fd < 0 || report < 0 || fd == report {
+		return;
+	}
+
+	let pid = std::process::id();
+	let mut record = [0u8; 20];
+	record[..4].copy_from_slice(&pid.to_ne_bytes());
+	record[4..8].copy_from_slice(&fd.to_ne_bytes());
+	record[8..12].copy_from_slice(&u32::from(opened).to_ne_bytes());
+	record[12..].copy_from_slice(&next_seq(pid).to_ne_bytes());
+
+	// Keep the caller's errno, don't block when the supervisor falls behind
+	// and don't get killed if it's gone
+	let errno = unsafe { *libc::__errno_location() };
+	let identity = (REPORT_DEV.load(Ordering::Relaxed), REPORT_INO.load(Ordering::Relaxed));
+	if self::identity(report) == Some(identity) {
+		let flags = libc::MSG_DONTWAIT | libc::MSG_NOSIGNAL;
+		unsafe { libc::send(report, record.as_ptr().cast(), record.len(), flags) };
+	} else {
+		// The process closed the socket, the descriptor may now be another file
+		REPORT_FD.store(DISABLED, Ordering::Relaxed);
+	}
+	unsafe { *libc::__errno_location() = errno };
+}
+
+/// Sequence number of the next record of the process, only the forking thread
+/// is left in a child so resetting the counter doesn't race
+fn next_seq(pid: u32) -> u64 {
+	if SEQ_PID.load(Ordering::Relaxed) != pid {
+		SEQ_PID.store(pid, Ordering::Relaxed);
+		SEQ.store(0, Ordering::Relaxed);
+	}
+	SEQ.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Next definition of the symbol, the one the hook wraps
+macro_rules! real {
+	($name:expr => $ty:ty) => {{
+		static ADDR: AtomicUsize = AtomicUsize::new(0);
+		let mut addr = ADDR.load(Ordering::Relaxed);
+		if addr == 0 {
+			addr = unsafe { libc::dlsym(libc::RTLD_NEXT, $name.as_ptr().cast()) } as usize;
+			ADDR.store(addr, Ordering::Relaxed);
+		}
+		match addr {
+			0 => {
+				unsafe { *libc::__errno_location() = libc::ENOSYS };
+				return -1;
+			}
+			addr => unsafe { std::mem::transmute::<usize, $ty>(addr) },
+		}
+	}};
+}
+
+/// Hook reporting the returned descriptor as opened
+macro_rules! hook_open {
+	($name:ident($($arg:ident: $ty:ty),*)) => {
+		#[no_mangle]
+		pub unsafe extern "C" fn $name($($arg: $ty),*) -> c_int {
+			type Real = unsafe extern "C" fn($($ty),*) -> c_int;
+			let real = real!(concat!(stringify!($name), "\0").as_bytes() => Real);
+			let fd = real($($arg),*);
+			report(fd, true);
+			fd
+		}
+	};
+}
+
+// open and openat are variadic, the mode is only read by the kernel for
+// O_CREAT and O_TMPFILE and is passed in the same register as a regular
+// argument on the supported architectures
+hook_open!(open(path: *const c_char, flags: c_int, mode: mode_t));
+hook_open!(open64(path: *const c_char, flags: c_int, mode: mode_t));
+hook_open!(openat(dirfd: c_int, path: *const c_char, flags: c_int, mode: mode_t));
+hook_open!(openat64(dirfd: c_int, path: *const c_char, flags: c_int, mode: mode_t));
+hook_open!(creat(path: *const c_char, mode: mode_t));
+hook_open!(socket(domain: c_int, kind: c_int, protocol: c_int));
+hook_open!(accept(fd: c_int, addr: *mut sockaddr, len: *mut socklen_t));
+hook_open!(accept4(fd: c_int, addr: *mut sockaddr, len: *mut socklen_t, flags: c_int));
+hook_open!(dup(fd: c_int));
+hook_open!(dup2(fd: c_int, new: c_int));
+hook_open!(dup3(fd: c_int, new: c_int, flags: c_int));
+
+#[no_mangle]
+pub unsafe extern "C" fn pipe(fds: *mut c_int) -> c_int {
+	pipe2(fds, 0)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn pipe2(fds: *mut c_int, flags: c_int) -> c_int {
+	let real = real!(b"pipe2\0" => unsafe extern "C" fn(*mut c_int, c_int) -> c_int);
+	let ret = real(fds, flags);
+	if ret == 0 {
+		report(*fds, true);
+		report(*fds.add(1), true);
+	}
+	ret
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn close(fd: c_int) -> c_int {
+	let real = real!(b"close\0" => unsafe extern "C" fn(c_int) -> c_int);
+	let ret = real(fd);
+	if ret == 0 {
+		report(fd, false);
+	}
+	ret
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn identifies_sockets_only() {
+		let mut fds = [0; 2];
+		let ret = unsafe { libc::socketpair(libc::AF_UNIX, libc::SOCK_DGRAM, 0, fds.as_mut_ptr()) };
+		assert_eq!(ret, 0);
+		let socket = identity(fds[0]).unwrap();
+		assert_ne!(identity(fds[1]), Some(socket));
+
+		let file = std::fs::File::open("/proc/self/status").unwrap();
+		assert_eq!(identity(std::os::fd::AsRawFd::as_raw_fd(&file)), None);
+		assert_eq!(identity(-1), None);
+		unsafe { libc::close(fds[0]) };
+		unsafe { libc::close(fds[1]) };
+	}
+}
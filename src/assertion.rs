@@ -0,0 +1,244 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::backend::RlimitOps;
+use crate::{Error, Limit, Resource};
+use std::fmt;
+
+/// Soft or hard side of a resource limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Side {
+	/// Soft limit, the value enforced by the kernel
+	Soft,
+	/// Hard limit, the ceiling for the soft limit
+	Hard,
+}
+
+/// How the limit is compared to the expected value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Comparison {
+	/// The limit is at least the value
+	AtLeast,
+	/// The limit is at most the value
+	AtMost,
+	/// The limit is the value
+	Equal,
+}
+
+/// Invariant on a resource limit checked by [`check_limits`], usually built
+/// with [`assert_limits!`](crate::assert_limits) or
+/// [`check_limits!`](crate::check_limits!)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LimitAssertion {
+	/// Resource checked
+	pub resource: Resource,
+	/// Limit checked
+	pub side: Side,
+	/// How the limit compares to the value
+	pub comparison: Comparison,
+	/// Expected value
+	pub value: Limit,
+}
+
+impl LimitAssertion {
+	/// Whether the limit satisfies the assertion
+	pub fn holds(&self, limit: Limit) -> bool {
+		match self.comparison {
+			Comparison::AtLeast => limit >= self.value,
+			Comparison::AtMost => limit <= self.value,
+			Comparison::Equal => limit == self.value,
+		}
+	}
+}
+
+/// Check the current limits against the assertions, failing with
+/// [`Error::AssertionFailed`] at the first one that doesn't hold
+pub fn check_limits(assertions: &[LimitAssertion]) -> Result<(), Error> {
+	check_limits_with(&crate::backend::System, assertions)
+}
+
+/// Same as [`check_limits`] using the given backend
+pub fn check_limits_with<B: RlimitOps>(
+	backend: &B,
+	assertions: &[LimitAssertion],
+) -> Result<(), Error> {
+	for &assertion in assertions {
+		let rlim = crate::get_limit_with(backend, assertion.resource)?;
+		let actual = match assertion.side {
+			Side::Soft => rlim.soft_limit(),
+			Side::Hard => rlim.hard_limit(),
+		};
+		if !assertion.holds(actual) {
+			let remediation = match actual > assertion.value {
+				true => crate::remediation::lowering(
+					assertion.resource,
+					assertion.side,
+					assertion.value,
+				),
+				false => crate::remediation(assertion.resource, assertion.value).to_string(),
+			};
+			return Err(Error::AssertionFailed { assertion, actual, remediation });
+		}
+	}
+	Ok(())
+}
+
+/// Check invariants on the current limits, returning the first failure as an
+/// [`Error::AssertionFailed`] carrying the steps to fix it.
+///
+/// Each invariant is a [`Resource`](crate::Resource) variant, `soft` or
+/// `hard`, one of `>=`, `<=` or `==`, and a value, a number or a
+/// [`Limit`](crate::Limit):
+///
+/// ```no_run
+/// # use fdlimit::{check_limits, Limit};
+/// # fn main() -> Result<(), fdlimit::Error> {
+/// check_limits!(NoFile.soft >= 8192, NoFile.hard >= 65536, Core.soft == Limit::Unlimited)?;
+/// # Ok(())
+/// # }
+/// ```
+#[macro_export]
+macro_rules! check_limits {
+	($($resource:ident . $side:ident $op:tt $value:expr),+ $(,)?) => {
+		$crate::check_limits(&[$($crate::LimitAssertion {
+			resource: $crate::Resource::$resource,
+			side: $crate::__limit_side!($side),
+			comparison: $crate::__limit_comparison!($op),
+			value: $crate::__IntoLimit::__into_limit($value),
+		}),+])
+	};
+}
+
+/// Same as [`check_limits!`](crate::check_limits!) panicking with the failure
+/// and the steps to fix it, e.g. at the start of `main`:
+///
+/// ```no_run
+/// # use fdlimit::assert_limits;
+/// assert_limits!(NoFile.soft >= 8192, NProc.soft >= 4096);
+/// ```
+#[macro_export]
+macro_rules! assert_limits {
+	($($assertion:tt)+) => {
+		if let Err(error) = $crate::check_limits!($($assertion)+) {
+			panic!("{}", error);
+		}
+	};
+}
+
+/// Value of an assertion, a number being a finite limit
+#[doc(hidden)]
+pub trait __IntoLimit {
+	fn __into_limit(self) -> Limit;
+}
+
+impl __IntoLimit for u64 {
+	fn __into_limit(self) -> Limit {
+		Limit::Finite(self)
+	}
+}
+
+impl __IntoLimit for Limit {
+	fn __into_limit(self) -> Limit {
+		self
+	}
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __limit_side {
+	(soft) => {
+		$crate::Side::Soft
+	};
+	(hard) => {
+		$crate::Side::Hard
+	};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __limit_comparison {
+	(>=) => {
+		$crate::Comparison::AtLeast
+	};
+	(<=) => {
+		$crate::Comparison::AtMost
+	};
+	(==) => {
+		$crate::Comparison::Equal
+	};
+}
+
+impl fmt::Display for Side {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.pad(match self {
+			Side::Soft => "soft",
+			Side::Hard => "hard",
+		})
+	}
+}
+
+impl fmt::Display for Comparison {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.pad(match self {
+			Comparison::AtLeast => ">=",
+			Comparison::AtMost => "<=",
+			Comparison::Equal => "==",
+		})
+	}
+}
+
+/// For example `nofile soft limit >= 8192`
+impl fmt::Display for LimitAssertion {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{} {} limit {} {}", self.resource, self.side, self.comparison, self.value)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::simulated::Simulated;
+
+	fn assertion(comparison: Comparison, value: u64) -> LimitAssertion {
+		let value = Limit::Finite(value);
+		LimitAssertion { resource: Resource::NoFile, side: Side::Soft, comparison, value }
+	}
+
+	#[test]
+	fn suggests_raising_a_low_limit() {
+		let backend = Simulated::new(256, 4096);
+		let error =
+			check_limits_with(&backend, &[assertion(Comparison::AtLeast, 1024)]).unwrap_err();
+		let Error::AssertionFailed { remediation, .. } = error else { panic!("{error}") };
+		assert!(!remediation.contains("lower"), "{remediation}");
+	}
+
+	#[test]
+	fn suggests_lowering_a_high_limit() {
+		let backend = Simulated::new(4096, 4096);
+		for comparison in [Comparison::AtMost, Comparison::Equal] {
+			let error = check_limits_with(&backend, &[assertion(comparison, 1024)]).unwrap_err();
+			let Error::AssertionFailed { actual, remediation, .. } = error else {
+				panic!("{error}")
+			};
+			assert_eq!(actual, Limit::Finite(4096));
+			assert_eq!(remediation, "lower it with `ulimit -Sn 1024` before starting the process");
+		}
+	}
+
+	#[test]
+	fn macro_takes_limits() {
+		assert!(crate::check_limits!(NoFile.hard <= Limit::Unlimited, NoFile.soft >= 0).is_ok());
+	}
+}
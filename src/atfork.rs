@@ -0,0 +1,130 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Re-applying the limit policy in child processes after `fork`.
+//!
+//! The child handler runs in a freshly forked process where only
+//! async-signal-safe operations are allowed. The strategy is evaluated in the
+//! parent by the prepare handler just before forking, and the child only
+//! calls `setrlimit` with the limits computed there, without locking,
+//! allocating, probing the platform or recording to the audit log.
+
+use crate::backend::{Rlimit, RlimitOps, System};
+use crate::{Error, Resource, Strategy};
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+
+const NONE: u8 = 0;
+const MAX_AVAILABLE: u8 = 1;
+const EXACT: u8 = 2;
+const AT_LEAST: u8 = 3;
+const FRACTION_OF_HARD: u8 = 4;
+
+static REGISTERED: AtomicBool = AtomicBool::new(false);
+static KIND: AtomicU8 = AtomicU8::new(NONE);
+static VALUE: AtomicU64 = AtomicU64::new(0);
+/// Limits computed by the prepare handler for the child, `SKIP` as soft
+/// limit when there's nothing to apply
+static SOFT: AtomicU64 = AtomicU64::new(SKIP);
+static HARD: AtomicU64 = AtomicU64::new(0);
+const SKIP: u64 = 0;
+
+/// Register (once) the `pthread_atfork` child handler and make it apply the
+/// given strategy, replacing any previously configured one
+pub(crate) fn reapply_after_fork(strategy: &Strategy) -> Result<(), Error> {
+	let (kind, value) = match *strategy {
+		Strategy::MaxAvailable => (MAX_AVAILABLE, 0),
+		Strategy::Exact(value) => (EXACT, value),
+		Strategy::AtLeast(value) => (AT_LEAST, value),
+		Strategy::FractionOfHard(fraction) => (FRACTION_OF_HARD, fraction.to_bits()),
+	};
+	// A fork racing with reconfiguration may observe the new kind with the old
+	// value, the raise then fails harmlessly in the child
+	VALUE.store(value, Ordering::Release);
+	KIND.store(kind, Ordering::Release);
+
+	// Make sure the child finds the kernel ceiling in the cache rather than
+	// reading it, which may allocate
+	crate::refresh_kernel_ceiling()?;
+
+	if REGISTERED.swap(true, Ordering::AcqRel) {
+		return Ok(());
+	}
+	let result = unsafe { libc::pthread_atfork(Some(prepare), None, Some(child)) };
+	if result != 0 {
+		REGISTERED.store(false, Ordering::Release);
+		return Err(Error::FailedToRegisterAtfork(io::Error::from_raw_os_error(result)));
+	}
+
+	Ok(())
+}
+
+/// Evaluate the strategy against the parent's limits, which the child
+/// inherits, right before forking
+extern "C" fn prepare() {
+	let value = VALUE.load(Ordering::Acquire);
+	let strategy = match KIND.load(Ordering::Acquire) {
+		MAX_AVAILABLE => Strategy::MaxAvailable,
+		EXACT => Strategy::Exact(value),
+		AT_LEAST => Strategy::AtLeast(value),
+		FRACTION_OF_HARD => Strategy::FractionOfHard(f64::from_bits(value)),
+		_ => return SOFT.store(SKIP, Ordering::Release),
+	};
+	let soft = System.getrlimit(Resource::NoFile).ok().and_then(|rlim| {
+		// The ceiling is cached by reapply_after_fork
+		let maxfiles = System.sysctl_maxfiles().ok().flatten();
+		let available = maxfiles.map_or(rlim.hard, |maxfiles| maxfiles.min(rlim.hard));
+		let soft = strategy.target(rlim.hard, available).ok()?;
+		HARD.store(rlim.hard, Ordering::Release);
+		Some(soft)
+	});
+	// A soft limit of 0 isn't applied, the process couldn't open anything
+	SOFT.store(soft.unwrap_or(SKIP), Ordering::Release);
+}
+
+extern "C" fn child() {
+	let soft = SOFT.load(Ordering::Acquire);
+	if soft == SKIP {
+		return;
+	}
+	let hard = HARD.load(Ordering::Acquire);
+	// There is nobody to report a failure to in the child, and setrlimit
+	// reports it through the raw errno without allocating
+	let _ = System.setrlimit(Resource::NoFile, Rlimit { soft, hard });
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn child_gets_the_strategy_evaluated_before_fork() {
+		let before = System.getrlimit(Resource::NoFile).unwrap();
+		reapply_after_fork(&Strategy::Exact(64)).unwrap();
+		let pid = unsafe { libc::fork() };
+		if pid == 0 {
+			// Only async-signal-safe calls in the child
+			let mut rlim = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+			let ok = unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut rlim) } == 0
+				&& rlim.rlim_cur == 64;
+			unsafe { libc::_exit(if ok { 0 } else { 1 }) };
+		}
+		assert!(pid > 0, "fork failed");
+		let mut status = 0;
+		assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+		KIND.store(NONE, Ordering::Release);
+		assert!(libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0, "status {status}");
+		assert_eq!(System.getrlimit(Resource::NoFile).unwrap(), before);
+	}
+}
@@ -0,0 +1,133 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! In-process record of the limit changes made through this crate.
+
+use crate::backend::Rlimit;
+use crate::Resource;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Mutex, MutexGuard};
+use std::time::SystemTime;
+
+const DEFAULT_CAPACITY: usize = 256;
+
+struct AuditLog {
+	entries: VecDeque<AuditEntry>,
+	capacity: usize,
+}
+
+static LOG: Mutex<AuditLog> =
+	Mutex::new(AuditLog { entries: VecDeque::new(), capacity: DEFAULT_CAPACITY });
+
+thread_local! {
+	static REASON: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Limit change made through this crate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditEntry {
+	/// When the limits were set
+	pub at: SystemTime,
+	/// Process whose limits changed, `None` for the current one
+	pub pid: Option<u32>,
+	/// Resource whose limits changed
+	pub resource: Resource,
+	/// Limits before the change
+	pub before: Rlimit,
+	/// Limits after the change
+	pub after: Rlimit,
+	/// Reason given with [`with_audit_reason`] or
+	/// [`Builder::reason`](crate::Builder::reason)
+	pub reason: Option<String>,
+}
+
+/// Limit changes made through this crate, oldest first.
+///
+/// Raises, setters and changes to other processes are recorded, the last 256
+/// by default, see [`set_audit_capacity`]. The allocation-free
+/// [`raise_fd_limit_infallible`](crate::raise_fd_limit_infallible) isn't.
+pub fn audit_log() -> Vec<AuditEntry> {
+	log().entries.iter().cloned().collect()
+}
+
+/// Keep the last `capacity` changes in the audit log, dropping the oldest
+/// ones if there are more
+pub fn set_audit_capacity(capacity: usize) {
+	let mut log = log();
+	log.capacity = capacity;
+	let excess = log.entries.len().saturating_sub(capacity);
+	log.entries.drain(..excess);
+}
+
+/// Run `f`, recording `reason` with the limit changes it makes on this thread
+pub fn with_audit_reason<T>(reason: impl Into<String>, f: impl FnOnce() -> T) -> T {
+	struct Restore(Option<String>);
+
+	impl Drop for Restore {
+		fn drop(&mut self) {
+			REASON.with(|reason| *reason.borrow_mut() = self.0.take());
+		}
+	}
+
+	let previous = REASON.with(|current| current.borrow_mut().replace(reason.into()));
+	let _restore = Restore(previous);
+	f()
+}
+
+/// Record a change, unless the limits stayed the same
+pub(crate) fn record(pid: Option<u32>, resource: Resource, before: Rlimit, after: Rlimit) {
+	if before == after {
+		return;
+	}
+	let reason = REASON.with(|reason| reason.borrow().clone());
+	let entry = AuditEntry { at: SystemTime::now(), pid, resource, before, after, reason };
+
+	let mut log = log();
+	if log.capacity == 0 {
+		return;
+	}
+	if log.entries.len() >= log.capacity {
+		log.entries.pop_front();
+	}
+	log.entries.push_back(entry);
+}
+
+fn log() -> MutexGuard<'static, AuditLog> {
+	LOG.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// For example `nofile soft 1024 -> 65536, hard 65536 (startup)`, naming the
+/// process when it's another one
+impl fmt::Display for AuditEntry {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.resource)?;
+		if let Some(pid) = self.pid {
+			write!(f, " of process {pid}")?;
+		}
+		write!(f, " soft {}", self.before.soft_limit())?;
+		if self.after.soft != self.before.soft {
+			write!(f, " -> {}", self.after.soft_limit())?;
+		}
+		write!(f, ", hard {}", self.before.hard_limit())?;
+		if self.after.hard != self.before.hard {
+			write!(f, " -> {}", self.after.hard_limit())?;
+		}
+		if let Some(reason) = &self.reason {
+			write!(f, " ({reason})")?;
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,504 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+use crate::raw::RawResource;
+use crate::{Limit, Resource};
+use std::io;
+#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Raw limit value meaning no limit on this platform
+#[cfg(unix)]
+#[allow(clippy::unnecessary_cast)]
+pub const RLIM_INFINITY: u64 = libc::RLIM_INFINITY as u64;
+
+/// Raw limit value meaning no limit on this platform
+#[cfg(not(unix))]
+pub const RLIM_INFINITY: u64 = u64::MAX;
+
+/// Value that doesn't fit the type it's converted to, carried by the
+/// [`io::Error`] of the failed operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum ConversionError {
+	/// Value too large for the platform's `rlim_t`
+	#[error("{0} is too large for rlim_t on this platform")]
+	Overflow(u64),
+	/// Negative value returned by the OS
+	#[error("Unexpected negative value {0}")]
+	Negative(i64),
+}
+
+impl From<ConversionError> for io::Error {
+	fn from(error: ConversionError) -> Self {
+		let kind = match error {
+			ConversionError::Overflow(_) => io::ErrorKind::InvalidInput,
+			ConversionError::Negative(_) => io::ErrorKind::InvalidData,
+		};
+		io::Error::new(kind, error)
+	}
+}
+
+/// Convert a limit value to the platform's `rlim_t`, keeping unlimited
+#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+#[cfg(any(feature = "nix", not(all(feature = "raw-syscalls", target_os = "linux"))))]
+#[allow(clippy::useless_conversion, clippy::unnecessary_fallible_conversions)]
+pub(crate) fn to_rlim(value: u64) -> Result<libc::rlim_t, ConversionError> {
+	if value == RLIM_INFINITY {
+		return Ok(libc::RLIM_INFINITY);
+	}
+	libc::rlim_t::try_from(value).map_err(|_| ConversionError::Overflow(value))
+}
+
+/// Convert a limit value from the platform's `rlim_t`, signed on FreeBSD,
+/// keeping unlimited
+#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+#[cfg(any(feature = "nix", not(all(feature = "raw-syscalls", target_os = "linux"))))]
+#[allow(
+	clippy::useless_conversion,
+	clippy::unnecessary_fallible_conversions,
+	clippy::unnecessary_cast
+)]
+pub(crate) fn from_rlim(value: libc::rlim_t) -> Result<u64, ConversionError> {
+	if value == libc::RLIM_INFINITY {
+		return Ok(RLIM_INFINITY);
+	}
+	u64::try_from(value).map_err(|_| ConversionError::Negative(value as i64))
+}
+
+/// Convert an integer sysctl value
+#[cfg(any(target_vendor = "apple", target_os = "freebsd"))]
+pub(crate) fn from_c_int(value: libc::c_int) -> Result<u64, ConversionError> {
+	u64::try_from(value).map_err(|_| ConversionError::Negative(value.into()))
+}
+
+/// Soft and hard values of a resource limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rlimit {
+	/// Soft limit, the value enforced by the kernel
+	pub soft: u64,
+	/// Hard limit, the ceiling for the soft limit
+	pub hard: u64,
+}
+
+impl Rlimit {
+	/// Soft limit telling apart unlimited
+	pub fn soft_limit(&self) -> Limit {
+		Limit::from_raw(self.soft)
+	}
+
+	/// Hard limit telling apart unlimited
+	pub fn hard_limit(&self) -> Limit {
+		Limit::from_raw(self.hard)
+	}
+}
+
+/// Low level operations needed to raise resource limits.
+///
+/// The real implementation talks to the OS, other implementations allow
+/// exercising the raising logic (including error paths) without it, see
+/// [`raise_fd_limit_with`](crate::raise_fd_limit_with).
+pub trait RlimitOps {
+	/// Fetch the current limits of the resource, failing with
+	/// [`io::ErrorKind::Unsupported`] if it's not available
+	fn getrlimit(&self, resource: Resource) -> io::Result<Rlimit>;
+
+	/// Set new limits of the resource
+	fn setrlimit(&self, resource: Resource, rlim: Rlimit) -> io::Result<()>;
+
+	/// Set new limits of the resource, returning the limits they replaced when
+	/// the platform can fetch them in the same call.
+	///
+	/// The real implementation uses a single `prlimit64` call on Linux, so the
+	/// previous limits are exactly the ones replaced even when they're changed
+	/// concurrently. By default this is [`setrlimit`](Self::setrlimit)
+	/// returning `None`.
+	fn swap_rlimit(&self, resource: Resource, rlim: Rlimit) -> io::Result<Option<Rlimit>> {
+		self.setrlimit(resource, rlim).map(|()| None)
+	}
+
+	/// Fetch the max number of open files per process configured in sysctl
+	/// (`fs.nr_open` on Linux), `None` if the platform has no such setting
+	fn sysctl_maxfiles(&self) -> io::Result<Option<u64>>;
+
+	/// Fetch the max number of open files in the whole system, `None` if the
+	/// platform has no such setting
+	fn system_maxfiles(&self) -> io::Result<Option<u64>>;
+
+	/// Count the file descriptors currently open in the process
+	fn open_fds(&self) -> io::Result<u64>;
+
+	/// Process whose limits are changed, as recorded in the
+	/// [audit log](crate::audit_log), `None` for the current one
+	fn pid(&self) -> Option<u32> {
+		None
+	}
+}
+
+/// Backend calling into the OS, its operations fail with
+/// [`io::ErrorKind::Unsupported`] on unsupported platform or for resources the
+/// platform doesn't have
+pub(crate) struct System;
+
+#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+#[allow(clippy::unnecessary_cast)]
+impl RlimitOps for System {
+	fn getrlimit(&self, resource: Resource) -> io::Result<Rlimit> {
+		sys_getrlimit(raw_resource(resource).ok_or(io::ErrorKind::Unsupported)?)
+	}
+
+	fn setrlimit(&self, resource: Resource, rlim: Rlimit) -> io::Result<()> {
+		sys_setrlimit(raw_resource(resource).ok_or(io::ErrorKind::Unsupported)?, rlim)
+	}
+
+	#[cfg(target_os = "linux")]
+	fn swap_rlimit(&self, resource: Resource, rlim: Rlimit) -> io::Result<Option<Rlimit>> {
+		prlimit(std::process::id(), resource, Some(rlim)).map(Some)
+	}
+
+	fn sysctl_maxfiles(&self) -> io::Result<Option<u64>> {
+		match CEILING.load(Ordering::Acquire) {
+			NOT_FETCHED => refresh_ceiling(),
+			NO_CEILING => Ok(None),
+			ceiling => Ok(Some(ceiling)),
+		}
+	}
+
+	#[cfg(not(target_os = "linux"))]
+	fn system_maxfiles(&self) -> io::Result<Option<u64>> {
+		Ok(Some(from_c_int(sysctl_int(c"kern.maxfiles")?)?))
+	}
+
+	#[cfg(target_os = "linux")]
+	fn system_maxfiles(&self) -> io::Result<Option<u64>> {
+		let file_max = std::fs::read_to_string("/proc/sys/fs/file-max")?;
+		file_max.trim().parse().map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+	}
+
+	#[cfg(not(target_os = "freebsd"))]
+	fn open_fds(&self) -> io::Result<u64> {
+		#[cfg(target_os = "linux")]
+		let dir = "/proc/self/fd";
+		#[cfg(target_vendor = "apple")]
+		let dir = "/dev/fd";
+
+		// Listing the directory takes a descriptor of its own
+		Ok(std::fs::read_dir(dir)?.count().saturating_sub(1) as u64)
+	}
+
+	// /dev/fd only lists the standard streams unless fdescfs is mounted
+	#[cfg(target_os = "freebsd")]
+	fn open_fds(&self) -> io::Result<u64> {
+		Ok(from_c_int(sysctl_int(c"kern.proc.nfds")?)?)
+	}
+}
+
+#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+#[cfg(not(all(feature = "raw-syscalls", target_os = "linux")))]
+fn sys_getrlimit(resource: RawResource) -> io::Result<Rlimit> {
+	let mut rlim = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+	if unsafe { libc::getrlimit(resource, &mut rlim) } != 0 {
+		return Err(io::Error::last_os_error());
+	}
+
+	Ok(Rlimit { soft: from_rlim(rlim.rlim_cur)?, hard: from_rlim(rlim.rlim_max)? })
+}
+
+#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+#[cfg(not(all(feature = "raw-syscalls", target_os = "linux")))]
+fn sys_setrlimit(resource: RawResource, rlim: Rlimit) -> io::Result<()> {
+	let rlim = libc::rlimit { rlim_cur: to_rlim(rlim.soft)?, rlim_max: to_rlim(rlim.hard)? };
+	if unsafe { libc::setrlimit(resource, &rlim) } != 0 {
+		return Err(io::Error::last_os_error());
+	}
+
+	Ok(())
+}
+
+#[cfg(all(feature = "raw-syscalls", target_os = "linux"))]
+#[allow(clippy::unnecessary_cast)]
+fn sys_getrlimit(resource: RawResource) -> io::Result<Rlimit> {
+	crate::syscall::getrlimit(resource as libc::c_int)
+}
+
+#[cfg(all(feature = "raw-syscalls", target_os = "linux"))]
+#[allow(clippy::unnecessary_cast)]
+fn sys_setrlimit(resource: RawResource, rlim: Rlimit) -> io::Result<()> {
+	crate::syscall::setrlimit(resource as libc::c_int, rlim)
+}
+
+/// Set the limits of the resource of another process if given, returning its
+/// previous limits.
+///
+/// Kernels without `prlimit64` only allow it for the current process, through
+/// `getrlimit` and `setrlimit`, it fails with [`io::ErrorKind::Unsupported`]
+/// for the others.
+#[cfg(target_os = "linux")]
+#[allow(clippy::unnecessary_cast)]
+pub(crate) fn prlimit(pid: u32, resource: Resource, new: Option<Rlimit>) -> io::Result<Rlimit> {
+	let is_current = pid == std::process::id();
+	let pid =
+		libc::pid_t::try_from(pid).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+	let resource = raw_resource(resource).ok_or(io::ErrorKind::Unsupported)?;
+	if !has_prlimit() {
+		if !is_current {
+			return Err(io::ErrorKind::Unsupported.into());
+		}
+		let old = sys_getrlimit(resource)?;
+		if let Some(new) = new {
+			sys_setrlimit(resource, new)?;
+		}
+		return Ok(old);
+	}
+
+	#[cfg(feature = "raw-syscalls")]
+	return crate::syscall::prlimit(pid, resource as libc::c_int, new);
+
+	#[cfg(not(feature = "raw-syscalls"))]
+	{
+		let new = match new {
+			Some(rlim) => {
+				Some(libc::rlimit { rlim_cur: to_rlim(rlim.soft)?, rlim_max: to_rlim(rlim.hard)? })
+			}
+			None => None,
+		};
+		let new_ptr = new.as_ref().map_or(std::ptr::null(), |new| new as *const libc::rlimit);
+		let mut old = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+		if unsafe { libc::prlimit(pid, resource, new_ptr, &mut old) } != 0 {
+			return Err(io::Error::last_os_error());
+		}
+
+		Ok(Rlimit { soft: from_rlim(old.rlim_cur)?, hard: from_rlim(old.rlim_max)? })
+	}
+}
+
+/// Whether the kernel has `prlimit64`, probed once and cached in an atomic
+/// rather than through [`crate::platform_info`], which reads much more
+#[cfg(target_os = "linux")]
+fn has_prlimit() -> bool {
+	use crate::platform::{has_syscall, Syscall};
+	use std::sync::atomic::AtomicU8;
+
+	const UNKNOWN: u8 = 0;
+	const PRESENT: u8 = 1;
+	const MISSING: u8 = 2;
+	static PRLIMIT: AtomicU8 = AtomicU8::new(UNKNOWN);
+
+	match PRLIMIT.load(Ordering::Relaxed) {
+		PRESENT => true,
+		MISSING => false,
+		_ => {
+			let present = has_syscall(Syscall::Prlimit);
+			PRLIMIT.store(if present { PRESENT } else { MISSING }, Ordering::Relaxed);
+			present
+		}
+	}
+}
+
+/// Cached per-process kernel ceiling, an atomic so the fork handler can read it
+#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+static CEILING: AtomicU64 = AtomicU64::new(NOT_FETCHED);
+#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+const NOT_FETCHED: u64 = 0;
+#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+const NO_CEILING: u64 = u64::MAX;
+
+/// Fetch the per-process kernel ceiling again and cache it
+#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+pub(crate) fn refresh_ceiling() -> io::Result<Option<u64>> {
+	let ceiling = fetch_ceiling()?;
+	CEILING.store(ceiling.unwrap_or(NO_CEILING), Ordering::Release);
+	Ok(ceiling)
+}
+
+/// Fetch the per-process kernel ceiling again and cache it
+#[cfg(not(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd")))]
+pub(crate) fn refresh_ceiling() -> io::Result<Option<u64>> {
+	Err(io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(any(target_vendor = "apple", target_os = "freebsd"))]
+fn fetch_ceiling() -> io::Result<Option<u64>> {
+	Ok(Some(from_c_int(sysctl_int(c"kern.maxfilesperproc")?)?))
+}
+
+// The hard limit can't exceed fs.nr_open, so it's the soft limit's ceiling as
+// well. It's only informative though, carry on without it if /proc is missing.
+#[cfg(target_os = "linux")]
+fn fetch_ceiling() -> io::Result<Option<u64>> {
+	Ok(std::fs::read_to_string("/proc/sys/fs/nr_open")
+		.ok()
+		.and_then(|nr_open| nr_open.trim().parse().ok())
+		.filter(|&nr_open| nr_open != NOT_FETCHED && nr_open != NO_CEILING))
+}
+
+/// Read an integer sysctl value by name, names are stable unlike numeric MIBs
+#[cfg(any(target_vendor = "apple", target_os = "freebsd"))]
+pub(crate) fn sysctl_int(name: &std::ffi::CStr) -> io::Result<libc::c_int> {
+	let mut value: libc::c_int = 0;
+	let mut size = std::mem::size_of_val(&value);
+	if unsafe {
+		libc::sysctlbyname(
+			name.as_ptr(),
+			&mut value as *mut _ as *mut _,
+			&mut size,
+			std::ptr::null_mut(),
+			0,
+		)
+	} != 0
+	{
+		return Err(io::Error::last_os_error());
+	}
+
+	Ok(value)
+}
+
+/// Platform constant of the resource, `None` if the platform doesn't have it
+#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+pub(crate) fn raw_resource(resource: Resource) -> Option<RawResource> {
+	Some(match resource {
+		Resource::NoFile => libc::RLIMIT_NOFILE,
+		Resource::NProc => libc::RLIMIT_NPROC,
+		Resource::MemLock => libc::RLIMIT_MEMLOCK,
+		Resource::Core => libc::RLIMIT_CORE,
+		Resource::FSize => libc::RLIMIT_FSIZE,
+		Resource::Stack => libc::RLIMIT_STACK,
+		Resource::Cpu => libc::RLIMIT_CPU,
+		Resource::Data => libc::RLIMIT_DATA,
+		Resource::As => libc::RLIMIT_AS,
+		Resource::Rss => libc::RLIMIT_RSS,
+		#[cfg(target_os = "linux")]
+		Resource::MsgQueue => libc::RLIMIT_MSGQUEUE,
+		#[cfg(target_os = "linux")]
+		Resource::Nice => libc::RLIMIT_NICE,
+		#[cfg(target_os = "linux")]
+		Resource::RtPrio => libc::RLIMIT_RTPRIO,
+		#[cfg(target_os = "linux")]
+		Resource::SigPending => libc::RLIMIT_SIGPENDING,
+		#[cfg(target_os = "linux")]
+		Resource::Locks => libc::RLIMIT_LOCKS,
+		#[cfg(target_os = "linux")]
+		Resource::RtTime => libc::RLIMIT_RTTIME,
+		#[cfg(target_os = "freebsd")]
+		Resource::KQueues => libc::RLIMIT_KQUEUES,
+		#[cfg(target_os = "freebsd")]
+		Resource::Npts => libc::RLIMIT_NPTS,
+		#[cfg(target_os = "freebsd")]
+		Resource::SbSize => libc::RLIMIT_SBSIZE,
+		#[allow(unreachable_patterns)]
+		_ => return None,
+	})
+}
+
+/// Whether the platform has the resource
+#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+pub(crate) fn is_available(resource: Resource) -> bool {
+	raw_resource(resource).is_some()
+}
+
+/// Whether the platform has the resource
+#[cfg(not(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd")))]
+pub(crate) fn is_available(_resource: Resource) -> bool {
+	false
+}
+
+#[cfg(not(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd")))]
+impl RlimitOps for System {
+	fn getrlimit(&self, _resource: Resource) -> io::Result<Rlimit> {
+		Err(io::ErrorKind::Unsupported.into())
+	}
+
+	fn setrlimit(&self, _resource: Resource, _rlim: Rlimit) -> io::Result<()> {
+		Err(io::ErrorKind::Unsupported.into())
+	}
+
+	fn sysctl_maxfiles(&self) -> io::Result<Option<u64>> {
+		Err(io::ErrorKind::Unsupported.into())
+	}
+
+	fn system_maxfiles(&self) -> io::Result<Option<u64>> {
+		Err(io::ErrorKind::Unsupported.into())
+	}
+
+	fn open_fds(&self) -> io::Result<u64> {
+		Err(io::ErrorKind::Unsupported.into())
+	}
+}
+
+#[cfg(test)]
+#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+#[cfg(any(feature = "nix", not(all(feature = "raw-syscalls", target_os = "linux"))))]
+mod tests {
+	use super::*;
+
+	#[test]
+	#[cfg(target_os = "linux")]
+	fn probes_prlimit_once() {
+		assert_eq!(has_prlimit(), crate::platform_info().has_prlimit);
+		assert_eq!(has_prlimit(), crate::platform_info().has_prlimit);
+	}
+
+	#[test]
+	fn keeps_unlimited() {
+		assert_eq!(to_rlim(RLIM_INFINITY), Ok(libc::RLIM_INFINITY));
+		assert_eq!(from_rlim(libc::RLIM_INFINITY), Ok(RLIM_INFINITY));
+	}
+
+	#[test]
+	fn round_trips_finite_values() {
+		for value in [0, 1, 1024, 1 << 20, u64::from(u32::MAX) - 1] {
+			assert_eq!(from_rlim(to_rlim(value).unwrap()), Ok(value));
+		}
+	}
+
+	// glibc's rlim_t is 32 bits wide there, musl's and FreeBSD's stay 64 bits
+	#[cfg(target_pointer_width = "32")]
+	mod narrow {
+		use super::*;
+
+		fn narrow_rlim_t() -> bool {
+			libc::rlim_t::BITS == 32
+		}
+
+		#[test]
+		fn unlimited_is_the_32_bit_max() {
+			if narrow_rlim_t() {
+				assert_eq!(RLIM_INFINITY, u64::from(u32::MAX));
+			}
+		}
+
+		#[test]
+		fn overflow_isnt_truncated() {
+			if !narrow_rlim_t() {
+				return;
+			}
+			// Truncating these would give 0, 1 and unlimited
+			for value in [1 << 32, (1 << 32) + 1, u64::MAX] {
+				assert_eq!(to_rlim(value), Err(ConversionError::Overflow(value)));
+			}
+			let error = io::Error::from(to_rlim(u64::MAX).unwrap_err());
+			assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+		}
+
+		#[test]
+		#[allow(clippy::unnecessary_cast)]
+		fn largest_finite_value_fits() {
+			if narrow_rlim_t() {
+				let largest = u64::from(u32::MAX) - 1;
+				assert_eq!(to_rlim(largest), Ok(largest as libc::rlim_t));
+				assert_eq!(from_rlim(largest as libc::rlim_t), Ok(largest));
+			}
+		}
+	}
+}
@@ -0,0 +1,63 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::Error;
+use std::fs::File;
+
+/// Descriptors held open for load tests and for checking monitor thresholds,
+/// closed when dropped
+#[derive(Debug)]
+#[must_use = "the descriptors are closed when dropped"]
+pub struct FdBallast {
+	held: Vec<File>,
+}
+
+impl FdBallast {
+	/// Open and hold `count` descriptors, duplicates of `/dev/null`
+	pub fn allocate(count: u64) -> Result<Self, Error> {
+		let ballast = Self::allocate_up_to(count)?;
+		if (ballast.len() as u64) < count {
+			return Err(Error::FailedToOpenFds(std::io::Error::from_raw_os_error(libc::EMFILE)));
+		}
+		Ok(ballast)
+	}
+
+	/// Open and hold up to `count` descriptors, stopping early when the open
+	/// files limit is reached
+	pub(crate) fn allocate_up_to(count: u64) -> Result<Self, Error> {
+		let mut held = Vec::new();
+		if count > 0 {
+			held.push(File::open("/dev/null").map_err(Error::FailedToOpenFds)?);
+		}
+		while (held.len() as u64) < count {
+			match held[0].try_clone() {
+				Ok(file) => held.push(file),
+				Err(error) if crate::is_fd_exhaustion(&error) => break,
+				Err(error) => return Err(Error::FailedToOpenFds(error)),
+			}
+		}
+
+		Ok(FdBallast { held })
+	}
+
+	/// Number of descriptors held open
+	pub fn len(&self) -> usize {
+		self.held.len()
+	}
+
+	/// Whether no descriptor is held
+	pub fn is_empty(&self) -> bool {
+		self.held.is_empty()
+	}
+}
@@ -0,0 +1,224 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Privileged helper running a command with raised hard limits.
+//!
+//! Meant to be installed setuid root or with `CAP_SYS_RESOURCE`, so it
+//! trusts nothing but its arguments and the kernel. Invoked as
+//! `fdlimit-helper <resource> <soft> <hard> -- <command> [<arg>...]`, it raises
+//! its own limits, only for the resources of [`fdlimit::HELPER_RESOURCES`],
+//! never lowering the soft or hard limit and capping the hard limit at the
+//! kernel ceiling. It then drops back to the user and group that ran it and
+//! executes the command, which inherits the limits.
+//!
+//! No other process is touched, so there's no process ID that could be reused
+//! by another one between the checks and the change. Before running the
+//! command it exits with 2 for invalid arguments, 3 for refused requests and 4
+//! when setting the limits or dropping privileges fails.
+
+use std::process::ExitCode;
+
+/// Arguments aren't `<resource> <soft> <hard> -- <command>`
+#[cfg(target_os = "linux")]
+const USAGE: u8 = 2;
+/// The request isn't allowed
+#[cfg(target_os = "linux")]
+const REFUSED: u8 = 3;
+/// Setting the limits or running the command failed
+const FAILED: u8 = 4;
+
+fn main() -> ExitCode {
+	let Err((code, message)) = run();
+	eprintln!("fdlimit-helper: {message}");
+	ExitCode::from(code)
+}
+
+#[cfg(target_os = "linux")]
+fn run() -> Result<std::convert::Infallible, (u8, String)> {
+	use fdlimit::{HardLimitLowering, Resource};
+	use std::os::unix::process::CommandExt;
+
+	let args: Vec<_> = std::env::args_os().skip(1).collect();
+	let request = parse(&args)?;
+	let ceiling = match request.resource {
+		Resource::NoFile => kernel_setting("/proc/sys/fs/nr_open")?,
+		_ => kernel_setting("/proc/sys/kernel/threads-max")?,
+	};
+	if request.hard > ceiling {
+		let hard = request.hard;
+		return Err((REFUSED, format!("hard limit {hard} is above the kernel ceiling {ceiling}")));
+	}
+
+	let failed = |error: fdlimit::Error| (FAILED, error.to_string());
+	let current = fdlimit::get_limit(request.resource).map_err(failed)?;
+	if request.soft < current.soft || request.hard < current.hard {
+		return Err((
+			REFUSED,
+			format!(
+				"limits {} {} are below the current ones {} {}",
+				request.soft, request.hard, current.soft, current.hard
+			),
+		));
+	}
+	fdlimit::set_hard_limit(request.resource, request.hard, HardLimitLowering::Refuse)
+		.map_err(failed)?;
+	fdlimit::set_soft_limit(request.resource, request.soft).map_err(failed)?;
+
+	drop_privileges().map_err(|error| (FAILED, format!("can't drop privileges: {error}")))?;
+	let (program, args) = request.command;
+	let error = std::process::Command::new(program).args(args).exec();
+	Err((FAILED, format!("can't run {}: {error}", program.to_string_lossy())))
+}
+
+/// Parsed arguments
+#[cfg(target_os = "linux")]
+#[derive(Debug)]
+struct Request<'a> {
+	resource: fdlimit::Resource,
+	soft: u64,
+	hard: u64,
+	command: (&'a std::ffi::OsString, &'a [std::ffi::OsString]),
+}
+
+#[cfg(target_os = "linux")]
+fn parse(args: &[std::ffi::OsString]) -> Result<Request<'_>, (u8, String)> {
+	let usage =
+		|| (USAGE, "usage: fdlimit-helper <resource> <soft> <hard> -- <command>".to_owned());
+	let [resource, soft, hard, separator, program, args @ ..] = args else {
+		return Err(usage());
+	};
+	if separator != "--" {
+		return Err(usage());
+	}
+	let resource = resource_name(resource)
+		.ok_or_else(|| (USAGE, "resource must be nofile or nproc".to_owned()))?;
+	let soft = number(soft)?;
+	let hard = number(hard)?;
+	if soft > hard {
+		return Err((USAGE, format!("soft limit {soft} is above the hard limit {hard}")));
+	}
+	Ok(Request { resource, soft, hard, command: (program, args) })
+}
+
+/// Resource of [`fdlimit::HELPER_RESOURCES`] with exactly this name, not the
+/// case-insensitive forms the library parses
+#[cfg(target_os = "linux")]
+fn resource_name(name: &std::ffi::OsStr) -> Option<fdlimit::Resource> {
+	fdlimit::HELPER_RESOURCES
+		.iter()
+		.copied()
+		.find(|allowed| name.to_str() == Some(allowed.to_string().as_str()))
+}
+
+/// Limit given as decimal digits, without sign, suffix or `unlimited`
+#[cfg(target_os = "linux")]
+fn number(arg: &std::ffi::OsStr) -> Result<u64, (u8, String)> {
+	arg.to_str()
+		.filter(|digits| !digits.is_empty() && digits.bytes().all(|byte| byte.is_ascii_digit()))
+		.and_then(|digits| digits.parse().ok())
+		.ok_or_else(|| (USAGE, format!("invalid limit {}", arg.to_string_lossy())))
+}
+
+#[cfg(target_os = "linux")]
+fn kernel_setting(path: &str) -> Result<u64, (u8, String)> {
+	std::fs::read_to_string(path)
+		.ok()
+		.and_then(|value| value.trim().parse().ok())
+		.ok_or_else(|| (REFUSED, format!("can't read {path}")))
+}
+
+/// Set every user and group ID to the real ones, those of the user who ran
+/// the helper. Capabilities are cleared along when leaving root, and the
+/// command gets none from the helper's file capabilities.
+#[cfg(target_os = "linux")]
+fn drop_privileges() -> std::io::Result<()> {
+	let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+	// The group first, changing it needs the privileges being dropped
+	if unsafe { libc::setresgid(gid, gid, gid) } != 0
+		|| unsafe { libc::setresuid(uid, uid, uid) } != 0
+	{
+		return Err(std::io::Error::last_os_error());
+	}
+	if unsafe { (libc::geteuid(), libc::getegid()) } != (uid, gid) {
+		return Err(std::io::Error::from_raw_os_error(libc::EPERM));
+	}
+	Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn run() -> Result<std::convert::Infallible, (u8, String)> {
+	Err((FAILED, "only runs on Linux".to_owned()))
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+	use super::*;
+	use std::ffi::{OsStr, OsString};
+
+	fn args(args: &[&str]) -> Vec<OsString> {
+		args.iter().map(OsString::from).collect()
+	}
+
+	#[test]
+	fn numbers_are_plain_digits() {
+		assert_eq!(number(OsStr::new("65536")), Ok(65536));
+		for invalid in ["", "-1", "+1", "64k", "unlimited", " 1", "18446744073709551616"] {
+			assert_eq!(number(OsStr::new(invalid)).unwrap_err().0, USAGE, "{invalid}");
+		}
+	}
+
+	#[test]
+	fn resources_are_exact_names() {
+		assert_eq!(resource_name(OsStr::new("nofile")), Some(fdlimit::Resource::NoFile));
+		assert_eq!(resource_name(OsStr::new("nproc")), Some(fdlimit::Resource::NProc));
+		for invalid in ["NOFILE", "RLIMIT_NOFILE", "core", "memlock", ""] {
+			assert_eq!(resource_name(OsStr::new(invalid)), None, "{invalid}");
+		}
+	}
+
+	#[test]
+	fn parses_the_command() {
+		let args = args(&["nofile", "1024", "4096", "--", "server", "--port", "80"]);
+		let request = parse(&args).unwrap();
+		assert_eq!(
+			(request.resource, request.soft, request.hard),
+			(fdlimit::Resource::NoFile, 1024, 4096)
+		);
+		assert_eq!(request.command.0, "server");
+		assert_eq!(request.command.1, &args[5..]);
+	}
+
+	#[test]
+	fn rejects_invalid_requests() {
+		for invalid in [
+			&["nofile", "1024", "4096"][..],
+			&["nofile", "1024", "4096", "--"],
+			&["nofile", "1024", "4096", "server"],
+			&["nofile", "4096", "1024", "--", "server"],
+			&["core", "1024", "4096", "--", "server"],
+		] {
+			assert_eq!(parse(&args(invalid)).unwrap_err().0, USAGE, "{invalid:?}");
+		}
+	}
+
+	#[test]
+	fn drops_nothing_when_unprivileged() {
+		let ids = unsafe { (libc::getuid(), libc::getgid()) };
+		if unsafe { (libc::geteuid(), libc::getegid()) } != ids {
+			return;
+		}
+		drop_privileges().unwrap();
+		assert_eq!(unsafe { (libc::geteuid(), libc::getegid()) }, ids);
+	}
+}
@@ -0,0 +1,40 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use fdlimit::FdBallast;
+use std::thread;
+use std::time::Duration;
+
+#[derive(clap::Args)]
+pub struct Args {
+	/// Number of descriptors to hold open
+	count: u64,
+	/// Release the descriptors after this many seconds, instead of holding
+	/// them until killed
+	#[arg(long)]
+	duration_secs: Option<u64>,
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+	let ballast = FdBallast::allocate(args.count)?;
+	println!("holding {} descriptors in process {}", ballast.len(), std::process::id());
+	match args.duration_secs {
+		Some(secs) => thread::sleep(Duration::from_secs(secs)),
+		None => loop {
+			thread::park();
+		},
+	}
+
+	Ok(())
+}
@@ -0,0 +1,137 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::doctor::CEILINGS;
+use crate::exit::Hinted;
+use crate::raise::parse_limit;
+use fdlimit::{sysctl_command, Requirement, Setting};
+use std::io;
+use std::path::PathBuf;
+use std::process::Command;
+
+#[derive(clap::Args)]
+pub struct Args {
+	/// Raise the kernel settings below this value to it
+	#[arg(long, value_parser = parse_limit)]
+	at_least: u64,
+	/// Set them through sudo, or pkexec, showing the command first, when not
+	/// allowed to change them
+	#[arg(long)]
+	escalate: bool,
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+	let Args { at_least: value, escalate } = args;
+	let requirements: Vec<_> = CEILINGS
+		.iter()
+		.map(|&name| Requirement {
+			setting: Setting::Sysctl(name),
+			minimum: value,
+			recommended: value,
+		})
+		.collect();
+	let mut changed = Vec::new();
+	for finding in fdlimit::preflight(&requirements) {
+		let Setting::Sysctl(name) = finding.setting else { continue };
+		let Some(observed) = finding.observed else {
+			return Err(format!("can't read {name}").into());
+		};
+		if observed >= value {
+			println!("{name}: {observed}");
+			continue;
+		}
+		match write_sysctl(name, value) {
+			Ok(()) => {}
+			Err(error) if error.kind() == io::ErrorKind::PermissionDenied && escalate => {
+				run_escalated(name, value)?
+			}
+			Err(error) if error.kind() == io::ErrorKind::PermissionDenied => {
+				let hint = format!(
+					"run again with --escalate to set it through sudo, or run `sudo {}`",
+					sysctl_command(name, value).join(" ")
+				);
+				return Err(Box::new(Hinted { error: error.into(), hint }));
+			}
+			Err(error) => return Err(error.into()),
+		}
+		println!("{name}: {observed} -> {value}");
+		changed.push(name);
+	}
+	if !changed.is_empty() {
+		let lines: Vec<_> = changed.iter().map(|name| format!("`{name} = {value}`")).collect();
+		println!("not kept across reboots, add {} to {SYSCTL_CONF}", lines.join(" and "));
+	}
+	Ok(())
+}
+
+/// File of the settings applied at boot
+#[cfg(target_os = "linux")]
+const SYSCTL_CONF: &str = "/etc/sysctl.d/90-fdlimit.conf";
+#[cfg(not(target_os = "linux"))]
+const SYSCTL_CONF: &str = "/etc/sysctl.conf";
+
+#[cfg(target_os = "linux")]
+fn write_sysctl(name: &str, value: u64) -> io::Result<()> {
+	std::fs::write(format!("/proc/sys/{}", name.replace('.', "/")), value.to_string())
+}
+
+#[cfg(any(target_vendor = "apple", target_os = "freebsd"))]
+fn write_sysctl(name: &str, value: u64) -> io::Result<()> {
+	let name = std::ffi::CString::new(name)?;
+	let value = libc::c_int::try_from(value).map_err(|_| io::ErrorKind::InvalidInput)?;
+	let ret = unsafe {
+		libc::sysctlbyname(
+			name.as_ptr(),
+			std::ptr::null_mut(),
+			std::ptr::null_mut(),
+			&value as *const libc::c_int as *mut libc::c_void,
+			std::mem::size_of::<libc::c_int>(),
+		)
+	};
+	match ret {
+		0 => Ok(()),
+		_ => Err(io::Error::last_os_error()),
+	}
+}
+
+/// Set the value with sudo, or with polkit's pkexec when there's no sudo, which
+/// ask for the password themselves
+fn run_escalated(name: &str, value: u64) -> Result<(), Box<dyn std::error::Error>> {
+	let program = ["sudo", "pkexec"].into_iter().find(|program| find_in_path(program).is_some());
+	let Some(program) = program else {
+		return Err(
+			format!("setting {name} needs root, and neither sudo nor pkexec was found").into()
+		);
+	};
+	let command = sysctl_command(name, value);
+	eprintln!(
+		"fdlimit: setting {name} to {value} needs root, running `{program} {}`",
+		command.join(" ")
+	);
+	let status =
+		Command::new(program).args(&command).stdout(std::process::Stdio::null()).status()?;
+	if !status.success() {
+		let error = io::Error::new(
+			io::ErrorKind::PermissionDenied,
+			format!("`{program} {}` failed with {status}", command.join(" ")),
+		);
+		return Err(error.into());
+	}
+	Ok(())
+}
+
+fn find_in_path(program: &str) -> Option<PathBuf> {
+	let path = std::env::var_os("PATH")?;
+	std::env::split_paths(&path).map(|dir| dir.join(program)).find(|path| path.is_file())
+}
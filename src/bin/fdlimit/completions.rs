@@ -0,0 +1,31 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use clap::CommandFactory;
+use clap_complete::Shell;
+use std::io::Write;
+
+#[derive(clap::Args)]
+pub struct Args {
+	/// Shell to generate completions for
+	shell: Shell,
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+	// Generated in memory first, writing straight to a closed pipe panics
+	let mut script = Vec::new();
+	clap_complete::generate(args.shell, &mut crate::Cli::command(), "fdlimit", &mut script);
+	std::io::stdout().write_all(&script)?;
+	Ok(())
+}
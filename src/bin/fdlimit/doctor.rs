@@ -0,0 +1,55 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::raise::parse_limit;
+use fdlimit::{Requirement, Resource, Setting, Severity, Summary};
+
+#[derive(clap::Args)]
+pub struct Args {
+	/// Open files below this fail the check
+	#[arg(long, default_value = "1024", value_parser = parse_limit)]
+	min: u64,
+	/// Open files below this are a warning
+	#[arg(long, default_value = "64k", value_parser = parse_limit)]
+	recommended: u64,
+}
+
+/// Kernel settings capping the open files of a process and of the system
+#[cfg(target_os = "linux")]
+pub const CEILINGS: &[&str] = &["fs.nr_open", "fs.file-max"];
+#[cfg(any(target_vendor = "apple", target_os = "freebsd"))]
+pub const CEILINGS: &[&str] = &["kern.maxfilesperproc", "kern.maxfiles"];
+#[cfg(not(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd")))]
+pub const CEILINGS: &[&str] = &[];
+
+pub fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+	print!("{}", fdlimit::diagnose());
+	println!();
+
+	let Args { min: minimum, recommended } = args;
+	let settings = [Setting::Limit(Resource::NoFile), Setting::HardLimit(Resource::NoFile)]
+		.into_iter()
+		.chain(CEILINGS.iter().map(|&name| Setting::Sysctl(name)));
+	let requirements: Vec<_> =
+		settings.map(|setting| Requirement { setting, minimum, recommended }).collect();
+	let summary = Summary { findings: fdlimit::preflight(&requirements) };
+	print!("{summary}");
+
+	let failed = summary.findings.iter().filter(|finding| finding.severity == Severity::Error);
+	match failed.count() {
+		0 => Ok(()),
+		1 => Err("1 check failed".into()),
+		failed => Err(format!("{failed} checks failed").into()),
+	}
+}
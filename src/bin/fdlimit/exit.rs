@@ -0,0 +1,135 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Exit codes and error reports, kept stable for scripts and init systems.
+
+use fdlimit::json_string;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::process::ExitCode;
+
+/// Exit code of the tool, documented in the README. Invalid arguments exit
+/// with 2, as clap does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Code {
+	/// Everything went fine
+	Success = 0,
+	/// Any failure not covered below
+	Failure = 1,
+	/// The limit stayed below the requested value
+	Partial = 3,
+	/// Not allowed to change the limit
+	PermissionDenied = 4,
+	/// The platform doesn't have the limit or can't change it
+	Unsupported = 5,
+}
+
+impl Code {
+	/// Classify the error returned by a subcommand
+	pub fn of(error: &(dyn Error + 'static)) -> Self {
+		let error = match error.downcast_ref::<Hinted>() {
+			Some(hinted) => &*hinted.error,
+			None => error,
+		};
+		if let Some(error) = error.downcast_ref::<fdlimit::Error>() {
+			return match error {
+				fdlimit::Error::ResourceNotAvailable(_) => Code::Unsupported,
+				fdlimit::Error::LimitUnavailable { .. } | fdlimit::Error::BelowMinimum { .. } => {
+					Code::Partial
+				}
+				_ => error.io_error().map_or(Code::Failure, Code::of_io),
+			};
+		}
+		error.downcast_ref::<io::Error>().map_or(Code::Failure, Code::of_io)
+	}
+
+	fn of_io(error: &io::Error) -> Self {
+		match error.kind() {
+			io::ErrorKind::PermissionDenied => Code::PermissionDenied,
+			io::ErrorKind::Unsupported => Code::Unsupported,
+			_ => Code::Failure,
+		}
+	}
+
+	/// Name used in JSON reports
+	fn name(self) -> &'static str {
+		match self {
+			Code::Success => "success",
+			Code::Failure => "failure",
+			Code::Partial => "partial",
+			Code::PermissionDenied => "permission-denied",
+			Code::Unsupported => "unsupported",
+		}
+	}
+}
+
+impl From<Code> for ExitCode {
+	fn from(code: Code) -> Self {
+		ExitCode::from(code as u8)
+	}
+}
+
+/// Error carrying a remediation hint, printed after it
+#[derive(Debug)]
+pub struct Hinted {
+	pub error: Box<dyn Error>,
+	pub hint: String,
+}
+
+impl fmt::Display for Hinted {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		self.error.fmt(f)
+	}
+}
+
+impl Error for Hinted {}
+
+/// Print the error to stderr, as text or as a JSON object on one line, and
+/// return the matching exit code
+pub fn report(error: &(dyn Error + 'static), json: bool) -> Code {
+	let code = Code::of(error);
+	let hinted = error.downcast_ref::<Hinted>();
+	let hint = hinted.map(|hinted| hinted.hint.as_str());
+	if !json {
+		eprintln!("fdlimit: {error}");
+		if let Some(hint) = hint {
+			eprintln!("hint: {hint}");
+		}
+		return code;
+	}
+
+	let inner = hinted.map_or(error, |hinted| &*hinted.error);
+	let library = inner.downcast_ref::<fdlimit::Error>();
+	let mut fields = vec![
+		format!("\"code\":{}", code as u8),
+		format!("\"kind\":\"{}\"", code.name()),
+		format!("\"message\":{}", json_string(&error.to_string())),
+	];
+	if let Some(method) = library.map(fdlimit::Error::method) {
+		fields.push(format!("\"method\":{}", json_string(&method.to_string())));
+	}
+	let io_error = library.and_then(fdlimit::Error::io_error).or(inner.downcast_ref::<io::Error>());
+	if let Some(errno) = io_error.and_then(io::Error::raw_os_error) {
+		fields.push(format!("\"os_error\":{errno}"));
+		if let Some(name) = fdlimit::errno_name(errno) {
+			fields.push(format!("\"errno\":{}", json_string(name)));
+		}
+	}
+	if let Some(hint) = hint {
+		fields.push(format!("\"hint\":{}", json_string(hint)));
+	}
+	eprintln!("{{\"error\":{{{}}}}}", fields.join(","));
+	code
+}
@@ -0,0 +1,88 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use fdlimit::{FdAges, OpenFd};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(clap::Args)]
+pub struct Args {
+	/// Milliseconds between two samples of the open descriptors
+	#[arg(long, default_value_t = 500)]
+	interval_ms: u64,
+	/// Stop the command after this many seconds, for daemons that don't exit
+	#[arg(long)]
+	duration_secs: Option<u64>,
+	/// Number of new descriptors tolerated between the first and last sample
+	#[arg(long, default_value_t = 0)]
+	threshold: u64,
+	/// Command to check
+	#[arg(last = true, required = true)]
+	command: Vec<String>,
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+	let (program, program_args) = args.command.split_first().ok_or("no command given")?;
+	let mut child = Command::new(program).args(program_args).spawn()?;
+	let interval = Duration::from_millis(args.interval_ms);
+	let deadline = args.duration_secs.map(|secs| Instant::now() + Duration::from_secs(secs));
+
+	// Snapshots can fail while the command is exiting, those are just skipped
+	let mut first: Option<Vec<OpenFd>> = None;
+	let mut last = Vec::new();
+	let mut peak = 0;
+	let mut ages = FdAges::new();
+	let mut stopped = false;
+	let status = loop {
+		if let Some(status) = child.try_wait()? {
+			break status;
+		}
+		if let Ok(fds) = fdlimit::open_fds_for_pid(child.id()) {
+			peak = peak.max(fds.len());
+			ages.update(&fds);
+			first.get_or_insert_with(|| fds.clone());
+			last = fds;
+		}
+		if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+			child.kill()?;
+			stopped = true;
+			break child.wait()?;
+		}
+		thread::sleep(interval);
+	};
+
+	let first = first.unwrap_or_default();
+	println!("open descriptors: first {}, peak {peak}, last {}", first.len(), last.len());
+	if !stopped && !status.success() {
+		return Err(format!("command failed with {status}").into());
+	}
+
+	if last.len() <= first.len() + args.threshold as usize {
+		return Ok(());
+	}
+
+	// Long-lived ones are the likely leaks, the others may still be closed
+	let mut leaked: Vec<_> = last
+		.iter()
+		.filter(|fd| !first.contains(fd))
+		.map(|fd| (fd, ages.age(fd).unwrap_or_default()))
+		.collect();
+	leaked.sort_by_key(|(_, age)| std::cmp::Reverse(*age));
+	println!("probable leak, descriptors opened since the first sample, oldest first:");
+	for (fd, age) in leaked {
+		println!("{:>6} {:>8.1}s {}", fd.fd, age.as_secs_f64(), fd.target.display());
+	}
+	Err(format!("{} descriptors leaked", last.len() - first.len()).into())
+}
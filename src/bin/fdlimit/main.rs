@@ -0,0 +1,104 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Command line tool for inspecting and raising file descriptor limits.
+
+#[cfg(unix)]
+mod ballast;
+#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+mod ceiling;
+mod completions;
+mod doctor;
+mod exit;
+#[cfg(target_os = "linux")]
+mod leak_check;
+#[cfg(target_os = "linux")]
+mod ps;
+mod raise;
+mod show;
+#[cfg(target_os = "linux")]
+mod top;
+
+use clap::{Parser, Subcommand};
+use std::process::ExitCode;
+
+#[derive(Parser)]
+#[command(name = "fdlimit", version, about)]
+struct Cli {
+	/// Format of the error printed on failure
+	#[arg(long, global = true, value_enum, default_value_t = Format::Text)]
+	format: Format,
+	#[command(subcommand)]
+	command: Command,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Format {
+	Text,
+	/// One JSON object on stderr
+	Json,
+}
+
+#[derive(Subcommand)]
+enum Command {
+	/// Hold descriptors open, for load tests and checking monitor thresholds
+	#[cfg(unix)]
+	Ballast(ballast::Args),
+	/// Raise the kernel ceilings of open files, e.g. fs.nr_open, optionally
+	/// through sudo
+	#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+	Ceiling(ceiling::Args),
+	/// Print shell completions, e.g. `fdlimit completions bash`
+	Completions(completions::Args),
+	/// Check the limits and kernel settings, printing how to fix the failures
+	Doctor(doctor::Args),
+	/// Run a command and report file descriptors it leaks
+	#[cfg(target_os = "linux")]
+	LeakCheck(leak_check::Args),
+	/// Open files limits and usage of every visible process
+	#[cfg(target_os = "linux")]
+	Ps(ps::Args),
+	/// Raise the soft limit, optionally running a command with it
+	Raise(raise::Args),
+	/// Show the soft and hard limits of every resource
+	Show(show::Args),
+	/// Live view of the processes closest to their open files limit
+	#[cfg(target_os = "linux")]
+	Top(top::Args),
+}
+
+fn main() -> ExitCode {
+	let cli = Cli::parse();
+	let result = match cli.command {
+		#[cfg(unix)]
+		Command::Ballast(args) => ballast::run(args),
+		#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+		Command::Ceiling(args) => ceiling::run(args),
+		Command::Completions(args) => completions::run(args),
+		Command::Doctor(args) => doctor::run(args),
+		#[cfg(target_os = "linux")]
+		Command::LeakCheck(args) => leak_check::run(args),
+		#[cfg(target_os = "linux")]
+		Command::Ps(args) => ps::run(args),
+		Command::Raise(args) => raise::run(args),
+		Command::Show(args) => show::run(args),
+		#[cfg(target_os = "linux")]
+		Command::Top(args) => top::run(args),
+	};
+
+	match result {
+		Ok(()) => exit::Code::Success.into(),
+		Err(e) => exit::report(&*e, cli.format == Format::Json).into(),
+	}
+}
@@ -0,0 +1,27 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[derive(clap::Args)]
+pub struct Args {}
+
+pub fn run(_args: Args) -> Result<(), Box<dyn std::error::Error>> {
+	println!("{:>8} {:>8} {:>20} {:>20}", "PID", "OPEN", "SOFT", "HARD");
+	for process in fdlimit::all_process_limits()? {
+		let open = process.open_fds.map_or_else(|| "-".to_owned(), |open| open.to_string());
+		let (soft, hard) = (process.limit.soft_limit(), process.limit.hard_limit());
+		println!("{:>8} {open:>8} {soft:>20} {hard:>20}", process.pid);
+	}
+
+	Ok(())
+}
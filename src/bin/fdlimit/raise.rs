@@ -0,0 +1,164 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::exit::Hinted;
+use fdlimit::{Builder, Limit, Outcome, Resource, Strategy};
+use std::io;
+use std::process::Command;
+
+#[derive(clap::Args)]
+#[command(group(clap::ArgGroup::new("strategy").multiple(false)))]
+pub struct Args {
+	/// Set the soft limit to exactly this value, e.g. 4096, 64k or unlimited
+	#[arg(long, group = "strategy", value_parser = parse_limit)]
+	exact: Option<u64>,
+	/// Raise to the maximum available, failing if it's below this value
+	#[arg(long, group = "strategy", value_parser = parse_limit)]
+	at_least: Option<u64>,
+	/// Raise to this fraction of the hard limit, e.g. 0.8 or 80%
+	#[arg(long, group = "strategy", value_parser = parse_fraction)]
+	fraction: Option<f64>,
+	/// Resource to raise, e.g. nofile or nproc
+	#[arg(long, default_value = "nofile")]
+	resource: Resource,
+	/// Change the limits of this running process instead
+	#[arg(long, conflicts_with_all = ["strategy", "command"])]
+	pid: Option<u32>,
+	/// New soft limit of the process, its hard limit by default
+	#[arg(long, requires = "pid", value_parser = parse_limit)]
+	soft: Option<u64>,
+	/// New hard limit of the process, unchanged by default
+	#[arg(long, requires = "pid", value_parser = parse_limit)]
+	hard: Option<u64>,
+	/// Allow --hard to lower the hard limit, which the process can't raise
+	/// back without privileges
+	#[arg(long, requires = "hard")]
+	lower_hard: bool,
+	/// Command to run with the new limit
+	#[arg(last = true)]
+	command: Vec<String>,
+}
+
+impl Args {
+	fn strategy(&self) -> Strategy {
+		match (self.exact, self.at_least, self.fraction) {
+			(Some(value), _, _) => Strategy::Exact(value),
+			(_, Some(value), _) => Strategy::AtLeast(value),
+			(_, _, Some(fraction)) => Strategy::FractionOfHard(fraction),
+			_ => Strategy::MaxAvailable,
+		}
+	}
+}
+
+/// Raw value of a limit such as 4096, 64k or unlimited
+pub fn parse_limit(s: &str) -> Result<u64, fdlimit::Error> {
+	s.parse().map(Limit::to_raw)
+}
+
+fn parse_fraction(s: &str) -> Result<f64, String> {
+	let fraction = match s.strip_suffix('%') {
+		Some(percent) => percent.trim().parse::<f64>().map(|percent| percent / 100.0),
+		None => s.parse::<f64>(),
+	}
+	.map_err(|e| e.to_string())?;
+
+	if fraction > 0.0 && fraction <= 1.0 {
+		Ok(fraction)
+	} else {
+		Err(format!("{s} is not above 0 and at most 1 (100%)"))
+	}
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+	#[cfg(target_os = "linux")]
+	if let Some(pid) = args.pid {
+		return raise_pid(pid, &args);
+	}
+	#[cfg(not(target_os = "linux"))]
+	if args.pid.is_some() {
+		return Err("changing the limits of another process is only supported on Linux".into());
+	}
+
+	let outcome = Builder::new()
+		.resource(args.resource)
+		.strategy(args.strategy())
+		.raise()
+		.map_err(|error| with_hint(args.resource, error))?;
+
+	let Some((program, program_args)) = args.command.split_first() else {
+		match outcome {
+			Outcome::LimitRaised { from, to } => println!("soft limit {from} -> {to}"),
+			Outcome::Unsupported => {
+				return Err(fdlimit::Error::ResourceNotAvailable(args.resource).into())
+			}
+		}
+		return Ok(());
+	};
+
+	let mut command = Command::new(program);
+	command.args(program_args);
+	Err(exec(command).into())
+}
+
+#[cfg(target_os = "linux")]
+fn raise_pid(pid: u32, args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+	let current = fdlimit::limits_for_pid(pid)?
+		.get(args.resource)
+		.ok_or_else(|| format!("no {} limit listed for process {pid}", args.resource))?;
+	let hard = args.hard.unwrap_or(current.hard);
+	let new = fdlimit::backend::Rlimit { soft: args.soft.unwrap_or(hard), hard };
+
+	let lowering = match args.lower_hard {
+		true => fdlimit::HardLimitLowering::Allow,
+		false => fdlimit::HardLimitLowering::Refuse,
+	};
+	let old = fdlimit::set_limits_for_pid(pid, args.resource, new, lowering)
+		.map_err(|error| with_hint(args.resource, error))?;
+	println!("soft limit {} -> {}", old.soft_limit(), new.soft_limit());
+	if old.hard != new.hard {
+		println!("hard limit {} -> {}", old.hard_limit(), new.hard_limit());
+	}
+	Ok(())
+}
+
+/// Explain how to get the limit raised above what the process may set itself
+fn with_hint(resource: Resource, error: fdlimit::Error) -> Box<dyn std::error::Error> {
+	let wanted = match &error {
+		fdlimit::Error::LimitUnavailable { requested, .. }
+		| fdlimit::Error::HardLimitCeilingInUserNamespace { requested, .. } => *requested,
+		fdlimit::Error::FailedToSetLimit { to, error, .. }
+			if error.kind() == io::ErrorKind::PermissionDenied =>
+		{
+			*to
+		}
+		_ => return error.into(),
+	};
+	let hint = fdlimit::remediation(resource, Limit::from_raw(wanted)).to_string();
+	Box::new(Hinted { error: error.into(), hint })
+}
+
+#[cfg(unix)]
+fn exec(mut command: Command) -> io::Error {
+	use std::os::unix::process::CommandExt;
+
+	command.exec()
+}
+
+#[cfg(not(unix))]
+fn exec(mut command: Command) -> io::Error {
+	match command.status() {
+		Ok(status) => std::process::exit(status.code().unwrap_or(1)),
+		Err(e) => e,
+	}
+}
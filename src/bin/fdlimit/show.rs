@@ -0,0 +1,33 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use fdlimit::LimitEntry;
+
+#[derive(clap::Args)]
+pub struct Args {}
+
+pub fn run(_args: Args) -> Result<(), Box<dyn std::error::Error>> {
+	println!("{:<10} {:>20} {:>20}", "RESOURCE", "SOFT", "HARD");
+	for LimitEntry { resource, soft, hard } in fdlimit::supported_limits()? {
+		println!("{resource:<10} {soft:>20} {hard:>20}");
+	}
+	if let Some(max_stdio) = fdlimit::max_stdio() {
+		println!("\nC runtime max open streams: {max_stdio}");
+	}
+	if let Some(max_files) = fdlimit::system_max_files()? {
+		println!("\nsystem-wide max open files: {max_files}");
+	}
+
+	Ok(())
+}
@@ -0,0 +1,146 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use fdlimit::ProcessUsage;
+use std::cmp::Reverse;
+use std::io::{self, Write};
+
+#[derive(clap::Args)]
+pub struct Args {
+	/// Milliseconds between two refreshes
+	#[arg(long, default_value_t = 1000)]
+	interval_ms: u64,
+	/// Number of processes shown
+	#[arg(long, default_value_t = 20)]
+	lines: usize,
+	/// Initial sort order, switch with r, f and p while running
+	#[arg(long, value_enum, default_value_t = Sort::Ratio)]
+	sort: Sort,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum Sort {
+	/// Share of the soft limit in use
+	Ratio,
+	/// Number of open descriptors
+	Fds,
+	/// Process ID
+	Pid,
+}
+
+impl Sort {
+	fn apply(self, usages: &mut [ProcessUsage]) {
+		match self {
+			Sort::Ratio => usages.sort_by(|a, b| b.ratio().total_cmp(&a.ratio())),
+			Sort::Fds => usages.sort_by_key(|usage| Reverse(usage.open_fds)),
+			Sort::Pid => usages.sort_by_key(|usage| usage.pid),
+		}
+	}
+}
+
+pub fn run(mut args: Args) -> Result<(), Box<dyn std::error::Error>> {
+	let _terminal = RawTerminal::enter();
+	loop {
+		let mut usages = fdlimit::scan_processes(usize::MAX)?;
+		args.sort.apply(&mut usages);
+		render(&usages[..usages.len().min(args.lines)])?;
+
+		match read_key(args.interval_ms)? {
+			// ^C reads as a key, the terminal doesn't send SIGINT
+			Some(b'q' | 0x03) => return Ok(()),
+			Some(b'r') => args.sort = Sort::Ratio,
+			Some(b'f') => args.sort = Sort::Fds,
+			Some(b'p') => args.sort = Sort::Pid,
+			_ => {}
+		}
+	}
+}
+
+fn render(usages: &[ProcessUsage]) -> io::Result<()> {
+	let mut out = io::stdout().lock();
+	// Move to the top left corner and clear the screen
+	write!(out, "\x1b[H\x1b[2J")?;
+	writeln!(out, "sort: r ratio, f descriptors, p pid; q or ^C to quit\r\n\r")?;
+	writeln!(
+		out,
+		"{:>8} {:>8} {:>12} {:>12} {:>6}  COMMAND\r",
+		"PID", "FDS", "SOFT", "HARD", "USE%"
+	)?;
+	for usage in usages {
+		let command =
+			std::fs::read_to_string(format!("/proc/{}/comm", usage.pid)).unwrap_or_default();
+		writeln!(
+			out,
+			"{:>8} {:>8} {:>12} {:>12} {:>5.1}%  {}\r",
+			usage.pid,
+			usage.open_fds,
+			usage.limit.soft_limit(),
+			usage.limit.hard_limit(),
+			usage.ratio() * 100.0,
+			command.trim_end(),
+		)?;
+	}
+	out.flush()
+}
+
+/// Wait up to the given time for a key press
+fn read_key(timeout_ms: u64) -> io::Result<Option<u8>> {
+	let mut pollfd = libc::pollfd { fd: libc::STDIN_FILENO, events: libc::POLLIN, revents: 0 };
+	let timeout = libc::c_int::try_from(timeout_ms).unwrap_or(libc::c_int::MAX);
+	match unsafe { libc::poll(&mut pollfd, 1, timeout) } {
+		-1 => Err(io::Error::last_os_error()),
+		0 => Ok(None),
+		_ => {
+			let mut key = 0u8;
+			match unsafe { libc::read(libc::STDIN_FILENO, (&mut key as *mut u8).cast(), 1) } {
+				1 => Ok(Some(key)),
+				// stdin was closed, keep refreshing until interrupted
+				0 => {
+					std::thread::sleep(std::time::Duration::from_millis(timeout_ms));
+					Ok(None)
+				}
+				_ => Err(io::Error::last_os_error()),
+			}
+		}
+	}
+}
+
+/// Terminal switched to reading single key presses without echo nor signals,
+/// so ^C quits through the loop and the terminal is always restored on drop
+struct RawTerminal {
+	original: Option<libc::termios>,
+}
+
+impl RawTerminal {
+	fn enter() -> Self {
+		let mut termios = unsafe { std::mem::zeroed::<libc::termios>() };
+		if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut termios) } != 0 {
+			// Not a terminal
+			return RawTerminal { original: None };
+		}
+
+		let original = termios;
+		termios.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+		unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios) };
+		RawTerminal { original: Some(original) }
+	}
+}
+
+impl Drop for RawTerminal {
+	fn drop(&mut self) {
+		if let Some(original) = &self.original {
+			unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, original) };
+		}
+	}
+}
@@ -0,0 +1,128 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::backend::RlimitOps;
+use crate::{AutoRaise, Error, Outcome, RateLimit, Resource, Strategy};
+
+/// Configures how a resource limit is raised.
+///
+/// `Builder::new().raise()` is equivalent to [`raise_fd_limit`](crate::raise_fd_limit).
+#[derive(Debug, Clone)]
+pub struct Builder {
+	resource: Resource,
+	strategy: Strategy,
+	rate_limit: Option<RateLimit>,
+	reason: Option<String>,
+}
+
+impl Default for Builder {
+	fn default() -> Self {
+		Builder {
+			resource: Resource::NoFile,
+			strategy: Strategy::default(),
+			rate_limit: None,
+			reason: None,
+		}
+	}
+}
+
+impl Builder {
+	/// Create a builder with the default settings
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Set the resource to raise, open files by default
+	pub fn resource(mut self, resource: Resource) -> Self {
+		self.resource = resource;
+		self
+	}
+
+	/// Set the strategy picking the new soft limit
+	pub fn strategy(mut self, strategy: Strategy) -> Self {
+		self.strategy = strategy;
+		self
+	}
+
+	/// Limit how often [`auto_raise`](Self::auto_raise) raises the limit again,
+	/// unlimited by default
+	pub fn rate_limit(mut self, rate_limit: RateLimit) -> Self {
+		self.rate_limit = Some(rate_limit);
+		self
+	}
+
+	/// Record the reason with the change in the [audit log](crate::audit_log)
+	pub fn reason(mut self, reason: impl Into<String>) -> Self {
+		self.reason = Some(reason.into());
+		self
+	}
+
+	/// Raise the limit of the current process
+	#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+	pub fn raise(&self) -> Result<Outcome, Error> {
+		self.raise_with(&crate::backend::System)
+	}
+
+	/// Does nothing on unsupported platform
+	#[cfg(not(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd")))]
+	pub fn raise(&self) -> Result<Outcome, Error> {
+		Ok(Outcome::Unsupported)
+	}
+
+	/// Re-apply this builder's strategy to the open files limit in child
+	/// processes after `fork`.
+	///
+	/// Limits are inherited across `fork` already, this makes child processes
+	/// explicitly follow the policy. Only the last registered strategy is applied.
+	#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+	pub fn reapply_after_fork(&self) -> Result<(), Error> {
+		crate::atfork::reapply_after_fork(&self.strategy)
+	}
+
+	/// Wrapper raising the open files limit with this builder's strategy and
+	/// rate limit when operations run out of descriptors, whatever the
+	/// builder's resource
+	pub fn auto_raise(&self) -> AutoRaise {
+		AutoRaise::with_config(self.strategy, self.rate_limit)
+	}
+
+	/// Same as [`raise`](Self::raise) reporting failures as an allocation-free
+	/// [`RawError`](crate::RawError), without firing the
+	/// [`on_raise`](crate::on_raise) callbacks or recording the change in the
+	/// [audit log](crate::audit_log).
+	#[cfg(feature = "raw-error")]
+	#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+	pub fn raise_raw(&self) -> Result<Outcome, crate::RawError> {
+		crate::raw_error::raise(&crate::backend::System, self.resource, &self.strategy)
+	}
+
+	/// Does nothing on unsupported platform
+	#[cfg(feature = "raw-error")]
+	#[cfg(not(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd")))]
+	pub fn raise_raw(&self) -> Result<Outcome, crate::RawError> {
+		Ok(Outcome::Unsupported)
+	}
+
+	/// Raise the limit using the given backend
+	pub fn raise_with<B: RlimitOps>(&self, backend: &B) -> Result<Outcome, Error> {
+		let raise = || {
+			crate::raise_and_notify(backend, self.resource, &self.strategy)
+				.map(|raised| raised.audit(backend))
+		};
+		match &self.reason {
+			Some(reason) => crate::with_audit_reason(reason.as_str(), raise),
+			None => raise(),
+		}
+	}
+}
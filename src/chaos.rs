@@ -0,0 +1,93 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Simulated descriptor exhaustion for resilience tests, not meant for
+//! production builds.
+
+use crate::backend::{Rlimit, RlimitOps, System};
+use crate::{Error, FdBallast, Resource};
+
+/// Descriptor exhaustion, undone when dropped or [restored](Self::restore)
+#[derive(Debug)]
+#[must_use = "the exhaustion is undone when dropped"]
+pub struct Exhaustion {
+	previous: Option<Rlimit>,
+	held: Option<FdBallast>,
+}
+
+impl Exhaustion {
+	/// Lower the soft open files limit so that about `remaining` more
+	/// descriptors can be opened.
+	///
+	/// The limit caps descriptor numbers rather than their count, when lower
+	/// numbers were closed a few more descriptors fit.
+	pub fn lower_limit(remaining: u64) -> Result<Self, Error> {
+		let rlim = crate::get_limit(Resource::NoFile)?;
+		let open = System.open_fds().map_err(Error::FailedToCountFds)?;
+		let soft = open.saturating_add(remaining).min(rlim.soft);
+		let lowered = Rlimit { soft, hard: rlim.hard };
+		System.setrlimit(Resource::NoFile, lowered).map_err(|error| Error::FailedToSetLimit {
+			from: rlim.soft,
+			to: soft,
+			error,
+		})?;
+		crate::audit::record(None, Resource::NoFile, rlim, lowered);
+
+		Ok(Exhaustion { previous: Some(rlim), held: None })
+	}
+
+	/// Open descriptors until only `remaining` can still be opened under the
+	/// soft limit, combine with [`lower_limit`](Self::lower_limit) when the
+	/// limit is high
+	pub fn consume(remaining: u64) -> Result<Self, Error> {
+		let soft = crate::get_limit(Resource::NoFile)?.soft;
+		let open = System.open_fds().map_err(Error::FailedToCountFds)?;
+		let count = soft.saturating_sub(open).saturating_sub(remaining);
+		let held = FdBallast::allocate_up_to(count)?;
+
+		Ok(Exhaustion { previous: None, held: Some(held) })
+	}
+
+	/// Number of descriptors held open
+	pub fn held(&self) -> usize {
+		self.held.as_ref().map_or(0, FdBallast::len)
+	}
+
+	/// Undo the exhaustion, reporting a failure to restore the limit
+	pub fn restore(mut self) -> Result<(), Error> {
+		self.undo()
+	}
+
+	fn undo(&mut self) -> Result<(), Error> {
+		self.held = None;
+		let Some(rlim) = self.previous.take() else { return Ok(()) };
+		// Only read for the audit log, the limit is restored regardless
+		let lowered = crate::get_limit(Resource::NoFile).ok();
+		System.setrlimit(Resource::NoFile, rlim).map_err(|error| Error::FailedToSetLimit {
+			from: lowered.map_or(rlim.soft, |lowered| lowered.soft),
+			to: rlim.soft,
+			error,
+		})?;
+		if let Some(lowered) = lowered {
+			crate::audit::record(None, Resource::NoFile, lowered, rlim);
+		}
+		Ok(())
+	}
+}
+
+impl Drop for Exhaustion {
+	fn drop(&mut self) {
+		let _ = self.undo();
+	}
+}
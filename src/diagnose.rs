@@ -0,0 +1,171 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Everything deciding the process's limits, gathered in one report.
+
+use crate::backend::{Rlimit, RlimitOps, System};
+use crate::{
+	Environment, PamLimit, PlatformInfo, Resource, SshSession, SystemdDefault, SystemdManager,
+	UserNamespace, WslVersion,
+};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Limits, usage, kernel ceilings and the context they come from, see
+/// [`diagnose`].
+///
+/// Parts that couldn't be read are left empty.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Diagnosis {
+	/// Limits of every resource available on this platform
+	pub limits: BTreeMap<Resource, Rlimit>,
+	/// Number of open descriptors
+	pub open_fds: Option<u64>,
+	/// Per-process kernel ceiling of open files (`kern.maxfilesperproc`,
+	/// `fs.nr_open` on Linux)
+	pub max_files_per_process: Option<u64>,
+	/// Max number of open files in the whole system
+	pub max_files: Option<u64>,
+	/// Max number of streams of the C runtime, on Windows
+	pub max_stdio: Option<u64>,
+	/// How the process was started
+	pub environment: Environment,
+	/// Whether the process may raise its hard limits
+	pub can_raise_hard_limit: bool,
+	/// Facts about the platform
+	pub platform: PlatformInfo,
+	/// Defaults of the systemd system manager for units without their own
+	/// limits, often the reason of a low hard limit
+	pub systemd_defaults: BTreeMap<Resource, SystemdDefault>,
+	/// Entries of `limits.conf` applying to the user in a login session, empty
+	/// elsewhere as `pam_limits` only runs when sessions are opened
+	pub pam_limits: Vec<PamLimit>,
+	/// SSH session the process was started from, whose limits often differ
+	/// from the ones services get
+	pub ssh: Option<SshSession>,
+	/// User namespace of the process, whose hard limits can't be raised
+	pub user_namespace: Option<UserNamespace>,
+}
+
+/// Gather the limits of the current process and what decides them, for
+/// startup logs and support tickets
+pub fn diagnose() -> Diagnosis {
+	diagnose_with(&System)
+}
+
+/// Same as [`diagnose`] reading limits, usage and ceilings from the given
+/// backend
+pub fn diagnose_with<B: RlimitOps>(backend: &B) -> Diagnosis {
+	let limits = crate::get_all_limits_with(backend)
+		.into_iter()
+		.filter_map(|(resource, rlim)| Some((resource, rlim.ok()?)))
+		.collect();
+	let environment = Environment::detect();
+	let pam_limits = match environment {
+		Environment::LoginSession { .. } => crate::pam_limits(),
+		_ => Vec::new(),
+	};
+	Diagnosis {
+		limits,
+		open_fds: backend.open_fds().ok(),
+		max_files_per_process: backend.sysctl_maxfiles().ok().flatten(),
+		max_files: backend.system_maxfiles().ok().flatten(),
+		max_stdio: crate::max_stdio(),
+		environment,
+		can_raise_hard_limit: crate::can_raise_hard_limit(),
+		platform: crate::platform_info().clone(),
+		systemd_defaults: crate::systemd_default_limits(SystemdManager::System),
+		pam_limits,
+		ssh: crate::ssh_session(),
+		user_namespace: crate::user_namespace(),
+	}
+}
+
+/// Multi-line report meant for logs
+impl fmt::Display for Diagnosis {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match &self.environment {
+			Environment::Shell => writeln!(f, "environment: shell")?,
+			Environment::Systemd { unit } => {
+				writeln!(f, "environment: systemd {}", unit.as_deref().unwrap_or("unit"))?
+			}
+			Environment::Launchd { session, label } => writeln!(
+				f,
+				"environment: launchd {} {}",
+				format!("{session:?}").to_lowercase(),
+				label.as_deref().unwrap_or("job")
+			)?,
+			Environment::Container => writeln!(f, "environment: container")?,
+			Environment::Kubernetes { namespace, pod } => writeln!(
+				f,
+				"environment: kubernetes pod {}/{}",
+				namespace.as_deref().unwrap_or("?"),
+				pod.as_deref().unwrap_or("?")
+			)?,
+			Environment::Wsl { version, distro } => writeln!(
+				f,
+				"environment: wsl {} {}",
+				match version {
+					WslVersion::V1 => 1,
+					WslVersion::V2 => 2,
+				},
+				distro.as_deref().unwrap_or("distribution")
+			)?,
+			Environment::LoginSession { service } => writeln!(
+				f,
+				"environment: login session {}",
+				service.as_deref().unwrap_or("of unknown service")
+			)?,
+		}
+		if let Some(ssh) = &self.ssh {
+			writeln!(f, "session: {ssh}")?;
+		}
+		if let Some(release) = &self.platform.kernel_release {
+			writeln!(f, "kernel: {release}")?;
+		}
+		for (resource, rlim) in &self.limits {
+			writeln!(
+				f,
+				"limit {resource}: soft {}, hard {}",
+				rlim.soft_limit(),
+				rlim.hard_limit()
+			)?;
+		}
+		for entry in &self.pam_limits {
+			writeln!(f, "pam limit: {entry}")?;
+		}
+		for default in self.systemd_defaults.values() {
+			writeln!(f, "systemd default: {default}")?;
+		}
+		match self.open_fds {
+			Some(open_fds) => writeln!(f, "open descriptors: {open_fds}")?,
+			None => writeln!(f, "open descriptors: unknown")?,
+		}
+		if let Some(ceiling) = self.max_files_per_process {
+			writeln!(f, "per-process max open files: {ceiling}")?;
+		}
+		if let Some(max_files) = self.max_files {
+			writeln!(f, "system-wide max open files: {max_files}")?;
+		}
+		if let Some(max_stdio) = self.max_stdio {
+			writeln!(f, "C runtime max open streams: {max_stdio}")?;
+		}
+		if let Some(namespace) = &self.user_namespace {
+			writeln!(f, "user namespace: {namespace}")?;
+		}
+		let can_raise = if self.can_raise_hard_limit { "yes" } else { "no" };
+		writeln!(f, "can raise hard limits: {can_raise}")
+	}
+}
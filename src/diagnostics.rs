@@ -0,0 +1,163 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Snapshots of the descriptor table taken when the application runs out of
+//! descriptors, for post-incident analysis.
+
+use crate::backend::{Rlimit, RlimitOps, System};
+use crate::{FdKind, Resource};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+type Callback = Arc<dyn Fn(&Snapshot) + Send + Sync>;
+
+/// Minimum time between two snapshots, exhaustion tends to come in storms of
+/// failed calls and each snapshot walks the whole descriptor table
+const MIN_INTERVAL: Duration = Duration::from_secs(1);
+
+static HOOK: Mutex<Option<Hook>> = Mutex::new(None);
+
+struct Hook {
+	callback: Callback,
+	last: Option<Instant>,
+	// Closed right before taking a snapshot, which needs a descriptor of its own
+	#[cfg(unix)]
+	spare: Option<std::fs::File>,
+}
+
+/// Descriptor table and limits of the process at the time of the exhaustion.
+///
+/// Parts that couldn't be read are left empty.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Snapshot {
+	/// Error that was reported
+	pub error: String,
+	/// Limits of every resource available on this platform
+	pub limits: BTreeMap<Resource, Rlimit>,
+	/// Number of open descriptors
+	pub open_fds: Option<u64>,
+	/// Open descriptors by kind
+	pub kinds: BTreeMap<FdKind, u64>,
+	/// Every open descriptor and what it refers to
+	#[cfg(target_os = "linux")]
+	pub fds: Vec<crate::OpenFd>,
+}
+
+impl Snapshot {
+	/// Capture the descriptor table and limits of the current process
+	pub fn capture(error: &io::Error) -> Self {
+		let limits = Resource::ALL
+			.iter()
+			.filter(|resource| resource.is_available())
+			.filter_map(|&resource| Some((resource, System.getrlimit(resource).ok()?)))
+			.collect();
+		Snapshot {
+			error: error.to_string(),
+			limits,
+			open_fds: System.open_fds().ok(),
+			kinds: crate::fd_kinds().unwrap_or_default(),
+			#[cfg(target_os = "linux")]
+			fds: crate::open_fds_for_pid(std::process::id()).unwrap_or_default(),
+		}
+	}
+}
+
+/// Multi-line report meant for logs
+impl fmt::Display for Snapshot {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		writeln!(f, "descriptor exhaustion: {}", self.error)?;
+		for (resource, rlim) in &self.limits {
+			writeln!(
+				f,
+				"limit {resource}: soft {}, hard {}",
+				rlim.soft_limit(),
+				rlim.hard_limit()
+			)?;
+		}
+		match self.open_fds {
+			Some(open_fds) => writeln!(f, "open descriptors: {open_fds}")?,
+			None => writeln!(f, "open descriptors: unknown")?,
+		}
+		for (kind, count) in &self.kinds {
+			writeln!(f, "{kind:>10}: {count}")?;
+		}
+		#[cfg(target_os = "linux")]
+		for fd in &self.fds {
+			writeln!(f, "{:>6} {}", fd.fd, fd.target.display())?;
+		}
+		Ok(())
+	}
+}
+
+/// Install the callback receiving a [`Snapshot`] every time the application
+/// hands an exhaustion error to [`report_fd_exhaustion`], replacing the
+/// previous one.
+///
+/// A descriptor is kept in reserve on Unix so the snapshot can still be taken
+/// once the table is full. Snapshots are taken at most once per second.
+pub fn install_emfile_diagnostics<F>(callback: F)
+where
+	F: Fn(&Snapshot) + Send + Sync + 'static,
+{
+	*hook() = Some(Hook {
+		callback: Arc::new(callback),
+		last: None,
+		#[cfg(unix)]
+		spare: std::fs::File::open("/dev/null").ok(),
+	});
+}
+
+/// Remove the installed callback, returns whether one was installed
+pub fn uninstall_emfile_diagnostics() -> bool {
+	hook().take().is_some()
+}
+
+/// Hand an error to the installed callback along with a [`Snapshot`] if it
+/// reports descriptor exhaustion, returns whether a snapshot was taken
+pub fn report_fd_exhaustion(error: &io::Error) -> bool {
+	if !crate::is_fd_exhaustion(error) {
+		return false;
+	}
+
+	let (callback, snapshot) = {
+		let mut hook = hook();
+		let Some(hook) = hook.as_mut() else { return false };
+		if hook.last.is_some_and(|last| last.elapsed() < MIN_INTERVAL) {
+			return false;
+		}
+		hook.last = Some(Instant::now());
+
+		#[cfg(unix)]
+		drop(hook.spare.take());
+		let snapshot = Snapshot::capture(error);
+		// Fails while the table is still full, the next report tries again
+		#[cfg(unix)]
+		{
+			hook.spare = std::fs::File::open("/dev/null").ok();
+		}
+		(hook.callback.clone(), snapshot)
+	};
+
+	// The callback may install another one, don't hold the lock while calling it
+	callback(&snapshot);
+	true
+}
+
+fn hook() -> MutexGuard<'static, Option<Hook>> {
+	HOOK.lock().unwrap_or_else(|e| e.into_inner())
+}
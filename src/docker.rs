@@ -0,0 +1,81 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `docker run --ulimit` arguments and compose file fragments derived from a
+//! [`Policy`].
+
+use crate::backend::{Rlimit, RlimitOps, System};
+use crate::{Error, Limit, Policy, Resource};
+use std::fmt::Write;
+
+/// Soft and hard limits to run a container with
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContainerLimits {
+	/// Limits of each resource, in the policy's order
+	pub limits: Vec<(Resource, Rlimit)>,
+}
+
+/// Resolve the policy into container limits against the hard limits of the
+/// current process, as if it ran on the container's host
+pub fn container_limits(policy: &Policy) -> Result<ContainerLimits, Error> {
+	container_limits_with(&System, policy)
+}
+
+/// Same as [`container_limits`] using the given backend, e.g. a
+/// [`Simulated`](crate::simulated::Simulated) one describing the host
+pub fn container_limits_with<B: RlimitOps>(
+	backend: &B,
+	policy: &Policy,
+) -> Result<ContainerLimits, Error> {
+	let mut limits = Vec::new();
+	for (&resource, rule) in &policy.rules {
+		if crate::remediation::docker_name(resource).is_none() {
+			return Err(Error::ResourceNotAvailable(resource));
+		}
+		let hard = crate::get_limit_with(backend, resource)?.hard;
+		let soft = rule.target.target(hard, hard)?;
+		if let Some(minimum) = rule.minimum.filter(|&minimum| soft < minimum) {
+			return Err(Error::BelowMinimum { resource, minimum, limit: soft });
+		}
+		limits.push((resource, Rlimit { soft, hard }));
+	}
+
+	Ok(ContainerLimits { limits })
+}
+
+impl ContainerLimits {
+	/// Arguments for `docker run`, e.g. `["--ulimit", "nofile=65536:1048576"]`
+	pub fn docker_args(&self) -> Vec<String> {
+		self.iter()
+			.flat_map(|(name, soft, hard)| ["--ulimit".to_owned(), format!("{name}={soft}:{hard}")])
+			.collect()
+	}
+
+	/// `ulimits` section of a compose file service
+	pub fn compose_yaml(&self) -> String {
+		let mut yaml = String::from("ulimits:\n");
+		for (name, soft, hard) in self.iter() {
+			let _ = write!(yaml, "  {name}:\n    soft: {soft}\n    hard: {hard}\n");
+		}
+		yaml
+	}
+
+	/// Docker name, soft and hard values, unlimited being -1
+	fn iter(&self) -> impl Iterator<Item = (&'static str, String, String)> + '_ {
+		let value = |raw| crate::remediation::docker_value(Limit::from_raw(raw));
+		self.limits.iter().filter_map(move |&(resource, rlim)| {
+			Some((crate::remediation::docker_name(resource)?, value(rlim.soft), value(rlim.hard)))
+		})
+	}
+}
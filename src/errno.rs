@@ -0,0 +1,106 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+use std::io;
+
+/// Codes with their names, the first one wins where platforms alias codes
+/// such as `EWOULDBLOCK` and `EAGAIN`
+#[cfg(unix)]
+const NAMES: &[(i32, &str)] = &[
+	(libc::EPERM, "EPERM"),
+	(libc::ENOENT, "ENOENT"),
+	(libc::ESRCH, "ESRCH"),
+	(libc::EINTR, "EINTR"),
+	(libc::EIO, "EIO"),
+	(libc::ENXIO, "ENXIO"),
+	(libc::E2BIG, "E2BIG"),
+	(libc::ENOEXEC, "ENOEXEC"),
+	(libc::EBADF, "EBADF"),
+	(libc::ECHILD, "ECHILD"),
+	(libc::EAGAIN, "EAGAIN"),
+	(libc::ENOMEM, "ENOMEM"),
+	(libc::EACCES, "EACCES"),
+	(libc::EFAULT, "EFAULT"),
+	(libc::EBUSY, "EBUSY"),
+	(libc::EEXIST, "EEXIST"),
+	(libc::EXDEV, "EXDEV"),
+	(libc::ENODEV, "ENODEV"),
+	(libc::ENOTDIR, "ENOTDIR"),
+	(libc::EISDIR, "EISDIR"),
+	(libc::EINVAL, "EINVAL"),
+	(libc::ENFILE, "ENFILE"),
+	(libc::EMFILE, "EMFILE"),
+	(libc::ENOTTY, "ENOTTY"),
+	(libc::ETXTBSY, "ETXTBSY"),
+	(libc::EFBIG, "EFBIG"),
+	(libc::ENOSPC, "ENOSPC"),
+	(libc::ESPIPE, "ESPIPE"),
+	(libc::EROFS, "EROFS"),
+	(libc::EMLINK, "EMLINK"),
+	(libc::EPIPE, "EPIPE"),
+	(libc::EDOM, "EDOM"),
+	(libc::ERANGE, "ERANGE"),
+	(libc::EDEADLK, "EDEADLK"),
+	(libc::ENAMETOOLONG, "ENAMETOOLONG"),
+	(libc::ENOSYS, "ENOSYS"),
+	(libc::ELOOP, "ELOOP"),
+	(libc::EOPNOTSUPP, "EOPNOTSUPP"),
+	(libc::ENOTSUP, "ENOTSUP"),
+	(libc::EADDRINUSE, "EADDRINUSE"),
+	(libc::ECONNREFUSED, "ECONNREFUSED"),
+	(libc::ECONNRESET, "ECONNRESET"),
+	(libc::ETIMEDOUT, "ETIMEDOUT"),
+];
+
+/// Win32 and Winsock codes, which `raw_os_error` returns there
+#[cfg(windows)]
+const NAMES: &[(i32, &str)] = &[
+	(2, "ERROR_FILE_NOT_FOUND"),
+	(4, "ERROR_TOO_MANY_OPEN_FILES"),
+	(5, "ERROR_ACCESS_DENIED"),
+	(6, "ERROR_INVALID_HANDLE"),
+	(8, "ERROR_NOT_ENOUGH_MEMORY"),
+	(87, "ERROR_INVALID_PARAMETER"),
+	(10024, "WSAEMFILE"),
+	(10055, "WSAENOBUFS"),
+];
+
+#[cfg(not(any(unix, windows)))]
+const NAMES: &[(i32, &str)] = &[];
+
+/// Symbolic name of an OS error code, e.g. `EMFILE` for 24 on Linux, `None`
+/// for codes unknown to this crate
+pub fn errno_name(code: i32) -> Option<&'static str> {
+	NAMES.iter().find(|&&(known, _)| known == code).map(|&(_, name)| name)
+}
+
+/// Displays an I/O error with the name of its code, e.g.
+/// `Too many open files (EMFILE, os error 24)`
+pub(crate) struct WithErrno<'a>(pub &'a io::Error);
+
+impl fmt::Display for WithErrno<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let Some((code, name)) =
+			self.0.raw_os_error().and_then(|code| Some((code, errno_name(code)?)))
+		else {
+			return self.0.fmt(f);
+		};
+		let text = self.0.to_string();
+		match text.strip_suffix(&format!(" (os error {code})")) {
+			Some(detail) => write!(f, "{detail} ({name}, os error {code})"),
+			None => write!(f, "{text} ({name})"),
+		}
+	}
+}
@@ -0,0 +1,225 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::backend::{RlimitOps, System};
+use crate::rate_limit::TokenBucket;
+use crate::{Outcome, RateLimit, Resource, Strategy};
+use std::io;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Instant;
+
+/// Whether the error reports running out of file descriptors, for the
+/// process or the whole system.
+///
+/// Covers `EMFILE` and `ENFILE` on Unix. On Windows it covers
+/// `WSAEMFILE` from sockets and `ERROR_TOO_MANY_OPEN_FILES` from file APIs,
+/// whose raw codes differ from the C runtime `EMFILE`.
+pub fn is_fd_exhaustion(error: &io::Error) -> bool {
+	#[cfg(unix)]
+	const CODES: &[i32] = &[libc::EMFILE, libc::ENFILE];
+	// WSAEMFILE and ERROR_TOO_MANY_OPEN_FILES
+	#[cfg(windows)]
+	const CODES: &[i32] = &[10024, 4];
+	#[cfg(not(any(unix, windows)))]
+	const CODES: &[i32] = &[];
+
+	error.raw_os_error().is_some_and(|code| CODES.contains(&code))
+}
+
+/// Whether raising the process limit can help, `ENFILE` is system-wide and
+/// Windows has no limit to raise
+#[cfg(unix)]
+fn is_process_exhaustion(error: &io::Error) -> bool {
+	error.raw_os_error() == Some(libc::EMFILE)
+}
+
+#[cfg(not(unix))]
+fn is_process_exhaustion(_error: &io::Error) -> bool {
+	false
+}
+
+/// Outcome of an operation run through [`AutoRaise`]
+#[derive(Debug)]
+pub enum Attempt<T> {
+	/// The operation completed, successfully or not
+	Done(io::Result<T>),
+	/// The operation ran out of descriptors and the open files limit was
+	/// raised since it started, retrying it is worthwhile
+	Retry(io::Error),
+}
+
+/// Raises the open files limit when an operation fails with `EMFILE`.
+///
+/// Once raising stops making progress the limit is left alone, later failures
+/// are returned as they are instead of looping on the same raise. With a
+/// [rate limit](Self::with_rate_limit) it keeps trying as often as the limit
+/// allows instead.
+#[derive(Debug, Default)]
+pub struct AutoRaise {
+	strategy: Strategy,
+	state: Mutex<RaiseState>,
+}
+
+#[derive(Debug, Default)]
+struct RaiseState {
+	// Number of raises that made progress, operations started before the latest
+	// one can be retried without raising again
+	generation: u64,
+	at_max: bool,
+	bucket: Option<TokenBucket>,
+}
+
+impl AutoRaise {
+	/// Create a wrapper that hasn't raised the limit yet
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Create a wrapper raising the limit as often as the rate limit allows,
+	/// so a limit raised from outside the process is picked up after failures
+	/// or once the maximum was reached
+	pub fn with_rate_limit(limit: RateLimit) -> Self {
+		AutoRaise::with_config(Strategy::MaxAvailable, Some(limit))
+	}
+
+	pub(crate) fn with_config(strategy: Strategy, limit: Option<RateLimit>) -> Self {
+		let state = RaiseState { bucket: limit.map(TokenBucket::new), ..Default::default() };
+		AutoRaise { strategy, state: Mutex::new(state) }
+	}
+
+	/// Run the operation, raising the limit if it runs out of descriptors
+	pub fn run<T>(&self, op: impl FnOnce() -> io::Result<T>) -> Attempt<T> {
+		self.run_with(&System, op)
+	}
+
+	/// Same as [`run`](Self::run) using the given backend
+	pub fn run_with<B: RlimitOps, T>(
+		&self,
+		backend: &B,
+		op: impl FnOnce() -> io::Result<T>,
+	) -> Attempt<T> {
+		let started = self.state().generation;
+		let error = match op() {
+			Err(error) if is_process_exhaustion(&error) => error,
+			result => return Attempt::Done(result),
+		};
+
+		{
+			let mut state = self.state();
+			if state.generation != started {
+				return Attempt::Retry(error);
+			}
+			if state.at_max {
+				return Attempt::Done(Err(error));
+			}
+			if let Some(bucket) = &mut state.bucket {
+				if !bucket.try_acquire(Instant::now()) {
+					return Attempt::Done(Err(error));
+				}
+			}
+		}
+
+		// Raising fires the observers, which may use this wrapper, so the state
+		// isn't locked meanwhile
+		let raised = crate::raise_and_notify(backend, Resource::NoFile, &self.strategy);
+		let outcome = raised.map(|raised| raised.audit(backend));
+
+		let mut state = self.state();
+		if state.generation != started {
+			// Raised by another thread in the meantime
+			return Attempt::Retry(error);
+		}
+		match outcome {
+			Ok(Outcome::LimitRaised { from, to }) if to > from => {
+				state.generation += 1;
+				Attempt::Retry(error)
+			}
+			_ => {
+				state.at_max = state.bucket.is_none();
+				Attempt::Done(Err(error))
+			}
+		}
+	}
+
+	/// Run the operation, retrying it for as long as raising the limit helps
+	pub fn call<T>(&self, op: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+		self.call_with(&System, op)
+	}
+
+	/// Same as [`call`](Self::call) using the given backend
+	pub fn call_with<B: RlimitOps, T>(
+		&self,
+		backend: &B,
+		mut op: impl FnMut() -> io::Result<T>,
+	) -> io::Result<T> {
+		loop {
+			if let Attempt::Done(result) = self.run_with(backend, &mut op) {
+				return result;
+			}
+		}
+	}
+
+	/// Number of raises that made progress
+	pub fn raises(&self) -> u64 {
+		self.state().generation
+	}
+
+	fn state(&self) -> MutexGuard<'_, RaiseState> {
+		self.state.lock().unwrap_or_else(|e| e.into_inner())
+	}
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+	use super::*;
+	use crate::simulated::Simulated;
+	use std::sync::atomic::{AtomicBool, Ordering};
+	use std::sync::Arc;
+
+	fn emfile() -> io::Result<()> {
+		Err(io::Error::from_raw_os_error(libc::EMFILE))
+	}
+
+	#[test]
+	fn retries_after_raising() {
+		let auto = AutoRaise::new();
+		let backend = Simulated::new(256, 4096);
+		assert!(matches!(auto.run_with(&backend, emfile), Attempt::Retry(_)));
+		assert_eq!(auto.raises(), 1);
+		// At the hard limit already, raising again doesn't help
+		assert!(matches!(auto.run_with(&backend, emfile), Attempt::Done(Err(_))));
+		assert!(matches!(auto.run_with(&backend, emfile), Attempt::Done(Err(_))));
+		assert_eq!(auto.raises(), 1);
+	}
+
+	#[test]
+	fn observers_can_use_the_wrapper() {
+		let auto = Arc::new(AutoRaise::new());
+		let observed = Arc::new(AtomicBool::new(false));
+		let id = crate::on_raise({
+			let (auto, observed) = (auto.clone(), observed.clone());
+			move |_, _| {
+				auto.raises();
+				observed.store(true, Ordering::Relaxed);
+			}
+		});
+
+		let backend = Simulated::new(256, 4096);
+		let attempt = auto.run_with(&backend, emfile);
+		crate::remove_on_raise(id);
+		assert!(matches!(attempt, Attempt::Retry(_)));
+		assert!(observed.load(Ordering::Relaxed));
+	}
+}
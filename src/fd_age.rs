@@ -0,0 +1,81 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::OpenFd;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// How long descriptors have been open, tracked across snapshots of the
+/// descriptor table.
+///
+/// The kernel doesn't record when a descriptor was opened, ages count from the
+/// first snapshot that listed it and are lower bounds. A number reused for
+/// another target counts as a new descriptor, one closed and reopened to the
+/// same target between two snapshots goes unnoticed.
+#[derive(Debug, Clone, Default)]
+pub struct FdAges {
+	seen: BTreeMap<u32, (PathBuf, Instant)>,
+}
+
+impl FdAges {
+	/// Create a tracker that hasn't seen any descriptor yet
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Record a snapshot taken now, see [`update_at`](Self::update_at)
+	pub fn update(&mut self, fds: &[OpenFd]) {
+		self.update_at(Instant::now(), fds)
+	}
+
+	/// Record a snapshot taken at the given time, forgetting the descriptors
+	/// it doesn't list
+	pub fn update_at(&mut self, at: Instant, fds: &[OpenFd]) {
+		let mut seen = BTreeMap::new();
+		for open_fd in fds {
+			let since = match self.seen.remove(&open_fd.fd) {
+				Some((target, since)) if target == open_fd.target => since,
+				_ => at,
+			};
+			seen.insert(open_fd.fd, (open_fd.target.clone(), since));
+		}
+		self.seen = seen;
+	}
+
+	/// When the descriptor was first seen, `None` if the latest snapshot
+	/// didn't list it
+	pub fn first_seen(&self, open_fd: &OpenFd) -> Option<Instant> {
+		match self.seen.get(&open_fd.fd) {
+			Some((target, since)) if *target == open_fd.target => Some(*since),
+			_ => None,
+		}
+	}
+
+	/// Time since the descriptor was first seen
+	pub fn age(&self, open_fd: &OpenFd) -> Option<Duration> {
+		self.first_seen(open_fd).map(|since| since.elapsed())
+	}
+
+	/// Descriptors of the latest snapshot with their age, oldest first
+	pub fn oldest(&self) -> Vec<(OpenFd, Duration)> {
+		let mut fds: Vec<_> = self
+			.seen
+			.iter()
+			.map(|(&fd, (target, since))| (OpenFd { fd, target: target.clone() }, since.elapsed()))
+			.collect();
+		fds.sort_by_key(|(_, age)| std::cmp::Reverse(*age));
+		fds
+	}
+}
@@ -0,0 +1,194 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Details of open descriptors read from `/proc/<pid>/fdinfo`, linking them to
+//! the subsystem consuming the underlying resources.
+
+use crate::{Error, OpenFd};
+use std::collections::BTreeMap;
+
+/// Contents of `/proc/<pid>/fdinfo/<fd>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FdInfo {
+	/// Descriptor and what it refers to
+	pub open_fd: OpenFd,
+	/// File offset
+	pub pos: u64,
+	/// Flags the file was opened with, `O_*` values
+	pub flags: u32,
+	/// Mount the file lives on, as in `/proc/<pid>/mountinfo`
+	pub mnt_id: Option<u64>,
+	/// Subsystem specific details
+	pub detail: FdDetail,
+}
+
+/// Subsystem specific part of an fdinfo file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FdDetail {
+	/// Number of descriptors registered with an `epoll` instance
+	Epoll {
+		/// Registered descriptors
+		watches: usize,
+	},
+	/// Number of watches of an `inotify` instance, each counting towards
+	/// `fs.inotify.max_user_watches`
+	Inotify {
+		/// Watches
+		watches: usize,
+	},
+	/// Files and buffers registered with an `io_uring` instance
+	IoUring {
+		/// Registered files
+		files: usize,
+		/// Registered buffers
+		buffers: usize,
+	},
+	/// Counter of an `eventfd`
+	EventFd {
+		/// Counter value
+		count: u64,
+	},
+	/// Nothing beyond the common fields
+	None,
+}
+
+/// Read the fdinfo of every descriptor the process has open, ordered by
+/// number.
+///
+/// Descriptors closed while reading are skipped.
+pub fn fdinfo_for_pid(pid: u32) -> Result<Vec<FdInfo>, Error> {
+	let mut infos = Vec::new();
+	for open_fd in crate::open_fds_for_pid(pid)? {
+		if let Ok(contents) = std::fs::read_to_string(format!("/proc/{pid}/fdinfo/{}", open_fd.fd))
+		{
+			infos.push(parse(open_fd, &contents));
+		}
+	}
+	Ok(infos)
+}
+
+/// Read the fdinfo of every descriptor of the current process
+pub fn fdinfo() -> Result<Vec<FdInfo>, Error> {
+	fdinfo_for_pid(std::process::id())
+}
+
+fn parse(open_fd: OpenFd, contents: &str) -> FdInfo {
+	let fields: BTreeMap<&str, &str> = contents
+		.lines()
+		.filter_map(|line| line.split_once(':'))
+		.map(|(key, value)| (key.trim(), value.trim()))
+		.collect();
+	let number = |key: &str| fields.get(key).and_then(|value| value.parse::<u64>().ok());
+	let count = |prefix: &str| contents.lines().filter(|line| line.starts_with(prefix)).count();
+
+	let detail = match open_fd.target.to_str().and_then(|target| target.strip_prefix("anon_inode:"))
+	{
+		Some("[eventpoll]") => FdDetail::Epoll { watches: count("tfd:") },
+		Some("inotify") => FdDetail::Inotify { watches: count("inotify wd:") },
+		Some("[io_uring]") => FdDetail::IoUring {
+			files: number("UserFiles").unwrap_or(0) as usize,
+			buffers: number("UserBufs").unwrap_or(0) as usize,
+		},
+		Some("[eventfd]") => FdDetail::EventFd {
+			// Printed in hex since Linux 5.1
+			count: fields
+				.get("eventfd-count")
+				.and_then(|value| u64::from_str_radix(value, 16).ok())
+				.unwrap_or(0),
+		},
+		_ => FdDetail::None,
+	};
+
+	FdInfo {
+		pos: number("pos").unwrap_or(0),
+		flags: fields
+			.get("flags")
+			.and_then(|value| u32::from_str_radix(value, 8).ok())
+			.unwrap_or(0),
+		mnt_id: number("mnt_id"),
+		detail,
+		open_fd,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn parse_sample(target: &str, contents: &str) -> FdInfo {
+		parse(OpenFd { fd: 3, target: target.into() }, contents)
+	}
+
+	#[test]
+	fn parses_epoll() {
+		let info = parse_sample(
+			"anon_inode:[eventpoll]",
+			"pos:\t0\nflags:\t02000002\nmnt_id:\t15\nino:\t1057\n\
+			 tfd:        5 events:       19 data:                5  pos:0 ino:4c1 sdev:d\n\
+			 tfd:        7 events:       19 data:                7  pos:0 ino:4c3 sdev:d\n",
+		);
+		assert_eq!(info.pos, 0);
+		assert_eq!(info.flags, 0o2000002);
+		assert_eq!(info.mnt_id, Some(15));
+		assert_eq!(info.detail, FdDetail::Epoll { watches: 2 });
+	}
+
+	#[test]
+	fn parses_inotify() {
+		let info = parse_sample(
+			"anon_inode:inotify",
+			"pos:\t0\nflags:\t02004000\nmnt_id:\t15\nino:\t1057\n\
+			 inotify wd:2 ino:a2 sdev:800001 mask:fce ignored_mask:0 fhandle-bytes:8 \
+			 fhandle-type:1 f_handle:a200000049be0b1e\n\
+			 inotify wd:1 ino:2 sdev:800001 mask:fce ignored_mask:0 fhandle-bytes:8 \
+			 fhandle-type:1 f_handle:0200000000000000\n",
+		);
+		assert_eq!(info.flags, 0o2004000);
+		assert_eq!(info.detail, FdDetail::Inotify { watches: 2 });
+	}
+
+	#[test]
+	fn parses_eventfd() {
+		let info = parse_sample(
+			"anon_inode:[eventfd]",
+			"pos:\t0\nflags:\t02\nmnt_id:\t15\nino:\t1057\n\
+			 eventfd-count:               1f\neventfd-id: 4\n",
+		);
+		assert_eq!(info.detail, FdDetail::EventFd { count: 31 });
+	}
+
+	#[test]
+	fn parses_io_uring() {
+		let info = parse_sample(
+			"anon_inode:[io_uring]",
+			"pos:\t0\nflags:\t02000002\nmnt_id:\t15\nino:\t1057\n\
+			 SqMask:\t0x3f\nSqHead:\t0\nSqTail:\t0\nCachedSqHead:\t0\n\
+			 CqMask:\t0x7f\nCqHead:\t0\nCqTail:\t0\nCachedCqTail:\t0\n\
+			 SQEs:\t0\nCQEs:\t0\nSqThread:\t-1\nSqThreadCpu:\t-1\n\
+			 UserFiles:\t2\n    0: null\n    1: zero\n\
+			 UserBufs:\t1\n    0: 0x7f0a2c000000/4096\n",
+		);
+		assert_eq!(info.detail, FdDetail::IoUring { files: 2, buffers: 1 });
+	}
+
+	#[test]
+	fn parses_plain_files() {
+		let info = parse_sample("/var/log/syslog", "pos:\t4096\nflags:\t0100002\nmnt_id:\t27\n");
+		assert_eq!(info.pos, 4096);
+		assert_eq!(info.flags, 0o100002);
+		assert_eq!(info.mnt_id, Some(27));
+		assert_eq!(info.detail, FdDetail::None);
+	}
+}
@@ -0,0 +1,134 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Capping the size of the files a process writes, for sandboxed workers.
+
+use crate::backend::{Rlimit, RlimitOps, System};
+use crate::{Error, Limit, Resource};
+use std::fmt;
+use std::io;
+
+/// Cap the size of the files the process and the children it spawns can
+/// write, lowering both the soft and the hard limit to `bytes`, returning the
+/// previous limits.
+///
+/// `SIGXFSZ`, which kills the process by default, is ignored as well so that
+/// writes past the limit fail with `EFBIG` instead, see [`FileTooLarge`].
+/// The hard limit can't be raised back without `CAP_SYS_RESOURCE` or root.
+pub fn limit_file_size(bytes: u64) -> Result<Rlimit, Error> {
+	let previous = limit_file_size_with(&System, bytes)?;
+	// Only once the limit is set, a failure leaves the process as it was. The
+	// only failure is an invalid signal number.
+	unsafe { libc::signal(libc::SIGXFSZ, libc::SIG_IGN) };
+	Ok(previous)
+}
+
+/// Same as [`limit_file_size`] using the given backend, leaving the
+/// `SIGXFSZ` disposition alone
+pub fn limit_file_size_with<B: RlimitOps>(backend: &B, bytes: u64) -> Result<Rlimit, Error> {
+	let previous = crate::get_limit_with(backend, Resource::FSize)?;
+	let limit = bytes.min(previous.hard);
+	let capped = Rlimit { soft: limit, hard: limit };
+	let replaced = backend
+		.swap_rlimit(Resource::FSize, capped)
+		.map_err(|error| Error::FailedToSetLimit { from: previous.soft, to: limit, error })?;
+
+	let previous = replaced.unwrap_or(previous);
+	crate::audit::record(backend.pid(), Resource::FSize, previous, capped);
+	Ok(previous)
+}
+
+/// Write that failed with `EFBIG`, past the file size limit of the process or
+/// the largest file the file system supports
+#[derive(Debug)]
+pub struct FileTooLarge {
+	/// Soft file size limit when finite, `None` when the file system's maximum
+	/// was hit
+	pub limit: Option<u64>,
+	/// Low level OS error
+	pub error: io::Error,
+}
+
+impl FileTooLarge {
+	/// Translate a write error, giving it back unchanged unless it's `EFBIG`
+	pub fn from_io(error: io::Error) -> Result<Self, io::Error> {
+		if error.raw_os_error() != Some(libc::EFBIG) {
+			return Err(error);
+		}
+		let limit = match crate::get_limit(Resource::FSize).map(|rlim| rlim.soft_limit()) {
+			Ok(Limit::Finite(limit)) => Some(limit),
+			Ok(Limit::Unlimited) | Err(_) => None,
+		};
+
+		Ok(FileTooLarge { limit, error })
+	}
+}
+
+impl fmt::Display for FileTooLarge {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let error = crate::errno::WithErrno(&self.error);
+		match self.limit {
+			Some(limit) => write!(f, "File size limit of {limit} bytes exceeded: {error}"),
+			None => write!(f, "File too large for the file system: {error}"),
+		}
+	}
+}
+
+impl std::error::Error for FileTooLarge {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		Some(&self.error)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::backend::RLIM_INFINITY;
+	use crate::simulated::Simulated;
+
+	#[test]
+	fn caps_both_limits() {
+		let backend =
+			Simulated::new(1024, 4096).with_limit(Resource::FSize, RLIM_INFINITY, RLIM_INFINITY);
+		let previous = limit_file_size_with(&backend, 1 << 20).unwrap();
+		assert_eq!(previous, Rlimit { soft: RLIM_INFINITY, hard: RLIM_INFINITY });
+		assert_eq!(backend.limit(Resource::FSize), Some(Rlimit { soft: 1 << 20, hard: 1 << 20 }));
+	}
+
+	#[test]
+	fn caps_at_the_previous_hard_limit() {
+		let backend = Simulated::new(1024, 4096).with_limit(Resource::FSize, 1 << 10, 1 << 20);
+		let previous = limit_file_size_with(&backend, 1 << 30).unwrap();
+		assert_eq!(previous, Rlimit { soft: 1 << 10, hard: 1 << 20 });
+		assert_eq!(backend.limit(Resource::FSize), Some(Rlimit { soft: 1 << 20, hard: 1 << 20 }));
+	}
+
+	#[test]
+	fn reports_failures() {
+		let backend = Simulated::new(1024, 4096)
+			.with_limit(Resource::FSize, 1 << 20, 1 << 20)
+			.fail_setrlimit(libc::EPERM);
+		let error = limit_file_size_with(&backend, 1 << 10).unwrap_err();
+		assert!(matches!(error, Error::FailedToSetLimit { from: 1048576, to: 1024, .. }));
+		assert_eq!(backend.limit(Resource::FSize), Some(Rlimit { soft: 1 << 20, hard: 1 << 20 }));
+	}
+
+	#[test]
+	fn translates_efbig_only() {
+		let error = FileTooLarge::from_io(io::Error::from_raw_os_error(libc::EFBIG)).unwrap();
+		assert_eq!(error.error.raw_os_error(), Some(libc::EFBIG));
+		let error = FileTooLarge::from_io(io::Error::from_raw_os_error(libc::ENOSPC)).unwrap_err();
+		assert_eq!(error.raw_os_error(), Some(libc::ENOSPC));
+	}
+}
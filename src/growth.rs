@@ -0,0 +1,172 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Alerting on the growth rate of the open descriptors, catching slow leaks
+//! long before the usage thresholds are reached.
+
+use crate::backend::{RlimitOps, System};
+use crate::{Error, Limit, Resource};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Sustained growth reported by [`GrowthWatch`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Growth {
+	/// Descriptors opened per second over the window, least squares fit
+	pub slope: f64,
+	/// Open descriptors at the latest sample
+	pub usage: u64,
+	/// Time left before reaching the soft limit at this rate, `None` when
+	/// unlimited or when the sample was recorded without the limit
+	pub exhaustion_in: Option<Duration>,
+}
+
+/// Tracks the open descriptors over a sliding window and alerts when they grow
+/// faster than the allowed slope for the whole window
+#[derive(Debug, Clone)]
+pub struct GrowthWatch {
+	max_slope: f64,
+	window: Duration,
+	samples: VecDeque<(Instant, u64)>,
+}
+
+impl GrowthWatch {
+	/// Alert when more than `max_slope` descriptors per second are opened on
+	/// average, sustained over `window`
+	pub fn new(max_slope: f64, window: Duration) -> Self {
+		GrowthWatch { max_slope, window, samples: VecDeque::new() }
+	}
+
+	/// Count the open descriptors of the current process and record them
+	pub fn sample(&mut self) -> Result<Option<Growth>, Error> {
+		self.sample_with(&System)
+	}
+
+	/// Same as [`sample`](Self::sample) using the given backend
+	pub fn sample_with<B: RlimitOps>(&mut self, backend: &B) -> Result<Option<Growth>, Error> {
+		let usage = backend.open_fds().map_err(Error::FailedToCountFds)?;
+		let Some(mut growth) = self.record(Instant::now(), usage) else { return Ok(None) };
+		if let Limit::Finite(soft) = crate::get_limit_with(backend, Resource::NoFile)?.soft_limit()
+		{
+			let left = soft.saturating_sub(usage) as f64;
+			growth.exhaustion_in = Duration::try_from_secs_f64(left / growth.slope).ok();
+		}
+		Ok(Some(growth))
+	}
+
+	/// Record a sample taken at the given time, returns the growth when it
+	/// exceeds the slope over a full window
+	pub fn record(&mut self, at: Instant, usage: u64) -> Option<Growth> {
+		self.samples.push_back((at, usage));
+		// Keep one sample at or before the window start so it's fully covered
+		while self
+			.samples
+			.get(1)
+			.is_some_and(|&(second, _)| at.duration_since(second) >= self.window)
+		{
+			self.samples.pop_front();
+		}
+
+		let &(first, _) = self.samples.front()?;
+		if at.duration_since(first) < self.window {
+			return None;
+		}
+		let slope = self.slope()?;
+		(slope > self.max_slope).then_some(Growth { slope, usage, exhaustion_in: None })
+	}
+
+	/// Least squares slope of the samples in the window, in descriptors per
+	/// second, `None` with fewer than two samples
+	pub fn slope(&self) -> Option<f64> {
+		let &(origin, _) = self.samples.front()?;
+		let points = self
+			.samples
+			.iter()
+			.map(|&(at, usage)| (at.duration_since(origin).as_secs_f64(), usage as f64));
+		let n = self.samples.len() as f64;
+		let (sum_x, sum_y) = points.clone().fold((0.0, 0.0), |(sx, sy), (x, y)| (sx + x, sy + y));
+		let (mean_x, mean_y) = (sum_x / n, sum_y / n);
+		let (covariance, variance) = points.fold((0.0, 0.0), |(c, v), (x, y)| {
+			(c + (x - mean_x) * (y - mean_y), v + (x - mean_x) * (x - mean_x))
+		});
+		(variance > 0.0).then(|| covariance / variance)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::simulated::Simulated;
+
+	const WINDOW: Duration = Duration::from_secs(60);
+
+	#[test]
+	fn fits_the_slope() {
+		let start = Instant::now();
+		let mut watch = GrowthWatch::new(1.0, WINDOW);
+		assert_eq!(watch.slope(), None);
+
+		for (secs, usage) in [(0, 100), (10, 118), (20, 142), (30, 160)] {
+			watch.record(start + Duration::from_secs(secs), usage);
+		}
+		assert!((watch.slope().unwrap() - 2.04).abs() < 1e-9);
+
+		let mut flat = GrowthWatch::new(1.0, WINDOW);
+		flat.record(start, 100);
+		flat.record(start, 200);
+		assert_eq!(flat.slope(), None);
+	}
+
+	#[test]
+	fn alerts_on_sustained_growth() {
+		let start = Instant::now();
+		let at = |secs| start + Duration::from_secs(secs);
+		let mut watch = GrowthWatch::new(1.0, WINDOW);
+
+		// Fast but not yet over a full window
+		assert_eq!(watch.record(at(0), 100), None);
+		assert_eq!(watch.record(at(30), 200), None);
+		let growth = watch.record(at(60), 300).unwrap();
+		assert!((growth.slope - 10.0 / 3.0).abs() < 1e-9);
+		assert_eq!(growth.usage, 300);
+		assert_eq!(growth.exhaustion_in, None);
+
+		// Samples older than the window are dropped, the usage is now stable
+		assert!(watch.record(at(90), 300).is_some());
+		assert_eq!(watch.record(at(150), 300), None);
+		assert_eq!(watch.slope(), Some(0.0));
+	}
+
+	#[test]
+	fn estimates_exhaustion() {
+		let mut watch = GrowthWatch::new(1.0, WINDOW);
+		let Some(start) = Instant::now().checked_sub(WINDOW) else { return };
+		watch.record(start, 100);
+
+		let backend = Simulated::new(1100, 4096).with_open_fds(700);
+		let growth = watch.sample_with(&backend).unwrap().unwrap();
+		assert_eq!(growth.usage, 700);
+		// 10 descriptors per second, 400 left
+		let exhaustion = growth.exhaustion_in.unwrap();
+		assert!(exhaustion >= Duration::from_secs(40), "{exhaustion:?}");
+		assert!(exhaustion < Duration::from_secs(41), "{exhaustion:?}");
+
+		let mut watch = GrowthWatch::new(1.0, WINDOW);
+		watch.record(start, 100);
+		let unlimited =
+			Simulated::new(crate::backend::RLIM_INFINITY, crate::backend::RLIM_INFINITY)
+				.with_open_fds(700);
+		assert_eq!(watch.sample_with(&unlimited).unwrap().unwrap().exhaustion_in, None);
+	}
+}
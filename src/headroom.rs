@@ -0,0 +1,234 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::backend::RlimitOps;
+use crate::{Error, Resource};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// Descriptors that must stay free, see [`reserve_headroom`]
+static RESERVED: AtomicU64 = AtomicU64::new(0);
+
+/// Record that `n` file descriptors must remain free, on top of any previous
+/// reservation.
+///
+/// The reservation is subtracted from [`fd_headroom`], which lets services keep
+/// room for logging, metrics or emergency admin connections.
+pub fn reserve_headroom(n: u64) {
+	RESERVED.fetch_add(n, Ordering::Relaxed);
+}
+
+/// Give back `n` previously reserved file descriptors
+pub fn release_headroom(n: u64) {
+	release(&RESERVED, n);
+}
+
+fn release(reserved: &AtomicU64, n: u64) {
+	let _ = reserved.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |reserved| {
+		Some(reserved.saturating_sub(n))
+	});
+}
+
+/// Number of file descriptors currently reserved
+pub fn reserved_headroom() -> u64 {
+	RESERVED.load(Ordering::Relaxed)
+}
+
+/// Number of file descriptors that can still be opened before reaching the
+/// soft limit, minus the reserved headroom
+pub fn fd_headroom() -> Result<u64, Error> {
+	fd_headroom_with(&crate::backend::System)
+}
+
+/// Same as [`fd_headroom`] using the given backend
+pub fn fd_headroom_with<B: RlimitOps>(backend: &B) -> Result<u64, Error> {
+	headroom(&RESERVED, backend)
+}
+
+fn headroom<B: RlimitOps>(reserved: &AtomicU64, backend: &B) -> Result<u64, Error> {
+	let rlim = crate::get_limit_with(backend, Resource::NoFile)?;
+	let open = backend.open_fds().map_err(Error::FailedToCountFds)?;
+
+	Ok(rlim.soft.saturating_sub(open).saturating_sub(reserved.load(Ordering::Relaxed)))
+}
+
+/// File descriptors claimed with [`try_reserve_fds`], released when dropped
+#[derive(Debug)]
+#[must_use = "the descriptors are released when the reservation is dropped"]
+pub struct FdReservation {
+	count: u64,
+	reserved: &'static AtomicU64,
+}
+
+impl FdReservation {
+	/// Number of descriptors reserved
+	pub fn count(&self) -> u64 {
+		self.count
+	}
+}
+
+impl Drop for FdReservation {
+	fn drop(&mut self) {
+		release(self.reserved, self.count);
+	}
+}
+
+/// Reserve `n` file descriptors for a batch of files about to be opened,
+/// failing with [`Error::InsufficientHeadroom`] if fewer are left.
+///
+/// The reservation counts towards [`reserved_headroom`], so concurrent
+/// reservations can't claim the same descriptors.
+pub fn try_reserve_fds(n: u64) -> Result<FdReservation, Error> {
+	try_reserve_fds_with(&crate::backend::System, n)
+}
+
+/// Same as [`try_reserve_fds`] using the given backend
+pub fn try_reserve_fds_with<B: RlimitOps>(backend: &B, n: u64) -> Result<FdReservation, Error> {
+	reserve(&RESERVED, backend, n)
+}
+
+fn reserve<B: RlimitOps>(
+	reserved: &'static AtomicU64,
+	backend: &B,
+	n: u64,
+) -> Result<FdReservation, Error> {
+	let rlim = crate::get_limit_with(backend, Resource::NoFile)?;
+	let open = backend.open_fds().map_err(Error::FailedToCountFds)?;
+	let free = rlim.soft.saturating_sub(open);
+
+	reserved
+		.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |reserved| {
+			reserved.checked_add(n).filter(|&total| total <= free)
+		})
+		.map(|_| FdReservation { count: n, reserved })
+		.map_err(|reserved| Error::InsufficientHeadroom {
+			requested: n,
+			available: free.saturating_sub(reserved),
+		})
+}
+
+/// Headroom measurement reused for an interval
+#[derive(Debug)]
+pub(crate) struct Sampler {
+	interval: Duration,
+	last: Mutex<Option<(Instant, u64)>>,
+}
+
+impl Sampler {
+	pub(crate) fn new(interval: Duration) -> Self {
+		Sampler { interval, last: Mutex::new(None) }
+	}
+
+	/// Headroom measured less than an interval ago, or now
+	pub(crate) fn headroom(&self) -> Option<u64> {
+		self.headroom_with(&crate::backend::System)
+	}
+
+	/// Threads finding the sample stale at the same time all take a new one,
+	/// the state isn't locked while measuring
+	pub(crate) fn headroom_with<B: RlimitOps>(&self, backend: &B) -> Option<u64> {
+		let last = *self.last();
+		match last {
+			Some((at, headroom)) if at.elapsed() < self.interval => return Some(headroom),
+			_ => {}
+		}
+		let headroom = fd_headroom_with(backend).ok()?;
+		*self.last() = Some((Instant::now(), headroom));
+		Some(headroom)
+	}
+
+	/// Forget the last measurement, the next one is taken anew
+	pub(crate) fn invalidate(&self) {
+		*self.last() = None;
+	}
+
+	fn last(&self) -> MutexGuard<'_, Option<(Instant, u64)>> {
+		self.last.lock().unwrap_or_else(|e| e.into_inner())
+	}
+}
+
+/// Sample [`fd_headroom`] every interval on a tokio task, publishing changes on
+/// a watch channel.
+///
+/// The task stops at the first tick after every receiver is dropped, and failed
+/// samples are skipped. Must be called from within a tokio runtime.
+#[cfg(feature = "tokio")]
+pub fn watch_headroom(interval: Duration) -> Result<tokio::sync::watch::Receiver<u64>, Error> {
+	let (sender, receiver) = tokio::sync::watch::channel(fd_headroom()?);
+	tokio::spawn(async move {
+		let mut ticks = tokio::time::interval(interval);
+		while !sender.is_closed() {
+			ticks.tick().await;
+			if let Ok(headroom) = fd_headroom() {
+				sender.send_if_modified(|current| std::mem::replace(current, headroom) != headroom);
+			}
+		}
+	});
+
+	Ok(receiver)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::simulated::Simulated;
+
+	#[test]
+	fn sampler_reuses_the_last_sample() {
+		let sampler = Sampler::new(Duration::from_secs(3600));
+		let first = sampler.headroom_with(&Simulated::new(256, 4096)).unwrap();
+		assert_eq!(sampler.headroom_with(&Simulated::new(1024, 4096)), Some(first));
+
+		sampler.invalidate();
+		let second = sampler.headroom_with(&Simulated::new(1024, 4096)).unwrap();
+		assert_eq!(second, first + 768);
+	}
+
+	#[test]
+	fn reservations_reduce_the_headroom() {
+		static RESERVED: AtomicU64 = AtomicU64::new(0);
+		let backend = Simulated::new(1024, 4096).with_open_fds(24);
+		assert_eq!(headroom(&RESERVED, &backend).unwrap(), 1000);
+
+		let first = reserve(&RESERVED, &backend, 300).unwrap();
+		let second = reserve(&RESERVED, &backend, 200).unwrap();
+		assert_eq!((first.count(), second.count()), (300, 200));
+		assert_eq!(headroom(&RESERVED, &backend).unwrap(), 500);
+
+		drop(first);
+		assert_eq!(headroom(&RESERVED, &backend).unwrap(), 800);
+		drop(second);
+		assert_eq!(headroom(&RESERVED, &backend).unwrap(), 1000);
+	}
+
+	#[test]
+	fn over_reservation_fails() {
+		static RESERVED: AtomicU64 = AtomicU64::new(0);
+		let backend = Simulated::new(1024, 4096).with_open_fds(24);
+
+		let _reservation = reserve(&RESERVED, &backend, 600).unwrap();
+		assert!(matches!(
+			reserve(&RESERVED, &backend, 401),
+			Err(Error::InsufficientHeadroom { requested: 401, available: 400 })
+		));
+		assert!(matches!(
+			reserve(&RESERVED, &backend, u64::MAX),
+			Err(Error::InsufficientHeadroom { requested: u64::MAX, available: 400 })
+		));
+		// Failed attempts don't count towards the reservation
+		assert_eq!(RESERVED.load(Ordering::Relaxed), 600);
+		assert_eq!(reserve(&RESERVED, &backend, 400).unwrap().count(), 400);
+	}
+}
@@ -0,0 +1,151 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::backend::RlimitOps;
+use crate::{Error, Limit, Resource};
+
+/// Headroom ratios below which the process is degraded or critical, see
+/// [`health`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Thresholds {
+	/// Degraded below this share of the soft limit left, 20% by default
+	pub degraded: f64,
+	/// Critical below this share of the soft limit left, 5% by default
+	pub critical: f64,
+}
+
+impl Default for Thresholds {
+	fn default() -> Self {
+		Thresholds { degraded: 0.2, critical: 0.05 }
+	}
+}
+
+/// Health of the process with regard to open files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(rename_all = "lowercase"))]
+pub enum State {
+	/// Plenty of descriptors left
+	Healthy,
+	/// Headroom is getting low
+	Degraded,
+	/// Close to running out of descriptors
+	Critical,
+}
+
+/// Open files usage, suitable for health endpoints.
+///
+/// With the `serde` feature it serializes as, for example,
+/// `{"usage":120,"limit":1024,"headroom_ratio":0.88,"state":"healthy"}`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Health {
+	/// Number of open file descriptors
+	pub usage: u64,
+	/// Soft open files limit
+	pub limit: Limit,
+	/// Share of the soft limit that can still be opened, minus the reserved
+	/// headroom, 1 when unlimited
+	pub headroom_ratio: f64,
+	/// State derived from the thresholds
+	pub state: State,
+	/// How to raise the limit back above the degraded threshold, `None` when
+	/// healthy
+	#[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+	pub remediation: Option<String>,
+}
+
+/// Check the open files usage of the process against the thresholds
+pub fn health(thresholds: &Thresholds) -> Result<Health, Error> {
+	health_with(&crate::backend::System, thresholds)
+}
+
+/// Same as [`health`] using the given backend
+pub fn health_with<B: RlimitOps>(backend: &B, thresholds: &Thresholds) -> Result<Health, Error> {
+	let rlim = crate::get_limit_with(backend, Resource::NoFile)?;
+	let usage = backend.open_fds().map_err(Error::FailedToCountFds)?;
+	let limit = rlim.soft_limit();
+	let headroom_ratio = match limit {
+		Limit::Finite(0) => 0.0,
+		Limit::Finite(soft) => {
+			soft.saturating_sub(usage).saturating_sub(crate::reserved_headroom()) as f64
+				/ soft as f64
+		}
+		Limit::Unlimited => 1.0,
+	};
+	let state = if headroom_ratio < thresholds.critical {
+		State::Critical
+	} else if headroom_ratio < thresholds.degraded {
+		State::Degraded
+	} else {
+		State::Healthy
+	};
+
+	let remediation = match limit {
+		Limit::Finite(_) if state != State::Healthy => {
+			let target = target(usage.saturating_add(crate::reserved_headroom()), thresholds);
+			Some(crate::remediation(Resource::NoFile, target).to_string())
+		}
+		_ => None,
+	};
+
+	Ok(Health { usage, limit, headroom_ratio, state, remediation })
+}
+
+/// Smallest power of two limit leaving the degraded share of it free with
+/// `needed` descriptors open, unlimited when no finite limit does
+fn target(needed: u64, thresholds: &Thresholds) -> Limit {
+	let limit = needed as f64 / (1.0 - thresholds.degraded);
+	Some(limit)
+		.filter(|limit| limit.is_finite() && *limit >= 0.0)
+		.and_then(|limit| (limit.ceil() as u64).checked_next_power_of_two())
+		.map_or(Limit::Unlimited, Limit::Finite)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::simulated::Simulated;
+
+	fn thresholds(degraded: f64) -> Thresholds {
+		Thresholds { degraded, critical: 0.0 }
+	}
+
+	#[test]
+	fn targets_the_next_power_of_two() {
+		assert_eq!(target(800, &Thresholds::default()), Limit::Finite(1024));
+		assert_eq!(target(1000, &Thresholds::default()), Limit::Finite(2048));
+		assert_eq!(target(0, &Thresholds::default()), Limit::Finite(1));
+	}
+
+	#[test]
+	fn targets_unlimited_out_of_range() {
+		assert_eq!(target(100, &thresholds(1.0)), Limit::Unlimited);
+		assert_eq!(target(100, &thresholds(1.5)), Limit::Unlimited);
+		assert_eq!(target(100, &thresholds(f64::NAN)), Limit::Unlimited);
+		assert_eq!(target(u64::MAX, &Thresholds::default()), Limit::Unlimited);
+	}
+
+	#[test]
+	fn degrades_without_panicking() {
+		let backend = Simulated::new(1024, 4096).with_open_fds(900);
+		let health = health_with(&backend, &thresholds(1.0)).unwrap();
+		assert_eq!(health.state, State::Degraded);
+		assert!(health.remediation.is_some());
+
+		let backend = Simulated::new(1024, 4096).with_open_fds(u64::MAX);
+		let health = health_with(&backend, &Thresholds::default()).unwrap();
+		assert_eq!(health.headroom_ratio, 0.0);
+		assert_eq!(health.state, State::Critical);
+	}
+}
@@ -0,0 +1,101 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Running commands with hard limits raised by the privileged `fdlimit-helper`
+//! binary.
+
+use crate::Resource;
+use std::path::Path;
+use std::process::Command;
+
+/// Where the helper is looked for when `FDLIMIT_HELPER` isn't set
+pub const DEFAULT_HELPER_PATH: &str = "/usr/local/libexec/fdlimit-helper";
+
+/// Resources the helper accepts
+pub const HELPER_RESOURCES: &[Resource] = &[Resource::NoFile, Resource::NProc];
+
+/// Command running `command` through the helper at `FDLIMIT_HELPER`, or
+/// [`DEFAULT_HELPER_PATH`], with the soft and hard limits of the resource
+/// raised.
+///
+/// The helper, built with the `helper` feature and installed setuid root or
+/// with `CAP_SYS_RESOURCE`, raises its own limits, then drops back to the
+/// user and group running it and executes the command, which inherits them.
+/// It only takes the resources of [`HELPER_RESOURCES`], never lowers the soft
+/// or hard limit and caps the hard limit at the kernel ceiling of the
+/// resource. Before running the command it exits with code 2, 3 or 4 and an
+/// error on stderr when the request is invalid, refused or fails.
+///
+/// The arguments, environment changes and working directory of `command` are
+/// carried over, an `env_clear` isn't. A process can raise its own hard
+/// limits by running itself again this way.
+pub fn helper_command(command: &Command, resource: Resource, soft: u64, hard: u64) -> Command {
+	match std::env::var_os("FDLIMIT_HELPER") {
+		Some(path) => helper_command_at(path.as_ref(), command, resource, soft, hard),
+		None => helper_command_at(DEFAULT_HELPER_PATH.as_ref(), command, resource, soft, hard),
+	}
+}
+
+/// Same as [`helper_command`] with the helper at the given path
+pub fn helper_command_at(
+	helper: &Path,
+	command: &Command,
+	resource: Resource,
+	soft: u64,
+	hard: u64,
+) -> Command {
+	let mut wrapped = Command::new(helper);
+	wrapped
+		.arg(resource.name())
+		.arg(soft.to_string())
+		.arg(hard.to_string())
+		.arg("--")
+		.arg(command.get_program())
+		.args(command.get_args());
+	for (name, value) in command.get_envs() {
+		match value {
+			Some(value) => wrapped.env(name, value),
+			None => wrapped.env_remove(name),
+		};
+	}
+	if let Some(dir) = command.get_current_dir() {
+		wrapped.current_dir(dir);
+	}
+	wrapped
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::ffi::OsStr;
+
+	#[test]
+	fn wraps_the_command() {
+		let mut command = Command::new("server");
+		command.args(["--port", "80"]).env("PORT", "80").env_remove("DEBUG").current_dir("/srv");
+		let helper = Path::new("/opt/fdlimit-helper");
+		let wrapped = helper_command_at(helper, &command, Resource::NoFile, 1024, 4096);
+
+		assert_eq!(wrapped.get_program(), helper);
+		let args: Vec<_> = wrapped.get_args().collect();
+		let expected = ["nofile", "1024", "4096", "--", "server", "--port", "80"].map(OsStr::new);
+		assert_eq!(args, expected);
+		let envs: Vec<_> = wrapped.get_envs().collect();
+		assert_eq!(
+			envs,
+			[(OsStr::new("DEBUG"), None), (OsStr::new("PORT"), Some(OsStr::new("80")))]
+		);
+		assert_eq!(wrapped.get_current_dir(), Some(Path::new("/srv")));
+	}
+}
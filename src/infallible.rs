@@ -0,0 +1,180 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Raising limits without allocating or panicking, for early startup and
+//! constrained contexts, and the raising algorithm every entry point builds
+//! on.
+
+use crate::backend::{Rlimit, RlimitOps};
+use crate::strategy::TargetError;
+use crate::{Error, Outcome, RawError, Resource, Strategy};
+use std::{cmp, io};
+
+/// Same as [`raise_fd_limit`](crate::raise_fd_limit) without allocating,
+/// formatting or panicking, failures carrying the OS error code in a
+/// [`RawError`] instead of an [`Error`](crate::Error).
+///
+/// The kernel ceiling isn't read on Linux, the hard limit can't exceed it. The
+/// [`on_raise`](crate::on_raise) callbacks aren't fired. With the
+/// `raw-syscalls` feature kernels without `prlimit64` report `ENOSYS`.
+#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+pub fn raise_fd_limit_infallible() -> Result<Outcome, RawError> {
+	let (before, after) = raise_with(&Boot, Resource::NoFile, &Strategy::MaxAvailable)?;
+	Ok(Outcome::LimitRaised { from: before.soft, to: after.soft })
+}
+
+/// Does nothing on unsupported platform
+#[cfg(not(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd")))]
+pub fn raise_fd_limit_infallible() -> Result<Outcome, RawError> {
+	Ok(Outcome::Unsupported)
+}
+
+/// Step of [`raise_with`] that failed, with the error of the backend
+#[derive(Debug)]
+pub(crate) enum Failure {
+	Sysctl(io::Error),
+	ResourceNotAvailable(Resource),
+	GetLimit(io::Error),
+	Strategy(TargetError),
+	SetLimit { from: u64, to: u64, error: io::Error },
+}
+
+impl From<Failure> for Error {
+	fn from(failure: Failure) -> Self {
+		match failure {
+			Failure::Sysctl(error) => Error::FailedToCallSysctl(error),
+			Failure::ResourceNotAvailable(resource) => Error::ResourceNotAvailable(resource),
+			Failure::GetLimit(error) => Error::FailedToGetLimit(error),
+			Failure::Strategy(error) => error.into(),
+			Failure::SetLimit { from, to, error } => Error::FailedToSetLimit { from, to, error },
+		}
+	}
+}
+
+/// Raise the soft limit to the value picked by the strategy, up to the smaller
+/// of the hard limit and, for open files, the kernel ceiling, returning the
+/// limits before and after. Nothing is allocated besides what the backend
+/// does.
+pub(crate) fn raise_with<B: RlimitOps>(
+	backend: &B,
+	resource: Resource,
+	strategy: &Strategy,
+) -> Result<(Rlimit, Rlimit), Failure> {
+	let maxfiles = match resource {
+		Resource::NoFile => backend.sysctl_maxfiles().map_err(Failure::Sysctl)?,
+		_ => None,
+	};
+	let current = backend.getrlimit(resource).map_err(|error| match error.kind() {
+		io::ErrorKind::Unsupported => Failure::ResourceNotAvailable(resource),
+		_ => Failure::GetLimit(error),
+	})?;
+
+	// The soft limit can be bumped up to the smaller of kern.maxfilesperproc
+	// and the hard limit
+	let available = match maxfiles {
+		Some(maxfiles) => cmp::min(maxfiles, current.hard),
+		None => current.hard,
+	};
+	let soft = strategy.pick(current.hard, available).map_err(Failure::Strategy)?;
+	let rlim = Rlimit { soft, hard: current.hard };
+
+	// Set our newly-increased resource limit, the limits it replaced may differ
+	// from the ones read above if they were changed concurrently
+	let replaced = backend.swap_rlimit(resource, rlim).map_err(|error| Failure::SetLimit {
+		from: current.soft,
+		to: soft,
+		error,
+	})?;
+	Ok((replaced.unwrap_or(current), rlim))
+}
+
+/// System backend without the kernel ceiling on Linux, reading `fs.nr_open`
+/// allocates
+#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+struct Boot;
+
+#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+impl RlimitOps for Boot {
+	fn getrlimit(&self, resource: Resource) -> io::Result<Rlimit> {
+		crate::backend::System.getrlimit(resource)
+	}
+
+	fn setrlimit(&self, resource: Resource, rlim: Rlimit) -> io::Result<()> {
+		crate::backend::System.setrlimit(resource, rlim)
+	}
+
+	fn swap_rlimit(&self, resource: Resource, rlim: Rlimit) -> io::Result<Option<Rlimit>> {
+		crate::backend::System.swap_rlimit(resource, rlim)
+	}
+
+	#[cfg(target_os = "linux")]
+	fn sysctl_maxfiles(&self) -> io::Result<Option<u64>> {
+		Ok(None)
+	}
+
+	#[cfg(not(target_os = "linux"))]
+	fn sysctl_maxfiles(&self) -> io::Result<Option<u64>> {
+		crate::backend::System.sysctl_maxfiles()
+	}
+
+	fn system_maxfiles(&self) -> io::Result<Option<u64>> {
+		Ok(None)
+	}
+
+	fn open_fds(&self) -> io::Result<u64> {
+		Err(io::ErrorKind::Unsupported.into())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::simulated::Simulated;
+	use crate::Method;
+
+	#[test]
+	fn raises_to_the_smallest_ceiling() {
+		let backend = Simulated::new(256, 4096).with_maxfiles(2048);
+		let (before, after) =
+			raise_with(&backend, Resource::NoFile, &Strategy::MaxAvailable).unwrap();
+		assert_eq!(before, Rlimit { soft: 256, hard: 4096 });
+		assert_eq!(after, Rlimit { soft: 2048, hard: 4096 });
+		assert_eq!(backend.limit(Resource::NoFile), Some(after));
+	}
+
+	#[test]
+	fn reports_the_failed_step() {
+		let backend = Simulated::new(256, 4096).fail_sysctl(libc::EACCES);
+		let failure = raise_with(&backend, Resource::NoFile, &Strategy::MaxAvailable).unwrap_err();
+		assert!(matches!(failure, Failure::Sysctl(_)));
+
+		let backend = Simulated::new(256, 4096).fail_setrlimit(libc::EPERM);
+		let error = RawError::from(
+			raise_with(&backend, Resource::NoFile, &Strategy::MaxAvailable).unwrap_err(),
+		);
+		assert_eq!(error, RawError::Failed { method: Method::SetRLimit, errno: libc::EPERM });
+
+		let failure = raise_with(&backend, Resource::NProc, &Strategy::MaxAvailable).unwrap_err();
+		assert!(matches!(failure, Failure::ResourceNotAvailable(Resource::NProc)));
+	}
+
+	#[test]
+	#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+	fn raises_the_process_limit() {
+		let rlim = crate::get_limit(Resource::NoFile).unwrap();
+		match raise_fd_limit_infallible() {
+			Ok(Outcome::LimitRaised { to, .. }) => assert!(to >= rlim.soft && to <= rlim.hard),
+			outcome => panic!("unexpected outcome {outcome:?}"),
+		}
+	}
+}
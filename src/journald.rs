@@ -0,0 +1,159 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Outcomes and alerts sent to journald with structured fields.
+
+use crate::{Error, Growth, Health, Limit, Outcome, Resource, State};
+use std::io;
+use std::os::unix::net::UnixDatagram;
+
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+// syslog priorities
+const CRITICAL: u8 = 2;
+const ERROR: u8 = 3;
+const WARNING: u8 = 4;
+const INFO: u8 = 6;
+
+/// Send one entry with the given fields to journald, names being uppercase
+/// such as `MESSAGE` or `FDLIMIT_NEW`.
+///
+/// `SYSLOG_IDENTIFIER` is set to the executable's name unless given. Entries
+/// must fit in a datagram, a few hundred KiB by default.
+pub fn journal_send(fields: &[(&str, &str)]) -> io::Result<()> {
+	let mut entry = Vec::new();
+	if !fields.iter().any(|&(name, _)| name == "SYSLOG_IDENTIFIER") {
+		if let Some(identifier) = identifier() {
+			append(&mut entry, "SYSLOG_IDENTIFIER", &identifier);
+		}
+	}
+	for &(name, value) in fields {
+		append(&mut entry, name, value);
+	}
+	UnixDatagram::unbound()?.send_to(&entry, JOURNAL_SOCKET)?;
+	Ok(())
+}
+
+/// Field in the native protocol, values with newlines are prefixed with their
+/// little-endian length instead of `=`
+fn append(entry: &mut Vec<u8>, name: &str, value: &str) {
+	entry.extend_from_slice(name.as_bytes());
+	if value.contains('\n') {
+		entry.push(b'\n');
+		entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+	} else {
+		entry.push(b'=');
+	}
+	entry.extend_from_slice(value.as_bytes());
+	entry.push(b'\n');
+}
+
+fn identifier() -> Option<String> {
+	let exe = std::env::current_exe().ok()?;
+	Some(exe.file_name()?.to_string_lossy().into_owned())
+}
+
+/// Log the outcome of raising the resource limit, with `FDLIMIT_RESOURCE`,
+/// `FDLIMIT_OLD` and `FDLIMIT_NEW` fields, or `FDLIMIT_ERROR` on failure
+pub fn journal_outcome(resource: Resource, result: &Result<Outcome, Error>) -> io::Result<()> {
+	let fields = outcome_fields(resource, result);
+	let fields: Vec<_> = fields.iter().map(|(name, value)| (*name, value.as_str())).collect();
+	journal_send(&fields)
+}
+
+fn outcome_fields(
+	resource: Resource,
+	result: &Result<Outcome, Error>,
+) -> Vec<(&'static str, String)> {
+	let resource = resource.to_string();
+	match result {
+		Ok(Outcome::LimitRaised { from, to }) => {
+			let (from, to) = (Limit::from_raw(*from), Limit::from_raw(*to));
+			vec![
+				("MESSAGE", format!("{resource} limit raised from {from} to {to}")),
+				("PRIORITY", INFO.to_string()),
+				("FDLIMIT_RESOURCE", resource),
+				("FDLIMIT_OLD", from.to_string()),
+				("FDLIMIT_NEW", to.to_string()),
+			]
+		}
+		Ok(Outcome::Unsupported) => vec![
+			("MESSAGE", format!("{resource} limit can't be raised here")),
+			("PRIORITY", INFO.to_string()),
+			("FDLIMIT_RESOURCE", resource),
+		],
+		Err(error) => {
+			let error = error.to_string();
+			vec![
+				("MESSAGE", format!("failed to raise {resource} limit: {error}")),
+				("PRIORITY", ERROR.to_string()),
+				("FDLIMIT_RESOURCE", resource),
+				("FDLIMIT_ERROR", error),
+			]
+		}
+	}
+}
+
+/// Log the open files usage when it isn't healthy, warnings for degraded and
+/// critical errors for critical, with `FDLIMIT_USAGE`, `FDLIMIT_LIMIT` and
+/// `FDLIMIT_STATE` fields
+pub fn journal_health(health: &Health) -> io::Result<()> {
+	let (priority, state) = match health.state {
+		State::Healthy => return Ok(()),
+		State::Degraded => (WARNING, "degraded"),
+		State::Critical => (CRITICAL, "critical"),
+	};
+	let mut message = format!("{} of {} file descriptors open", health.usage, health.limit);
+	if let Some(remediation) = &health.remediation {
+		message.push_str(&format!(", {remediation}"));
+	}
+	journal_send(&[
+		("MESSAGE", &message),
+		("PRIORITY", &priority.to_string()),
+		("FDLIMIT_USAGE", &health.usage.to_string()),
+		("FDLIMIT_LIMIT", &health.limit.to_string()),
+		("FDLIMIT_STATE", state),
+	])
+}
+
+/// Log sustained descriptor growth as a warning, with `FDLIMIT_USAGE`,
+/// `FDLIMIT_SLOPE` and, when known, `FDLIMIT_EXHAUSTION_SECS` fields
+pub fn journal_growth(growth: &Growth) -> io::Result<()> {
+	let message = format!("file descriptors growing by {:.1}/s", growth.slope);
+	let mut fields = vec![
+		("MESSAGE", message),
+		("PRIORITY", WARNING.to_string()),
+		("FDLIMIT_USAGE", growth.usage.to_string()),
+		("FDLIMIT_SLOPE", growth.slope.to_string()),
+	];
+	if let Some(exhaustion_in) = growth.exhaustion_in {
+		fields.push(("FDLIMIT_EXHAUSTION_SECS", exhaustion_in.as_secs().to_string()));
+	}
+	let fields: Vec<_> = fields.iter().map(|(name, value)| (*name, value.as_str())).collect();
+	journal_send(&fields)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn outcome_shows_unlimited() {
+		let outcome = Ok(Outcome::LimitRaised { from: 1024, to: crate::backend::RLIM_INFINITY });
+		let fields = outcome_fields(Resource::NoFile, &outcome);
+		assert_eq!(fields[0], ("MESSAGE", "nofile limit raised from 1024 to unlimited".to_owned()));
+		assert_eq!(fields[3], ("FDLIMIT_OLD", "1024".to_owned()));
+		assert_eq!(fields[4], ("FDLIMIT_NEW", "unlimited".to_owned()));
+	}
+}
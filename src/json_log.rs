@@ -0,0 +1,108 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! One JSON line per raise, for log pipelines.
+
+use crate::{json_string, Error, Outcome, Resource};
+use std::io::Write;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+type Sink = Box<dyn Write + Send>;
+
+static SINK: Mutex<Option<Sink>> = Mutex::new(None);
+
+/// Log every raise made through this crate, successful or not, as one JSON
+/// object per line written to `writer`, e.g.
+///
+/// ```text
+/// {"event":"raise","resource":"nofile","outcome":"raised","from":1024,"to":524288,"ceiling":1048576,"duration_us":12}
+/// {"event":"raise","resource":"nofile","outcome":"error","from":1024,"to":2097152,"ceiling":1048576,"duration_us":9,"error":{"method":"setrlimit","kind":"PermissionDenied","os_error":1,"errno":"EPERM","message":"..."}}
+/// ```
+///
+/// `ceiling` is the per-process kernel ceiling of open files, logged when
+/// known. Replaces the previous writer.
+pub fn log_raises_as_json<W: Write + Send + 'static>(writer: W) {
+	*sink() = Some(Box::new(writer));
+}
+
+/// Stop the logging started by [`log_raises_as_json`]
+pub fn stop_json_log() {
+	*sink() = None;
+}
+
+/// Write the line describing a raise if logging is on, dropping it when the
+/// writer fails
+pub(crate) fn log(
+	resource: Resource,
+	result: Result<Outcome, &Error>,
+	ceiling: Option<u64>,
+	duration: Duration,
+) {
+	let mut sink = sink();
+	let Some(writer) = sink.as_mut() else { return };
+
+	let mut fields = vec![
+		"\"event\":\"raise\"".to_owned(),
+		format!("\"resource\":{}", json_string(resource.name())),
+	];
+	let limits = match result {
+		Ok(Outcome::LimitRaised { from, to }) => {
+			fields.push("\"outcome\":\"raised\"".to_owned());
+			Some((from, to))
+		}
+		Ok(Outcome::Unsupported) => {
+			fields.push("\"outcome\":\"unsupported\"".to_owned());
+			None
+		}
+		Err(error) => {
+			fields.push("\"outcome\":\"error\"".to_owned());
+			match error {
+				Error::FailedToSetLimit { from, to, .. } => Some((*from, *to)),
+				_ => None,
+			}
+		}
+	};
+	if let Some((from, to)) = limits {
+		fields.push(format!("\"from\":{from},\"to\":{to}"));
+	}
+	if let Some(ceiling) = ceiling {
+		fields.push(format!("\"ceiling\":{ceiling}"));
+	}
+	fields.push(format!("\"duration_us\":{}", duration.as_micros()));
+	if let Err(error) = result {
+		fields.push(format!("\"error\":{{{}}}", error_fields(error).join(",")));
+	}
+
+	let _ = writeln!(writer, "{{{}}}", fields.join(",")).and_then(|()| writer.flush());
+}
+
+fn error_fields(error: &Error) -> Vec<String> {
+	let mut fields = vec![format!("\"method\":{}", json_string(&error.method().to_string()))];
+	if let Some(io_error) = error.io_error() {
+		fields.push(format!("\"kind\":{}", json_string(&format!("{:?}", io_error.kind()))));
+		if let Some(errno) = io_error.raw_os_error() {
+			fields.push(format!("\"os_error\":{errno}"));
+		}
+		if let Some(name) = error.errno_name() {
+			fields.push(format!("\"errno\":{}", json_string(name)));
+		}
+	}
+	fields.push(format!("\"message\":{}", json_string(&error.to_string())));
+	fields
+}
+
+fn sink() -> MutexGuard<'static, Option<Sink>> {
+	SINK.lock().unwrap_or_else(|e| e.into_inner())
+}
@@ -0,0 +1,530 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Breakdown of the open descriptors, or handles on Windows, by kind.
+
+use crate::Error;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// What an open descriptor refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum FdKind {
+	/// Regular file or device
+	File,
+	/// Directory
+	Directory,
+	/// Socket
+	Socket,
+	/// Pipe or FIFO
+	Pipe,
+	/// Event object, `eventfd` on Linux
+	Event,
+	/// Anything else, e.g. threads or registry keys on Windows
+	Other,
+}
+
+impl fmt::Display for FdKind {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.pad(match self {
+			FdKind::File => "file",
+			FdKind::Directory => "directory",
+			FdKind::Socket => "socket",
+			FdKind::Pipe => "pipe",
+			FdKind::Event => "event",
+			FdKind::Other => "other",
+		})
+	}
+}
+
+/// Finer classification of an open descriptor, see [`fd_histogram`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum FdClass {
+	/// Regular file
+	File,
+	/// Directory
+	Directory,
+	/// Character or block device
+	Device,
+	/// Socket of the given address family
+	Socket(SocketFamily),
+	/// Pipe or FIFO
+	Pipe,
+	/// `epoll` instance, on Linux
+	Epoll,
+	/// `kqueue`, on macOS and FreeBSD
+	Kqueue,
+	/// `eventfd` on Linux, event objects on Windows
+	EventFd,
+	/// `timerfd`, on Linux
+	TimerFd,
+	/// `signalfd`, on Linux
+	SignalFd,
+	/// `inotify` instance, on Linux
+	Inotify,
+	/// Anonymous memory file from `memfd_create`, on Linux
+	MemFd,
+	/// Anything else
+	Other,
+}
+
+/// Address family of a socket
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum SocketFamily {
+	/// Unix domain socket
+	Unix,
+	/// IPv4
+	Inet,
+	/// IPv6
+	Inet6,
+	/// Netlink, on Linux
+	Netlink,
+	/// Any other family, or unknown
+	Other,
+}
+
+impl FdClass {
+	/// Coarser kind of the descriptor, as counted by [`fd_kinds`]
+	pub fn kind(self) -> FdKind {
+		match self {
+			FdClass::File | FdClass::Device | FdClass::MemFd => FdKind::File,
+			FdClass::Directory => FdKind::Directory,
+			FdClass::Socket(_) => FdKind::Socket,
+			FdClass::Pipe => FdKind::Pipe,
+			FdClass::EventFd => FdKind::Event,
+			FdClass::Epoll
+			| FdClass::Kqueue
+			| FdClass::TimerFd
+			| FdClass::SignalFd
+			| FdClass::Inotify
+			| FdClass::Other => FdKind::Other,
+		}
+	}
+}
+
+#[cfg(windows)]
+impl FdClass {
+	fn from_kind(kind: FdKind) -> Self {
+		match kind {
+			FdKind::File => FdClass::File,
+			FdKind::Directory => FdClass::Directory,
+			FdKind::Socket => FdClass::Socket(SocketFamily::Other),
+			FdKind::Pipe => FdClass::Pipe,
+			FdKind::Event => FdClass::EventFd,
+			FdKind::Other => FdClass::Other,
+		}
+	}
+}
+
+/// Short name, sockets with their family, e.g. `socket/inet6`
+impl fmt::Display for FdClass {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.pad(match self {
+			FdClass::File => "file",
+			FdClass::Directory => "directory",
+			FdClass::Device => "device",
+			FdClass::Socket(SocketFamily::Unix) => "socket/unix",
+			FdClass::Socket(SocketFamily::Inet) => "socket/inet",
+			FdClass::Socket(SocketFamily::Inet6) => "socket/inet6",
+			FdClass::Socket(SocketFamily::Netlink) => "socket/netlink",
+			FdClass::Socket(SocketFamily::Other) => "socket/other",
+			FdClass::Pipe => "pipe",
+			FdClass::Epoll => "epoll",
+			FdClass::Kqueue => "kqueue",
+			FdClass::EventFd => "eventfd",
+			FdClass::TimerFd => "timerfd",
+			FdClass::SignalFd => "signalfd",
+			FdClass::Inotify => "inotify",
+			FdClass::MemFd => "memfd",
+			FdClass::Other => "other",
+		})
+	}
+}
+
+/// Count the descriptors open in the current process by kind, or the handles
+/// on Windows.
+///
+/// On FreeBSD `/dev/fd` only lists the standard streams unless fdescfs is
+/// mounted.
+pub fn fd_kinds() -> Result<BTreeMap<FdKind, u64>, Error> {
+	let mut kinds = BTreeMap::new();
+	for kind in sys::kinds().map_err(Error::FailedToCountFds)? {
+		*kinds.entry(kind).or_default() += 1;
+	}
+	Ok(kinds)
+}
+
+/// Count the descriptors open in the current process by class, telling apart
+/// socket families and the Linux anonymous descriptors.
+///
+/// On Windows handles are only classified by [kind](FdKind), socket families
+/// are unknown.
+pub fn fd_histogram() -> Result<BTreeMap<FdClass, u64>, Error> {
+	let mut histogram = BTreeMap::new();
+	for class in sys::classes().map_err(Error::FailedToCountFds)? {
+		*histogram.entry(class).or_default() += 1;
+	}
+	Ok(histogram)
+}
+
+#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+mod sys {
+	use super::{FdClass, FdKind, SocketFamily};
+	use std::io;
+
+	pub fn kinds() -> io::Result<Vec<FdKind>> {
+		Ok(classes()?.into_iter().map(FdClass::kind).collect())
+	}
+
+	pub fn classes() -> io::Result<Vec<FdClass>> {
+		#[cfg(target_os = "linux")]
+		let dir = "/proc/self/fd";
+		#[cfg(not(target_os = "linux"))]
+		let dir = "/dev/fd";
+
+		let fds: Vec<libc::c_int> = std::fs::read_dir(dir)?
+			.filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+			.collect();
+		let kqueues = kqueues();
+		// The listing's own descriptor is closed by now and fails fstat
+		Ok(fds
+			.into_iter()
+			.filter_map(|fd| if kqueues.contains(&fd) { Some(FdClass::Kqueue) } else { class(fd) })
+			.collect())
+	}
+
+	fn class(fd: libc::c_int) -> Option<FdClass> {
+		let mut stat = unsafe { std::mem::zeroed::<libc::stat>() };
+		if unsafe { libc::fstat(fd, &mut stat) } != 0 {
+			return None;
+		}
+
+		// Anonymous inodes have no file type, only their link tells them apart
+		#[cfg(target_os = "linux")]
+		let target = std::fs::read_link(format!("/proc/self/fd/{fd}")).unwrap_or_default();
+		#[cfg(target_os = "linux")]
+		match target.to_str().and_then(|target| target.strip_prefix("anon_inode:")) {
+			Some("[eventfd]") => return Some(FdClass::EventFd),
+			Some("[eventpoll]") => return Some(FdClass::Epoll),
+			Some("[timerfd]") => return Some(FdClass::TimerFd),
+			Some("[signalfd]") => return Some(FdClass::SignalFd),
+			Some("inotify") => return Some(FdClass::Inotify),
+			_ => {}
+		}
+
+		#[cfg(target_os = "linux")]
+		let memfd = target.as_os_str().as_encoded_bytes().starts_with(b"/memfd:");
+		Some(match stat.st_mode & libc::S_IFMT {
+			#[cfg(target_os = "linux")]
+			libc::S_IFREG if memfd => FdClass::MemFd,
+			libc::S_IFREG => FdClass::File,
+			libc::S_IFCHR | libc::S_IFBLK => FdClass::Device,
+			libc::S_IFDIR => FdClass::Directory,
+			libc::S_IFSOCK => FdClass::Socket(socket_family(fd)),
+			libc::S_IFIFO => FdClass::Pipe,
+			_ => FdClass::Other,
+		})
+	}
+
+	fn socket_family(fd: libc::c_int) -> SocketFamily {
+		let mut address = unsafe { std::mem::zeroed::<libc::sockaddr_storage>() };
+		let mut len = std::mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+		if unsafe {
+			libc::getsockname(fd, (&mut address as *mut libc::sockaddr_storage).cast(), &mut len)
+		} != 0
+		{
+			return SocketFamily::Other;
+		}
+
+		match libc::c_int::from(address.ss_family) {
+			libc::AF_UNIX => SocketFamily::Unix,
+			libc::AF_INET => SocketFamily::Inet,
+			libc::AF_INET6 => SocketFamily::Inet6,
+			#[cfg(target_os = "linux")]
+			libc::AF_NETLINK => SocketFamily::Netlink,
+			_ => SocketFamily::Other,
+		}
+	}
+
+	/// Descriptors that are kqueues, which fstat reports as other types
+	#[cfg(target_os = "macos")]
+	fn kqueues() -> Vec<libc::c_int> {
+		let pid = std::process::id() as libc::c_int;
+		let size =
+			unsafe { libc::proc_pidinfo(pid, libc::PROC_PIDLISTFDS, 0, std::ptr::null_mut(), 0) };
+		let Ok(capacity) = usize::try_from(size) else { return Vec::new() };
+		// Leave room for descriptors opened in the meantime
+		let mut fds = Vec::<libc::proc_fdinfo>::with_capacity(
+			capacity / std::mem::size_of::<libc::proc_fdinfo>() + 16,
+		);
+		let size = unsafe {
+			libc::proc_pidinfo(
+				pid,
+				libc::PROC_PIDLISTFDS,
+				0,
+				fds.as_mut_ptr().cast(),
+				(fds.capacity() * std::mem::size_of::<libc::proc_fdinfo>()) as libc::c_int,
+			)
+		};
+		let Ok(size) = usize::try_from(size) else { return Vec::new() };
+		unsafe { fds.set_len(size / std::mem::size_of::<libc::proc_fdinfo>()) };
+		fds.iter()
+			.filter(|fd| fd.proc_fdtype == libc::PROX_FDTYPE_KQUEUE as u32)
+			.map(|fd| fd.proc_fd)
+			.collect()
+	}
+
+	/// Descriptors that are kqueues, from the `kinfo_file` records of the
+	/// process
+	#[cfg(target_os = "freebsd")]
+	fn kqueues() -> Vec<libc::c_int> {
+		let mib =
+			[libc::CTL_KERN, libc::KERN_PROC, libc::KERN_PROC_FILEDESC, unsafe { libc::getpid() }];
+		let mut len = 0;
+		let sysctl = |buffer: *mut libc::c_void, len: &mut usize| unsafe {
+			libc::sysctl(mib.as_ptr(), mib.len() as libc::c_uint, buffer, len, std::ptr::null(), 0)
+		};
+		if sysctl(std::ptr::null_mut(), &mut len) != 0 {
+			return Vec::new();
+		}
+		// Leave room for descriptors opened in the meantime
+		len = len * 4 / 3;
+		let mut buffer = vec![0u8; len];
+		if sysctl(buffer.as_mut_ptr().cast(), &mut len) != 0 {
+			return Vec::new();
+		}
+
+		// Records are packed, each starting with its size, type and descriptor
+		let field = |offset: usize| -> Option<libc::c_int> {
+			let bytes = buffer.get(offset..offset + 4)?;
+			Some(libc::c_int::from_ne_bytes(bytes.try_into().ok()?))
+		};
+		let mut kqueues = Vec::new();
+		let mut offset = 0;
+		while offset < len {
+			let (Some(size), Some(kind), Some(fd)) =
+				(field(offset), field(offset + 4), field(offset + 8))
+			else {
+				break;
+			};
+			if kind == libc::KF_TYPE_KQUEUE {
+				kqueues.push(fd);
+			}
+			match usize::try_from(size) {
+				Ok(size) if size > 0 => offset += size,
+				_ => break,
+			}
+		}
+		kqueues
+	}
+
+	#[cfg(not(any(target_os = "macos", target_os = "freebsd")))]
+	fn kqueues() -> Vec<libc::c_int> {
+		Vec::new()
+	}
+}
+
+#[cfg(windows)]
+mod sys {
+	//! Handles are listed with `NtQueryInformationProcess`, their object type
+	//! read with `NtQueryObject`, and file handles told apart with
+	//! `getsockopt` and `GetFileType`.
+
+	use super::{FdClass, FdKind};
+	use std::ffi::c_void;
+	use std::io;
+
+	type Handle = *mut c_void;
+
+	const PROCESS_HANDLE_INFORMATION: u32 = 51;
+	const OBJECT_TYPE_INFORMATION: u32 = 2;
+	const STATUS_INFO_LENGTH_MISMATCH: i32 = 0xC000_0004_u32 as i32;
+	const FILE_TYPE_DISK: u32 = 1;
+	const FILE_TYPE_CHAR: u32 = 2;
+	const FILE_TYPE_PIPE: u32 = 3;
+	const SOL_SOCKET: i32 = 0xffff;
+	const SO_TYPE: i32 = 0x1008;
+
+	#[repr(C)]
+	struct HandleSnapshot {
+		number_of_handles: usize,
+		reserved: usize,
+		handles: [HandleEntry; 0],
+	}
+
+	#[repr(C)]
+	struct HandleEntry {
+		handle_value: Handle,
+		handle_count: usize,
+		pointer_count: usize,
+		granted_access: u32,
+		object_type_index: u32,
+		handle_attributes: u32,
+		reserved: u32,
+	}
+
+	/// Start of `PUBLIC_OBJECT_TYPE_INFORMATION`
+	#[repr(C)]
+	struct UnicodeString {
+		length: u16,
+		maximum_length: u16,
+		buffer: *const u16,
+	}
+
+	#[link(name = "ntdll")]
+	extern "system" {
+		fn NtQueryInformationProcess(
+			process: Handle,
+			class: u32,
+			info: *mut c_void,
+			length: u32,
+			return_length: *mut u32,
+		) -> i32;
+		fn NtQueryObject(
+			handle: Handle,
+			class: u32,
+			info: *mut c_void,
+			length: u32,
+			return_length: *mut u32,
+		) -> i32;
+	}
+
+	#[link(name = "kernel32")]
+	extern "system" {
+		fn GetCurrentProcess() -> Handle;
+		fn GetFileType(file: Handle) -> u32;
+	}
+
+	#[link(name = "ws2_32")]
+	extern "system" {
+		fn getsockopt(socket: usize, level: i32, name: i32, value: *mut u8, len: *mut i32) -> i32;
+	}
+
+	pub fn kinds() -> io::Result<Vec<FdKind>> {
+		let snapshot = handle_snapshot()?;
+		// Object types are few, look each one up once
+		let mut types: Vec<(u32, Option<String>)> = Vec::new();
+		let mut kinds = Vec::new();
+		for entry in handles(&snapshot) {
+			let type_name = match types.iter().find(|(index, _)| *index == entry.object_type_index)
+			{
+				Some((_, name)) => name.clone(),
+				None => {
+					let name = object_type(entry.handle_value);
+					types.push((entry.object_type_index, name.clone()));
+					name
+				}
+			};
+			kinds.push(match type_name.as_deref() {
+				Some("File") => file_kind(entry.handle_value),
+				Some("Event") => FdKind::Event,
+				_ => FdKind::Other,
+			});
+		}
+		Ok(kinds)
+	}
+
+	/// Buffer of 8-byte words holding a `PROCESS_HANDLE_SNAPSHOT_INFORMATION`
+	fn handle_snapshot() -> io::Result<Vec<u64>> {
+		let mut buffer = vec![0u64; 1024];
+		loop {
+			let length = u32::try_from(buffer.len() * 8).unwrap_or(u32::MAX);
+			let mut needed = 0;
+			let status = unsafe {
+				NtQueryInformationProcess(
+					GetCurrentProcess(),
+					PROCESS_HANDLE_INFORMATION,
+					buffer.as_mut_ptr().cast(),
+					length,
+					&mut needed,
+				)
+			};
+			match status {
+				0 => return Ok(buffer),
+				STATUS_INFO_LENGTH_MISMATCH => {
+					// Leave room for handles opened in the meantime
+					buffer.resize((needed as usize / 8 + 1) * 2, 0);
+				}
+				status => return Err(io::Error::other(format!("NTSTATUS {status:#x}"))),
+			}
+		}
+	}
+
+	fn handles(snapshot: &[u64]) -> &[HandleEntry] {
+		let header = snapshot.as_ptr().cast::<HandleSnapshot>();
+		unsafe {
+			let entries = std::ptr::addr_of!((*header).handles).cast::<HandleEntry>();
+			std::slice::from_raw_parts(entries, (*header).number_of_handles)
+		}
+	}
+
+	fn object_type(handle: Handle) -> Option<String> {
+		let mut buffer = vec![0u64; 128];
+		let status = unsafe {
+			NtQueryObject(
+				handle,
+				OBJECT_TYPE_INFORMATION,
+				buffer.as_mut_ptr().cast(),
+				(buffer.len() * 8) as u32,
+				std::ptr::null_mut(),
+			)
+		};
+		if status != 0 {
+			return None;
+		}
+
+		let name = unsafe { &*buffer.as_ptr().cast::<UnicodeString>() };
+		let name = unsafe { std::slice::from_raw_parts(name.buffer, usize::from(name.length) / 2) };
+		Some(String::from_utf16_lossy(name))
+	}
+
+	pub fn classes() -> io::Result<Vec<FdClass>> {
+		Ok(kinds()?.into_iter().map(FdClass::from_kind).collect())
+	}
+
+	fn file_kind(handle: Handle) -> FdKind {
+		// Sockets are files of the AFD driver, only getsockopt tells them apart
+		let mut socket_type = [0u8; 4];
+		let mut len = socket_type.len() as i32;
+		if unsafe {
+			getsockopt(handle as usize, SOL_SOCKET, SO_TYPE, socket_type.as_mut_ptr(), &mut len)
+		} == 0
+		{
+			return FdKind::Socket;
+		}
+
+		match unsafe { GetFileType(handle) } {
+			FILE_TYPE_DISK | FILE_TYPE_CHAR => FdKind::File,
+			FILE_TYPE_PIPE => FdKind::Pipe,
+			_ => FdKind::Other,
+		}
+	}
+}
+
+#[cfg(not(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd", windows)))]
+mod sys {
+	use super::{FdClass, FdKind};
+	use std::io;
+
+	pub fn kinds() -> io::Result<Vec<FdKind>> {
+		Err(io::ErrorKind::Unsupported.into())
+	}
+
+	pub fn classes() -> io::Result<Vec<FdClass>> {
+		Err(io::ErrorKind::Unsupported.into())
+	}
+}
@@ -12,7 +12,206 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod assertion;
+#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+mod atfork;
+mod audit;
+pub mod backend;
+#[cfg(unix)]
+mod ballast;
+mod builder;
+#[cfg(all(
+	feature = "chaos",
+	any(target_vendor = "apple", target_os = "linux", target_os = "freebsd")
+))]
+pub mod chaos;
+mod diagnose;
+mod diagnostics;
+mod docker;
+mod errno;
+mod exhaustion;
+#[cfg(target_os = "linux")]
+mod fd_age;
+#[cfg(target_os = "linux")]
+mod fdinfo;
+#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+mod fsize;
+mod growth;
+mod headroom;
+mod health;
+#[cfg(all(feature = "helper", target_os = "linux"))]
+mod helper;
+mod infallible;
+#[cfg(all(feature = "journald", target_os = "linux"))]
+mod journald;
+mod json;
+#[cfg(feature = "json-log")]
+mod json_log;
+mod kinds;
+mod limit;
+mod listener;
+#[cfg(feature = "tower")]
+mod load_shed;
+mod manager;
+mod method;
+#[cfg(all(
+	feature = "nix",
+	any(target_vendor = "apple", target_os = "linux", target_os = "freebsd")
+))]
+mod nix_interop;
+mod observer;
+#[cfg(feature = "otel")]
+pub mod otel;
+mod pam;
+mod platform;
+mod policy;
+mod pool;
+mod preflight;
+#[cfg(all(feature = "preload", target_os = "linux"))]
+mod preload;
+mod preset;
+#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+mod privdrop;
+mod privilege;
+mod proc_limits;
+#[cfg(target_os = "linux")]
+mod process;
+mod rate_limit;
+#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+pub mod raw;
+mod raw_error;
+mod reload;
+mod remediation;
+mod resource;
+#[cfg(all(feature = "rlimit", unix))]
+mod rlimit_interop;
+mod setter;
+#[cfg(any(test, feature = "simulated"))]
+pub mod simulated;
+mod snapshot;
+mod sources;
+mod ssh;
+mod state;
+mod strategy;
+#[cfg(all(feature = "raw-syscalls", target_os = "linux"))]
+mod syscall;
+#[cfg(all(feature = "systemd", target_os = "linux"))]
+mod systemd;
+mod systemd_conf;
+mod userns;
+mod validator;
+mod watcher;
+#[cfg(all(feature = "windows-service", windows))]
+mod windows_service;
+
+#[doc(hidden)]
+pub use assertion::__IntoLimit;
+pub use assertion::{check_limits, check_limits_with, Comparison, LimitAssertion, Side};
+pub use audit::{audit_log, set_audit_capacity, with_audit_reason, AuditEntry};
+#[cfg(unix)]
+pub use ballast::FdBallast;
+pub use builder::Builder;
+pub use diagnose::{diagnose, diagnose_with, Diagnosis};
+pub use diagnostics::{
+	install_emfile_diagnostics, report_fd_exhaustion, uninstall_emfile_diagnostics, Snapshot,
+};
+pub use docker::{container_limits, container_limits_with, ContainerLimits};
+pub use errno::errno_name;
+pub use exhaustion::{is_fd_exhaustion, Attempt, AutoRaise};
+#[cfg(target_os = "linux")]
+pub use fd_age::FdAges;
+#[cfg(target_os = "linux")]
+pub use fdinfo::{fdinfo, fdinfo_for_pid, FdDetail, FdInfo};
+#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+pub use fsize::{limit_file_size, limit_file_size_with, FileTooLarge};
+pub use growth::{Growth, GrowthWatch};
+#[cfg(feature = "tokio")]
+pub use headroom::watch_headroom;
+pub use headroom::{
+	fd_headroom, fd_headroom_with, release_headroom, reserve_headroom, reserved_headroom,
+	try_reserve_fds, try_reserve_fds_with, FdReservation,
+};
+pub use health::{health, health_with, Health, State, Thresholds};
+#[cfg(all(feature = "helper", target_os = "linux"))]
+pub use helper::{helper_command, helper_command_at, DEFAULT_HELPER_PATH, HELPER_RESOURCES};
+pub use infallible::raise_fd_limit_infallible;
+#[cfg(all(feature = "journald", target_os = "linux"))]
+pub use journald::{journal_growth, journal_health, journal_outcome, journal_send};
+// For the command line tool, not part of the API
+#[doc(hidden)]
+pub use json::json_string;
+#[cfg(feature = "json-log")]
+pub use json_log::{log_raises_as_json, stop_json_log};
+pub use kinds::{fd_histogram, fd_kinds, FdClass, FdKind, SocketFamily};
+pub use limit::{Limit, LimitEntry};
+#[cfg(feature = "tokio")]
+pub use listener::TokioListener;
+pub use listener::{Listener, ListenerEvent};
+#[cfg(feature = "tower")]
+pub use load_shed::{FdLoadShed, FdLoadShedFuture, FdLoadShedLayer, Overloaded};
+pub use manager::LimitsManager;
+pub use method::Method;
+pub use observer::{on_raise, remove_on_raise, ObserverId};
+pub use pam::{pam_limits, pam_limits_enabled, PamLimit, PamLimitKind};
+pub use platform::{platform_info, PlatformInfo, WslVersion};
+pub use policy::{
+	apply_policy, apply_policy_with, raise_all_recommended, raise_all_recommended_with, Policy,
+	Report, Rule,
+};
+pub use pool::{
+	connection_budget, connection_budget_with, suggest_pool_size, suggest_pool_size_with,
+	ConnectionCost, PoolKind,
+};
+pub use preflight::{preflight, sysctl_command, Finding, Requirement, Setting, Severity, Summary};
+#[cfg(all(feature = "preload", target_os = "linux"))]
+pub use preload::{spawn_with_preload, PreloadChild};
+pub use preset::{apply_preset, apply_preset_with, preset_preflight, Preset};
+#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+pub use privdrop::{drop_privileges, raise_across_privilege_drop, DropEntry, DropReport};
+pub use privilege::can_raise_hard_limit;
+pub use proc_limits::{Discrepancy, ProcLimits};
+#[cfg(target_os = "linux")]
+pub use process::{
+	all_process_limits, apply_policy_to_cgroup, apply_policy_to_process_group, fd_usage_for_pid,
+	limits_for_pid, open_fds_for_pid, scan_processes, set_limits_for_pid, OpenFd, Prlimit,
+	ProcessLimits, ProcessReport, ProcessUsage,
+};
+pub use rate_limit::RateLimit;
+pub use raw_error::RawError;
+pub use reload::{diff_policies, Change, PolicyFile, Reload};
+pub use remediation::{
+	is_running_under_launchd, launchd_session, remediation, Environment, LaunchdSession,
+	Remediation,
+};
+pub use resource::Resource;
+pub use setter::{
+	set_hard_fd_limit, set_hard_limit, set_hard_limit_with, set_soft_fd_limit, set_soft_limit,
+	set_soft_limit_with, HardLimitLowering,
+};
+pub use snapshot::{Direction, LimitChange, LimitsSnapshot, SnapshotDiff, ValueChange};
+pub use sources::{Origin, PolicySources, ResolvedPolicy, Source};
+pub use ssh::{ssh_session, SshSession};
+pub use state::{
+	apply_policy_with_state, apply_policy_with_state_with, LimitState, Regression, StateEntry,
+};
+pub use strategy::Strategy;
+#[cfg(all(feature = "systemd", target_os = "linux"))]
+pub use systemd::{notify_outcome, sd_notify};
+pub use systemd_conf::{
+	systemd_default_limit, systemd_default_limits, SystemdDefault, SystemdManager,
+};
+pub use userns::{user_namespace, IdMapping, UserNamespace};
+pub use validator::validator_preflight;
+pub use watcher::{ensure_watcher_limits, WatcherReport};
+#[cfg(all(feature = "windows-service", windows))]
+pub use windows_service::{setup_windows_service, ServiceSetup};
+
+use backend::{Rlimit, RlimitOps};
+use errno::WithErrno;
+use std::io;
+
 /// Outcome of raising file descriptor resource limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Outcome {
 	/// Limit was raised successfully
 	LimitRaised {
@@ -29,14 +228,19 @@ pub enum Outcome {
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
 	/// Failed to call sysctl to get max supported value configured in sysctl
-	#[error("Failed to call sysctl to get max supported value configured in sysctl: {0}")]
-	#[cfg(any(target_os = "macos", target_os = "ios"))]
+	#[error(
+		"Failed to call sysctl to get max supported value configured in sysctl: {}",
+		WithErrno(.0)
+	)]
 	FailedToCallSysctl(std::io::Error),
 	/// Failed to get current limit
-	#[error("Failed to get current limit: {0}")]
+	#[error("Failed to get current limit: {}", WithErrno(.0))]
 	FailedToGetLimit(std::io::Error),
+	/// Resource is not available on this platform
+	#[error("{0} limit is not available on this platform")]
+	ResourceNotAvailable(Resource),
 	/// Failed to set new limit
-	#[error("Failed to set new limit ({from}->{to}): {error}")]
+	#[error("Failed to set new limit ({from}->{to}): {}", WithErrno(.error))]
 	FailedToSetLimit {
 		/// Current limit
 		from: u64,
@@ -45,6 +249,157 @@ pub enum Error {
 		/// Low level OS error
 		error: std::io::Error,
 	},
+	/// Requested limit is above the maximum the soft limit can be raised to
+	#[error("Requested limit {requested} exceeds the maximum available {available}")]
+	LimitUnavailable {
+		/// Requested limit
+		requested: u64,
+		/// Smaller of the kernel limit and the hard limit
+		available: u64,
+	},
+	/// Failed to count open file descriptors
+	#[error("Failed to count open file descriptors: {}", WithErrno(.0))]
+	FailedToCountFds(std::io::Error),
+	/// Failed to read from procfs
+	#[error("Failed to read from procfs: {}", WithErrno(.0))]
+	FailedToReadProc(std::io::Error),
+	/// Failed to open file descriptors
+	#[error("Failed to open file descriptors: {}", WithErrno(.0))]
+	FailedToOpenFds(std::io::Error),
+	/// Failed to read the policy file
+	#[error("Failed to read the policy file: {}", WithErrno(.0))]
+	FailedToReadPolicy(std::io::Error),
+	/// Failed to read or write the state file
+	#[error("Failed to read or write the state file: {}", WithErrno(.0))]
+	FailedToPersistState(std::io::Error),
+	/// Failed to drop privileges
+	#[error("Failed to drop privileges: {}", WithErrno(.0))]
+	FailedToDropPrivileges(std::io::Error),
+	/// Failed to register the fork handler
+	#[error("Failed to register the fork handler: {}", WithErrno(.0))]
+	FailedToRegisterAtfork(std::io::Error),
+	/// Fraction of the hard limit is not above 0 and at most 1
+	#[error("Invalid fraction of the hard limit: {0}")]
+	InvalidFraction(f64),
+	/// Value could not be parsed
+	#[error("Invalid value: {0}")]
+	InvalidValue(String),
+	/// Soft limit ended up below the policy's minimum
+	#[error("{resource} limit {limit} is below the required minimum {minimum}")]
+	BelowMinimum {
+		/// Resource whose limit is too low
+		resource: Resource,
+		/// Required minimum
+		minimum: u64,
+		/// Soft limit achieved
+		limit: u64,
+	},
+	/// Limit doesn't satisfy an assertion of [`check_limits`]
+	#[error("Assertion {assertion} failed with {actual}, {remediation}")]
+	AssertionFailed {
+		/// Assertion that failed
+		assertion: LimitAssertion,
+		/// Limit found
+		actual: Limit,
+		/// Steps to fix the limit
+		remediation: String,
+	},
+	/// Hard limit would be lowered without
+	/// [`HardLimitLowering::Allow`](crate::HardLimitLowering::Allow)
+	#[error(
+		"Refusing to lower the {resource} hard limit from {hard} to {requested}, which can't be \
+		 undone without privileges"
+	)]
+	WouldLowerHardLimit {
+		/// Resource whose hard limit would be lowered
+		resource: Resource,
+		/// Current hard limit
+		hard: u64,
+		/// Hard limit requested
+		requested: u64,
+	},
+	/// Raising a hard limit was refused inside a user namespace, where the hard
+	/// limits set outside of it are a ceiling even for root
+	#[error(
+		"Can't raise the {resource} hard limit from {ceiling} to {requested} inside a user \
+		 namespace, it has to be raised outside of it: {}",
+		WithErrno(.error)
+	)]
+	HardLimitCeilingInUserNamespace {
+		/// Resource whose hard limit was to be raised
+		resource: Resource,
+		/// Current hard limit, the most the namespace allows
+		ceiling: u64,
+		/// Hard limit requested
+		requested: u64,
+		/// Error returned by the OS
+		error: std::io::Error,
+	},
+	/// Not enough file descriptors left to reserve
+	#[error("Can't reserve {requested} file descriptors, only {available} left")]
+	InsufficientHeadroom {
+		/// Descriptors to reserve
+		requested: u64,
+		/// Descriptors left, minus the ones already reserved
+		available: u64,
+	},
+}
+
+impl Error {
+	/// Operation that failed
+	pub fn method(&self) -> Method {
+		match self {
+			Error::FailedToCallSysctl(_) => Method::Sysctl,
+			Error::FailedToGetLimit(_) | Error::ResourceNotAvailable(_) => Method::GetRLimit,
+			Error::FailedToSetLimit { .. } | Error::HardLimitCeilingInUserNamespace { .. } => {
+				Method::SetRLimit
+			}
+			Error::FailedToCountFds(_) => Method::CountFds,
+			Error::FailedToReadProc(_) => Method::ReadProc,
+			Error::FailedToOpenFds(_) => Method::OpenFds,
+			Error::FailedToReadPolicy(_) => Method::ReadPolicy,
+			Error::FailedToPersistState(_) => Method::PersistState,
+			Error::FailedToDropPrivileges(_) => Method::DropPrivileges,
+			Error::FailedToRegisterAtfork(_) => Method::PthreadAtfork,
+			Error::LimitUnavailable { .. }
+			| Error::InvalidFraction(_)
+			| Error::InvalidValue(_)
+			| Error::BelowMinimum { .. }
+			| Error::AssertionFailed { .. }
+			| Error::WouldLowerHardLimit { .. }
+			| Error::InsufficientHeadroom { .. } => Method::Strategy,
+		}
+	}
+
+	/// Low level OS error, if the failure comes from the OS
+	pub fn io_error(&self) -> Option<&std::io::Error> {
+		match self {
+			Error::FailedToCallSysctl(error)
+			| Error::FailedToGetLimit(error)
+			| Error::FailedToSetLimit { error, .. }
+			| Error::HardLimitCeilingInUserNamespace { error, .. }
+			| Error::FailedToCountFds(error)
+			| Error::FailedToReadProc(error)
+			| Error::FailedToOpenFds(error)
+			| Error::FailedToReadPolicy(error)
+			| Error::FailedToPersistState(error)
+			| Error::FailedToDropPrivileges(error)
+			| Error::FailedToRegisterAtfork(error) => Some(error),
+			Error::ResourceNotAvailable(_)
+			| Error::LimitUnavailable { .. }
+			| Error::InvalidFraction(_)
+			| Error::InvalidValue(_)
+			| Error::BelowMinimum { .. }
+			| Error::AssertionFailed { .. }
+			| Error::WouldLowerHardLimit { .. }
+			| Error::InsufficientHeadroom { .. } => None,
+		}
+	}
+
+	/// Symbolic name of the OS error code, e.g. `EPERM`, see [`errno_name`]
+	pub fn errno_name(&self) -> Option<&'static str> {
+		self.io_error()?.raw_os_error().and_then(errno_name)
+	}
 }
 
 /// Raise the soft open file descriptor resource limit to the smaller of the
@@ -54,91 +409,303 @@ pub enum Error {
 /// defaults the rlimit maxfiles to 256/unlimited. The default soft limit of 256
 /// ends up being far too low for our multithreaded scheduler testing, depending
 /// on the number of cores available.
-#[cfg(any(target_os = "macos", target_os = "ios"))]
-#[allow(clippy::useless_conversion, non_camel_case_types)]
+#[cfg(target_vendor = "apple")]
 pub fn raise_fd_limit() -> Result<Outcome, Error> {
-	use std::cmp;
-	use std::io;
-	use std::mem::size_of_val;
-	use std::ptr::null_mut;
-
-	unsafe {
-		static CTL_KERN: libc::c_int = 1;
-		static KERN_MAXFILESPERPROC: libc::c_int = 29;
-
-		// The strategy here is to fetch the current resource limits, read the
-		// kern.maxfilesperproc sysctl value, and bump the soft resource limit for
-		// maxfiles up to the sysctl value.
-
-		// Fetch the kern.maxfilesperproc value
-		let mut mib: [libc::c_int; 2] = [CTL_KERN, KERN_MAXFILESPERPROC];
-		let mut maxfiles: libc::c_int = 0;
-		let mut size: libc::size_t = size_of_val(&maxfiles) as libc::size_t;
-		if libc::sysctl(&mut mib[0], 2, &mut maxfiles as *mut _ as *mut _, &mut size, null_mut(), 0)
-			!= 0
-		{
-			return Err(Error::FailedToCallSysctl(io::Error::last_os_error()));
-		}
+	raise_fd_limit_with(&backend::System)
+}
 
-		// Fetch the current resource limits
-		let mut rlim = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
-		if libc::getrlimit(libc::RLIMIT_NOFILE, &mut rlim) != 0 {
-			return Err(Error::FailedToGetLimit(io::Error::last_os_error()));
-		}
+/// Raise the soft open file descriptor resource limit to the smaller of the
+/// kern.maxfilesperproc sysctl value and the hard resource limit.
+#[cfg(target_os = "freebsd")]
+pub fn raise_fd_limit() -> Result<Outcome, Error> {
+	raise_fd_limit_with(&backend::System)
+}
 
-		let old_value = rlim.rlim_cur;
+/// Raise the soft open file descriptor resource limit to the hard resource
+/// limit.
+#[cfg(target_os = "linux")]
+pub fn raise_fd_limit() -> Result<Outcome, Error> {
+	raise_fd_limit_with(&backend::System)
+}
 
-		// Bump the soft limit to the smaller of kern.maxfilesperproc and the hard
-		// limit
-		rlim.rlim_cur = cmp::min(maxfiles as libc::rlim_t, rlim.rlim_max);
+/// Raise the soft limit to the smaller of the kernel limit and the hard limit
+/// using the given backend.
+pub fn raise_fd_limit_with<B: RlimitOps>(backend: &B) -> Result<Outcome, Error> {
+	raise_and_notify(backend, Resource::NoFile, &Strategy::MaxAvailable)
+		.map(|raised| raised.audit(backend))
+}
 
-		// Set our newly-increased resource limit
-		if libc::setrlimit(libc::RLIMIT_NOFILE, &rlim) != 0 {
-			return Err(Error::FailedToSetLimit {
-				from: old_value.into(),
-				to: rlim.rlim_cur.into(),
-				error: io::Error::last_os_error(),
-			});
-		}
+/// Limits of a resource before and after a raise
+#[derive(Debug, Clone, Copy)]
+struct Raised {
+	resource: Resource,
+	before: Rlimit,
+	after: Rlimit,
+}
+
+impl Raised {
+	fn outcome(&self) -> Outcome {
+		Outcome::LimitRaised { from: self.before.soft, to: self.after.soft }
+	}
 
-		Ok(Outcome::LimitRaised { from: old_value.into(), to: rlim.rlim_cur.into() })
+	/// Record the change in the audit log, for the public entry points
+	fn audit<B: RlimitOps>(self, backend: &B) -> Outcome {
+		audit::record(backend.pid(), self.resource, self.before, self.after);
+		self.outcome()
 	}
 }
 
-/// Raise the soft open file descriptor resource limit to the hard resource
-/// limit.
-#[cfg(target_os = "linux")]
-#[allow(clippy::useless_conversion, non_camel_case_types)]
-pub fn raise_fd_limit() -> Result<Outcome, Error> {
-	use std::io;
+/// Raise the limit and fire the [`on_raise`] callbacks when it's the limit of
+/// the current process, leaving the audit log to the caller
+fn raise_and_notify<B: RlimitOps>(
+	backend: &B,
+	resource: Resource,
+	strategy: &Strategy,
+) -> Result<Raised, Error> {
+	#[cfg(feature = "json-log")]
+	let started = std::time::Instant::now();
+	let result = raise(backend, resource, strategy);
+	#[cfg(feature = "json-log")]
+	{
+		let ceiling = match resource {
+			Resource::NoFile => backend.sysctl_maxfiles().ok().flatten(),
+			_ => None,
+		};
+		json_log::log(resource, result.as_ref().map(Raised::outcome), ceiling, started.elapsed());
+	}
+	let raised = result?;
+	if backend.pid().is_none() {
+		observer::notify(resource, &raised.outcome());
+	}
+	Ok(raised)
+}
 
-	unsafe {
-		// Fetch the current resource limits
-		let mut rlim = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
-		if libc::getrlimit(libc::RLIMIT_NOFILE, &mut rlim) != 0 {
-			return Err(Error::FailedToGetLimit(io::Error::last_os_error()));
-		}
+fn raise<B: RlimitOps>(
+	backend: &B,
+	resource: Resource,
+	strategy: &Strategy,
+) -> Result<Raised, Error> {
+	let (before, after) = infallible::raise_with(backend, resource, strategy)?;
+	Ok(Raised { resource, before, after })
+}
 
-		let old_value = rlim.rlim_cur;
+/// Fetch the soft and hard limits of the resource, failing with
+/// [`Error::ResourceNotAvailable`] if the platform doesn't have it
+pub fn get_limit(resource: Resource) -> Result<Rlimit, Error> {
+	get_limit_with(&backend::System, resource)
+}
 
-		// Set soft limit to hard imit
-		rlim.rlim_cur = rlim.rlim_max;
+/// Same as [`get_limit`] using the given backend
+pub fn get_limit_with<B: RlimitOps>(backend: &B, resource: Resource) -> Result<Rlimit, Error> {
+	backend.getrlimit(resource).map_err(|error| match error.kind() {
+		io::ErrorKind::Unsupported => Error::ResourceNotAvailable(resource),
+		_ => Error::FailedToGetLimit(error),
+	})
+}
 
-		// Set our newly-increased resource limit
-		if libc::setrlimit(libc::RLIMIT_NOFILE, &rlim) != 0 {
-			return Err(Error::FailedToSetLimit {
-				from: old_value.into(),
-				to: rlim.rlim_cur.into(),
-				error: io::Error::last_os_error(),
-			});
-		}
+/// Fetch the limits of every [resource](Resource::ALL), the ones the platform
+/// doesn't have failing with [`Error::ResourceNotAvailable`]
+pub fn get_all_limits() -> Vec<(Resource, Result<Rlimit, Error>)> {
+	get_all_limits_with(&backend::System)
+}
+
+/// Same as [`get_all_limits`] using the given backend
+pub fn get_all_limits_with<B: RlimitOps>(backend: &B) -> Vec<(Resource, Result<Rlimit, Error>)> {
+	Resource::ALL.iter().map(|&resource| (resource, get_limit_with(backend, resource))).collect()
+}
+
+/// Fetch the soft and hard limits of every resource the platform has, in the
+/// order of [`Resource::ALL`]
+pub fn supported_limits() -> Result<Vec<LimitEntry>, Error> {
+	supported_limits_with(&backend::System)
+}
+
+/// Same as [`supported_limits`] using the given backend
+pub fn supported_limits_with<B: RlimitOps>(backend: &B) -> Result<Vec<LimitEntry>, Error> {
+	get_all_limits_with(backend)
+		.into_iter()
+		.filter_map(|(resource, rlim)| match rlim {
+			Ok(rlim) => {
+				Some(Ok(LimitEntry { resource, soft: rlim.soft_limit(), hard: rlim.hard_limit() }))
+			}
+			Err(Error::ResourceNotAvailable(_)) => None,
+			Err(error) => Some(Err(error)),
+		})
+		.collect()
+}
+
+/// Fetch the per-process kernel ceiling of open files again.
+///
+/// The ceiling (`kern.maxfilesperproc`, `fs.nr_open` on Linux) is read once
+/// and cached, call this after changing it so later raises see the new value.
+pub fn refresh_kernel_ceiling() -> Result<Option<u64>, Error> {
+	backend::refresh_ceiling().map_err(Error::FailedToCallSysctl)
+}
 
-		Ok(Outcome::LimitRaised { from: old_value.into(), to: rlim.rlim_cur.into() })
+/// Max number of open files in the whole system (`kern.maxfiles`,
+/// `fs.file-max` on Linux), `None` if the platform has no such setting
+pub fn system_max_files() -> Result<Option<u64>, Error> {
+	system_max_files_with(&backend::System)
+}
+
+/// Same as [`system_max_files`] using the given backend
+pub fn system_max_files_with<B: RlimitOps>(backend: &B) -> Result<Option<u64>, Error> {
+	backend.system_maxfiles().map_err(Error::FailedToCallSysctl)
+}
+
+/// Max number of streams the C runtime can have open at once
+/// (`_getmaxstdio`) on Windows, which `fopen` and the CRT file descriptors of
+/// C libraries are subject to, `None` on other platforms
+pub fn max_stdio() -> Option<u64> {
+	#[cfg(windows)]
+	{
+		extern "C" {
+			fn _getmaxstdio() -> libc::c_int;
+		}
+		u64::try_from(unsafe { _getmaxstdio() }).ok()
 	}
+
+	#[cfg(not(windows))]
+	None
 }
 
+#[cfg(all(
+	feature = "deny-unsupported",
+	not(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))
+))]
+compile_error!(
+	"fdlimit can't raise limits on this platform and the deny-unsupported feature is enabled"
+);
+
 /// Does nothing on unsupported platform
-#[cfg(not(any(target_os = "macos", target_os = "ios", target_os = "linux")))]
+#[cfg(not(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd")))]
 pub fn raise_fd_limit() -> Result<Outcome, Error> {
 	Ok(Outcome::Unsupported)
 }
+
+/// Same as [`raise_fd_limit`] reporting failures as an allocation-free
+/// [`RawError`], without firing the [`on_raise`] callbacks or recording the
+/// change in the [audit log](audit_log)
+#[cfg(feature = "raw-error")]
+#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+pub fn raise_fd_limit_raw() -> Result<Outcome, RawError> {
+	raw_error::raise(&backend::System, Resource::NoFile, &Strategy::MaxAvailable)
+}
+
+/// Does nothing on unsupported platform
+#[cfg(feature = "raw-error")]
+#[cfg(not(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd")))]
+pub fn raise_fd_limit_raw() -> Result<Outcome, RawError> {
+	Ok(Outcome::Unsupported)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::simulated::Simulated;
+	use std::sync::atomic::{AtomicUsize, Ordering};
+	use std::sync::Arc;
+
+	#[test]
+	fn raise_fd_limit_with_raises_to_hard() {
+		let backend = Simulated::new(256, 4096);
+		let outcome = raise_fd_limit_with(&backend).unwrap();
+		assert_eq!(outcome, Outcome::LimitRaised { from: 256, to: 4096 });
+		assert_eq!(backend.limit(Resource::NoFile), Some(Rlimit { soft: 4096, hard: 4096 }));
+	}
+
+	#[test]
+	fn only_entry_points_are_audited() {
+		let audited = |reason: &str| {
+			audit_log().iter().filter(|entry| entry.reason.as_deref() == Some(reason)).count()
+		};
+
+		let backend = Simulated::new(256, 4096);
+		with_audit_reason("internal raise", || {
+			raise(&backend, Resource::NoFile, &Strategy::MaxAvailable)
+		})
+		.unwrap();
+		assert_eq!(audited("internal raise"), 0);
+
+		let backend = Simulated::new(256, 4096);
+		with_audit_reason("public raise", || raise_fd_limit_with(&backend)).unwrap();
+		assert_eq!(audited("public raise"), 1);
+	}
+
+	#[test]
+	fn other_processes_dont_fire_observers() {
+		let pid = u32::MAX - 1;
+		let fired = Arc::new(AtomicUsize::new(0));
+		let id = on_raise({
+			let fired = fired.clone();
+			// Other tests raise limits concurrently
+			move |_, outcome| {
+				if *outcome == (Outcome::LimitRaised { from: 257, to: 4099 }) {
+					fired.fetch_add(1, Ordering::Relaxed);
+				}
+			}
+		});
+		raise_fd_limit_with(&Simulated::new(257, 4099).with_pid(pid)).unwrap();
+		remove_on_raise(id);
+		assert_eq!(fired.load(Ordering::Relaxed), 0);
+		assert!(audit_log().iter().any(|entry| entry.pid == Some(pid)));
+	}
+
+	#[test]
+	fn raise_fd_limit_with_reports_setrlimit_eperm() {
+		let backend = Simulated::new(256, 4096).fail_setrlimit(libc::EPERM);
+		let error = raise_fd_limit_with(&backend).unwrap_err();
+		assert!(matches!(error, Error::FailedToSetLimit { from: 256, to: 4096, .. }));
+		assert_eq!(error.method(), Method::SetRLimit);
+		assert_eq!(error.io_error().and_then(io::Error::raw_os_error), Some(libc::EPERM));
+		assert_eq!(backend.limit(Resource::NoFile), Some(Rlimit { soft: 256, hard: 4096 }));
+	}
+
+	#[test]
+	fn raise_fd_limit_with_reports_sysctl_failure() {
+		let backend = Simulated::new(256, 4096).fail_sysctl(libc::EACCES);
+		let error = raise_fd_limit_with(&backend).unwrap_err();
+		assert!(matches!(error, Error::FailedToCallSysctl(_)));
+		assert_eq!(error.method(), Method::Sysctl);
+		assert_eq!(backend.limit(Resource::NoFile), Some(Rlimit { soft: 256, hard: 4096 }));
+	}
+
+	#[test]
+	fn raise_fd_limit_with_caps_at_kernel_ceiling() {
+		let backend = Simulated::new(256, 4096).with_maxfiles(1024);
+		let outcome = raise_fd_limit_with(&backend).unwrap();
+		assert_eq!(outcome, Outcome::LimitRaised { from: 256, to: 1024 });
+	}
+
+	#[test]
+	fn builder_raise_with_fails_when_hard_is_below_target() {
+		let backend = Simulated::new(256, 1024);
+		let error =
+			Builder::new().strategy(Strategy::AtLeast(4096)).raise_with(&backend).unwrap_err();
+		assert!(matches!(error, Error::LimitUnavailable { requested: 4096, available: 1024 }));
+		assert_eq!(error.method(), Method::Strategy);
+		assert_eq!(backend.limit(Resource::NoFile), Some(Rlimit { soft: 256, hard: 1024 }));
+	}
+
+	#[test]
+	fn builder_raise_with_reports_setrlimit_eperm() {
+		let backend = Simulated::new(256, 4096).fail_setrlimit(libc::EPERM);
+		let error =
+			Builder::new().strategy(Strategy::Exact(2048)).raise_with(&backend).unwrap_err();
+		assert!(matches!(error, Error::FailedToSetLimit { from: 256, to: 2048, .. }));
+	}
+
+	#[test]
+	fn builder_raise_with_reports_sysctl_failure() {
+		let backend = Simulated::new(256, 4096).fail_sysctl(libc::EACCES);
+		let error = Builder::new().raise_with(&backend).unwrap_err();
+		assert!(matches!(error, Error::FailedToCallSysctl(_)));
+	}
+
+	#[test]
+	fn builder_raise_with_reports_missing_resource() {
+		let backend = Simulated::new(256, 4096);
+		let error = Builder::new().resource(Resource::NProc).raise_with(&backend).unwrap_err();
+		assert!(matches!(error, Error::ResourceNotAvailable(Resource::NProc)));
+	}
+}
@@ -0,0 +1,200 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::backend::RLIM_INFINITY;
+use crate::{Error, Resource};
+use std::fmt;
+use std::str::FromStr;
+
+/// Value of a resource limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Limit {
+	/// Limited to the given value
+	Finite(u64),
+	/// Not limited
+	Unlimited,
+}
+
+impl Limit {
+	/// Interpret a raw limit value, as found in [`Rlimit`](crate::backend::Rlimit)
+	pub fn from_raw(raw: u64) -> Self {
+		if raw == RLIM_INFINITY {
+			Limit::Unlimited
+		} else {
+			Limit::Finite(raw)
+		}
+	}
+
+	/// Limit written as a number, the platform's raw infinity and `u64::MAX`
+	/// meaning unlimited
+	fn from_number(value: u64) -> Self {
+		match value {
+			u64::MAX => Limit::Unlimited,
+			value => Limit::from_raw(value),
+		}
+	}
+
+	/// Raw limit value understood by the platform
+	pub fn to_raw(self) -> u64 {
+		match self {
+			Limit::Finite(value) => value,
+			Limit::Unlimited => RLIM_INFINITY,
+		}
+	}
+}
+
+/// Soft and hard limits of one resource, as listed by
+/// [`supported_limits`](crate::supported_limits)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LimitEntry {
+	/// Resource limited
+	pub resource: Resource,
+	/// Soft limit, the value enforced by the kernel
+	pub soft: Limit,
+	/// Hard limit, the ceiling for the soft limit
+	pub hard: Limit,
+}
+
+impl fmt::Display for Limit {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Limit::Finite(value) => fmt::Display::fmt(value, f),
+			Limit::Unlimited => f.pad("unlimited"),
+		}
+	}
+}
+
+/// Parses `unlimited` or `infinity`, and values with an optional `k`, `m` or
+/// `g` suffix in powers of 1024, e.g. `64k` for 65536. The raw value meaning
+/// no limit, e.g. `18446744073709551615`, parses as unlimited.
+impl FromStr for Limit {
+	type Err = Error;
+
+	fn from_str(s: &str) -> Result<Self, Error> {
+		let value = s.trim().to_ascii_lowercase();
+		if value == "unlimited" || value == "infinity" {
+			return Ok(Limit::Unlimited);
+		}
+
+		let (digits, multiplier) = match value.as_bytes().last() {
+			Some(b'k') => (&value[..value.len() - 1], 1 << 10),
+			Some(b'm') => (&value[..value.len() - 1], 1 << 20),
+			Some(b'g') => (&value[..value.len() - 1], 1 << 30),
+			_ => (&value[..], 1),
+		};
+		digits
+			.trim_end()
+			.parse::<u64>()
+			.ok()
+			.and_then(|digits| digits.checked_mul(multiplier))
+			.map(Limit::from_number)
+			.ok_or_else(|| Error::InvalidValue(s.to_owned()))
+	}
+}
+
+/// Serializes as the number, or the `"unlimited"` string
+#[cfg(feature = "serde")]
+impl serde::Serialize for Limit {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		match self {
+			Limit::Finite(value) => serializer.serialize_u64(*value),
+			Limit::Unlimited => serializer.serialize_str("unlimited"),
+		}
+	}
+}
+
+/// Deserializes from a number, or a string such as `"64k"` or `"unlimited"`,
+/// the raw value meaning no limit as unlimited
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Limit {
+	fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		struct Visitor;
+
+		impl serde::de::Visitor<'_> for Visitor {
+			type Value = Limit;
+
+			fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+				f.write_str("a limit such as 4096, \"64k\" or \"unlimited\"")
+			}
+
+			fn visit_u64<E: serde::de::Error>(self, value: u64) -> Result<Limit, E> {
+				Ok(Limit::from_number(value))
+			}
+
+			fn visit_i64<E: serde::de::Error>(self, value: i64) -> Result<Limit, E> {
+				u64::try_from(value)
+					.map(Limit::from_number)
+					.map_err(|_| E::invalid_value(serde::de::Unexpected::Signed(value), &self))
+			}
+
+			fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Limit, E> {
+				value.parse().map_err(E::custom)
+			}
+		}
+
+		deserializer.deserialize_any(Visitor)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_suffixes() {
+		for (s, value) in [
+			("4096", 4096),
+			("64k", 64 << 10),
+			("64K", 64 << 10),
+			("2m", 2 << 20),
+			("1g", 1 << 30),
+			(" 8 k ", 8 << 10),
+			("0", 0),
+		] {
+			assert_eq!(s.parse::<Limit>().unwrap(), Limit::Finite(value), "{s}");
+		}
+	}
+
+	#[test]
+	fn parses_unlimited() {
+		for s in ["unlimited", "infinity", "Infinity", "18446744073709551615"] {
+			assert_eq!(s.parse::<Limit>().unwrap(), Limit::Unlimited, "{s}");
+		}
+		assert_eq!(RLIM_INFINITY.to_string().parse::<Limit>().unwrap(), Limit::Unlimited);
+		assert_eq!(Limit::Unlimited.to_string().parse::<Limit>().unwrap(), Limit::Unlimited);
+	}
+
+	#[test]
+	fn rejects_invalid_values() {
+		for s in ["", "k", "-1", "1.5", "lots", "64kb", "18446744073709551615k"] {
+			assert!(s.parse::<Limit>().is_err(), "{s}");
+		}
+	}
+
+	#[test]
+	#[cfg(feature = "serde")]
+	fn deserializes_unlimited() {
+		use serde::de::value::{Error, StrDeserializer, U64Deserializer};
+		use serde::Deserialize;
+
+		let number = |value| Limit::deserialize(U64Deserializer::<Error>::new(value)).unwrap();
+		assert_eq!(number(4096), Limit::Finite(4096));
+		assert_eq!(number(u64::MAX), Limit::Unlimited);
+		assert_eq!(number(RLIM_INFINITY), Limit::Unlimited);
+		let string = |value| Limit::deserialize(StrDeserializer::<Error>::new(value)).unwrap();
+		assert_eq!(string("64k"), Limit::Finite(65536));
+		assert_eq!(string("unlimited"), Limit::Unlimited);
+	}
+}
@@ -0,0 +1,221 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! TCP listeners that stop accepting while few file descriptors are left,
+//! rather than spinning on `EMFILE`.
+
+use crate::headroom::Sampler;
+use std::io;
+use std::net::{SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Change of the accepting state, passed to the [`on_event`](Listener::on_event)
+/// hook
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListenerEvent {
+	/// Accepting stopped, with the headroom left
+	Paused {
+		/// File descriptors left, 0 when accepting failed with `EMFILE` or
+		/// `ENFILE`
+		headroom: u64,
+	},
+	/// Accepting resumes, with the headroom left
+	Resumed {
+		/// File descriptors left
+		headroom: u64,
+	},
+}
+
+type Hook = Arc<dyn Fn(ListenerEvent) + Send + Sync>;
+
+/// Decides when accepting pauses, shared by the std and tokio listeners
+#[derive(Clone)]
+struct Gate {
+	minimum: u64,
+	interval: Duration,
+	sampler: Arc<Sampler>,
+	paused: Arc<AtomicBool>,
+	hook: Option<Hook>,
+}
+
+impl Gate {
+	fn new(minimum: u64) -> Self {
+		Gate {
+			minimum,
+			interval: Duration::from_millis(50),
+			sampler: Arc::new(Sampler::new(Duration::from_millis(100))),
+			paused: Arc::new(AtomicBool::new(false)),
+			hook: None,
+		}
+	}
+
+	/// How long to wait before checking again, `None` to accept now. Accepting
+	/// goes on when the headroom can't be measured.
+	///
+	/// The headroom sample is reused while accepting, a paused listener takes
+	/// a new one on every check so that it resumes without delay.
+	fn check(&self) -> Option<Duration> {
+		if self.paused.load(Ordering::Relaxed) {
+			self.sampler.invalidate();
+		}
+		match self.sampler.headroom() {
+			Some(headroom) if headroom < self.minimum => self.pause(headroom),
+			Some(headroom) => {
+				if self.paused.swap(false, Ordering::Relaxed) {
+					self.notify(ListenerEvent::Resumed { headroom });
+				}
+				None
+			}
+			None => None,
+		}
+	}
+
+	/// How long to wait before retrying a failed accept, `None` if the error
+	/// isn't about running out of descriptors
+	fn exhausted(&self, error: &io::Error) -> Option<Duration> {
+		if !crate::is_fd_exhaustion(error) {
+			return None;
+		}
+		self.pause(0)
+	}
+
+	fn pause(&self, headroom: u64) -> Option<Duration> {
+		if !self.paused.swap(true, Ordering::Relaxed) {
+			self.notify(ListenerEvent::Paused { headroom });
+		}
+		Some(self.interval)
+	}
+
+	fn notify(&self, event: ListenerEvent) {
+		if let Some(hook) = &self.hook {
+			hook(event);
+		}
+	}
+}
+
+/// Builder methods shared by both listeners
+macro_rules! gate_methods {
+	() => {
+		/// Set how long to wait between headroom checks while paused, 50ms by
+		/// default
+		pub fn interval(mut self, interval: Duration) -> Self {
+			self.gate.interval = interval;
+			self
+		}
+
+		/// Set how long a headroom sample is reused while accepting, 100ms by
+		/// default
+		pub fn sample_interval(mut self, interval: Duration) -> Self {
+			self.gate.sampler = Arc::new(Sampler::new(interval));
+			self
+		}
+
+		/// Call the hook when accepting pauses or resumes, e.g. to log it
+		pub fn on_event(mut self, hook: impl Fn(ListenerEvent) + Send + Sync + 'static) -> Self {
+			self.gate.hook = Some(Arc::new(hook));
+			self
+		}
+
+		/// Whether accepting is paused
+		pub fn is_paused(&self) -> bool {
+			self.gate.paused.load(Ordering::Relaxed)
+		}
+
+		/// Wrapped listener
+		pub fn get_ref(&self) -> &L {
+			&self.inner
+		}
+
+		/// Unwrap the listener
+		pub fn into_inner(self) -> L {
+			self.inner
+		}
+	};
+}
+
+/// `std` listener waiting while [`fd_headroom`](crate::fd_headroom) is below a
+/// minimum, or accepting fails with `EMFILE` or `ENFILE`
+pub struct Listener<L = std::net::TcpListener> {
+	inner: L,
+	gate: Gate,
+}
+
+impl<L> Listener<L> {
+	/// Stop accepting on the listener while fewer than `minimum` descriptors
+	/// are left
+	pub fn new(inner: L, minimum: u64) -> Self {
+		Listener { inner, gate: Gate::new(minimum) }
+	}
+
+	gate_methods!();
+}
+
+impl Listener<std::net::TcpListener> {
+	/// Accept a connection, blocking while paused
+	pub fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
+		loop {
+			if let Some(wait) = self.gate.check() {
+				std::thread::sleep(wait);
+				continue;
+			}
+			match self.inner.accept() {
+				Err(error) => match self.gate.exhausted(&error) {
+					Some(wait) => std::thread::sleep(wait),
+					None => return Err(error),
+				},
+				accepted => return accepted,
+			}
+		}
+	}
+}
+
+/// Tokio flavor of [`Listener`]
+#[cfg(feature = "tokio")]
+pub struct TokioListener<L = tokio::net::TcpListener> {
+	inner: L,
+	gate: Gate,
+}
+
+#[cfg(feature = "tokio")]
+impl<L> TokioListener<L> {
+	/// Stop accepting on the listener while fewer than `minimum` descriptors
+	/// are left
+	pub fn new(inner: L, minimum: u64) -> Self {
+		TokioListener { inner, gate: Gate::new(minimum) }
+	}
+
+	gate_methods!();
+}
+
+#[cfg(feature = "tokio")]
+impl TokioListener<tokio::net::TcpListener> {
+	/// Accept a connection, waiting while paused
+	pub async fn accept(&self) -> io::Result<(tokio::net::TcpStream, SocketAddr)> {
+		loop {
+			if let Some(wait) = self.gate.check() {
+				tokio::time::sleep(wait).await;
+				continue;
+			}
+			match self.inner.accept().await {
+				Err(error) => match self.gate.exhausted(&error) {
+					Some(wait) => tokio::time::sleep(wait).await,
+					None => return Err(error),
+				},
+				accepted => return accepted,
+			}
+		}
+	}
+}
@@ -0,0 +1,192 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tower middleware rejecting requests when the process is running out of file
+//! descriptors.
+
+use crate::headroom::Sampler;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tower_layer::Layer;
+use tower_service::Service;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Error returned instead of calling the service when the headroom is low
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("Only {headroom} file descriptors left, below the minimum {minimum}")]
+pub struct Overloaded {
+	/// File descriptors left, see [`fd_headroom`](crate::fd_headroom)
+	pub headroom: u64,
+	/// Minimum headroom the layer was configured with
+	pub minimum: u64,
+}
+
+/// Layer rejecting requests with [`Overloaded`] while
+/// [`fd_headroom`](crate::fd_headroom) is below a minimum.
+///
+/// The headroom is sampled at most once per interval, 100ms by default, and
+/// shared by every service built by the layer. Requests are let through when it
+/// can't be measured.
+///
+/// Requests are only rejected, never queued until descriptors are freed: the
+/// service is ready again the moment the headroom allows it, so retrying is
+/// left to the client or to a retry layer in front of this one.
+#[derive(Debug, Clone)]
+pub struct FdLoadShedLayer {
+	minimum: u64,
+	sampler: Arc<Sampler>,
+}
+
+impl FdLoadShedLayer {
+	/// Reject requests while fewer than `minimum` descriptors are left
+	pub fn new(minimum: u64) -> Self {
+		FdLoadShedLayer { minimum, sampler: Arc::new(Sampler::new(Duration::from_millis(100))) }
+	}
+
+	/// Set how long a headroom sample is reused
+	pub fn interval(mut self, interval: Duration) -> Self {
+		self.sampler = Arc::new(Sampler::new(interval));
+		self
+	}
+}
+
+impl<S> Layer<S> for FdLoadShedLayer {
+	type Service = FdLoadShed<S>;
+
+	fn layer(&self, inner: S) -> FdLoadShed<S> {
+		FdLoadShed { inner, minimum: self.minimum, sampler: self.sampler.clone(), shed: None }
+	}
+}
+
+/// Service built by [`FdLoadShedLayer`]
+#[derive(Debug, Clone)]
+pub struct FdLoadShed<S> {
+	inner: S,
+	minimum: u64,
+	sampler: Arc<Sampler>,
+	shed: Option<Overloaded>,
+}
+
+impl<S, R> Service<R> for FdLoadShed<S>
+where
+	S: Service<R>,
+	S::Error: Into<BoxError>,
+{
+	type Response = S::Response;
+	type Error = BoxError;
+	type Future = FdLoadShedFuture<S::Future>;
+
+	fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+		// Like tower's load shedding the inner service isn't polled when
+		// rejecting, the request won't reach it
+		self.shed = match self.sampler.headroom() {
+			Some(headroom) if headroom < self.minimum => {
+				Some(Overloaded { headroom, minimum: self.minimum })
+			}
+			_ => None,
+		};
+		match self.shed {
+			Some(_) => Poll::Ready(Ok(())),
+			None => self.inner.poll_ready(cx).map_err(Into::into),
+		}
+	}
+
+	fn call(&mut self, request: R) -> FdLoadShedFuture<S::Future> {
+		match self.shed.take() {
+			Some(overloaded) => FdLoadShedFuture { state: State::Overloaded(Some(overloaded)) },
+			None => FdLoadShedFuture { state: State::Called(self.inner.call(request)) },
+		}
+	}
+}
+
+/// Future returned by [`FdLoadShed`]
+#[derive(Debug)]
+pub struct FdLoadShedFuture<F> {
+	state: State<F>,
+}
+
+#[derive(Debug)]
+enum State<F> {
+	Called(F),
+	Overloaded(Option<Overloaded>),
+}
+
+impl<F, T, E> Future for FdLoadShedFuture<F>
+where
+	F: Future<Output = Result<T, E>>,
+	E: Into<BoxError>,
+{
+	type Output = Result<T, BoxError>;
+
+	fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+		// The state is never moved out of, only the future is polled in place
+		match unsafe { &mut self.get_unchecked_mut().state } {
+			State::Called(future) => {
+				unsafe { Pin::new_unchecked(future) }.poll(cx).map_err(Into::into)
+			}
+			State::Overloaded(overloaded) => {
+				Poll::Ready(Err(overloaded.take().expect("polled after completion").into()))
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::task::Waker;
+
+	struct Echo;
+
+	impl Service<u32> for Echo {
+		type Response = u32;
+		type Error = BoxError;
+		type Future = std::future::Ready<Result<u32, BoxError>>;
+
+		fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+			Poll::Ready(Ok(()))
+		}
+
+		fn call(&mut self, request: u32) -> Self::Future {
+			std::future::ready(Ok(request))
+		}
+	}
+
+	fn send(service: &mut FdLoadShed<Echo>, request: u32) -> Result<u32, BoxError> {
+		let mut cx = Context::from_waker(Waker::noop());
+		assert!(service.poll_ready(&mut cx).is_ready());
+		let mut future = std::pin::pin!(service.call(request));
+		match future.as_mut().poll(&mut cx) {
+			Poll::Ready(result) => result,
+			Poll::Pending => panic!("the response is ready"),
+		}
+	}
+
+	#[test]
+	fn rejects_below_minimum() {
+		let mut service = FdLoadShedLayer::new(u64::MAX).layer(Echo);
+		let error = send(&mut service, 1).unwrap_err();
+		assert_eq!(error.downcast_ref::<Overloaded>().unwrap().minimum, u64::MAX);
+	}
+
+	#[test]
+	fn passes_requests_through() {
+		let mut service = FdLoadShedLayer::new(0).layer(Echo);
+		assert_eq!(send(&mut service, 7).unwrap(), 7);
+	}
+}
@@ -0,0 +1,287 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::backend::{Rlimit, RlimitOps, System};
+use crate::{
+	AuditEntry, Builder, Error, Growth, GrowthWatch, Health, LimitsSnapshot, Outcome, Policy,
+	Report, Resource, Thresholds,
+};
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::time::Duration;
+
+static GLOBAL: OnceLock<LimitsManager> = OnceLock::new();
+
+/// Process-wide owner of the limit policy, the limits it reached, the growth
+/// monitor and the health thresholds, so any part of an application can ask
+/// for its budget without the policy being passed around.
+///
+/// ```no_run
+/// # use fdlimit::{LimitsManager, Policy, Thresholds};
+/// // in main
+/// LimitsManager::init(Policy::recommended(), Thresholds::default());
+/// // anywhere else
+/// let budget = LimitsManager::global().map(|limits| limits.budget());
+/// ```
+#[derive(Debug)]
+pub struct LimitsManager {
+	thresholds: Thresholds,
+	/// Held while applying a policy, so concurrent changes apply in turn and
+	/// the state matches the limits reached last
+	applying: Mutex<()>,
+	state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+	policy: Policy,
+	report: Arc<Report>,
+	snapshot: Option<LimitsSnapshot>,
+	growth: GrowthWatch,
+}
+
+/// Default growth monitor, alerting on more than a descriptor per second
+/// opened over five minutes
+fn default_growth() -> GrowthWatch {
+	GrowthWatch::new(1.0, Duration::from_secs(300))
+}
+
+impl LimitsManager {
+	/// Create the global manager and apply the policy, carrying on after
+	/// failures, see [`report`](Self::report).
+	///
+	/// Only the first call does anything, later ones return the same manager
+	/// and leave their arguments unused.
+	pub fn init(policy: Policy, thresholds: Thresholds) -> &'static LimitsManager {
+		GLOBAL.get_or_init(|| LimitsManager::new(&System, policy, thresholds, Builder::raise))
+	}
+
+	fn new<B: RlimitOps>(
+		backend: &B,
+		policy: Policy,
+		thresholds: Thresholds,
+		raise: impl Fn(&Builder) -> Result<Outcome, Error>,
+	) -> Self {
+		let report = Arc::new(apply(&policy, raise));
+		let snapshot = LimitsSnapshot::capture_with(backend).ok();
+		let state = State { policy, report, snapshot, growth: default_growth() };
+		LimitsManager { thresholds, applying: Mutex::new(()), state: Mutex::new(state) }
+	}
+
+	/// Global manager, `None` until [`init`](Self::init) is called
+	pub fn global() -> Option<&'static LimitsManager> {
+		GLOBAL.get()
+	}
+
+	/// Policy applied last
+	pub fn policy(&self) -> Policy {
+		self.state().policy.clone()
+	}
+
+	/// Result of applying the policy last
+	pub fn report(&self) -> Arc<Report> {
+		self.state().report.clone()
+	}
+
+	/// Apply a new policy, replacing the current one. Calls made at the same
+	/// time apply their policies one after the other.
+	pub fn set_policy(&self, policy: Policy) -> Arc<Report> {
+		self.set_policy_with(&System, policy, Builder::raise)
+	}
+
+	fn set_policy_with<B: RlimitOps>(
+		&self,
+		backend: &B,
+		policy: Policy,
+		raise: impl Fn(&Builder) -> Result<Outcome, Error>,
+	) -> Arc<Report> {
+		let _applying = self.applying.lock().unwrap_or_else(|e| e.into_inner());
+		// The state isn't locked, on_raise callbacks can query the manager
+		let report = Arc::new(apply(&policy, raise));
+		let snapshot = LimitsSnapshot::capture_with(backend).ok();
+		let mut state = self.state();
+		state.policy = policy;
+		state.report = report.clone();
+		state.snapshot = snapshot;
+		report
+	}
+
+	/// Limits read after applying the policy or at the last
+	/// [`refresh`](Self::refresh), `None` if they couldn't be read
+	pub fn limits(&self) -> Option<LimitsSnapshot> {
+		self.state().snapshot.clone()
+	}
+
+	/// Read the limits again, e.g. after they were changed outside this crate
+	pub fn refresh(&self) -> Result<LimitsSnapshot, Error> {
+		let snapshot = LimitsSnapshot::capture()?;
+		self.state().snapshot = Some(snapshot.clone());
+		Ok(snapshot)
+	}
+
+	/// Descriptors that can still be opened below the soft limit of the
+	/// [`limits`](Self::limits), minus the reserved headroom, see
+	/// [`fd_headroom`](crate::fd_headroom)
+	pub fn budget(&self) -> Result<u64, Error> {
+		self.budget_with(&System)
+	}
+
+	fn budget_with<B: RlimitOps>(&self, backend: &B) -> Result<u64, Error> {
+		crate::fd_headroom_with(&self.cached(backend))
+	}
+
+	/// Open files usage against the soft limit of the [`limits`](Self::limits)
+	/// and the manager's thresholds
+	pub fn health(&self) -> Result<Health, Error> {
+		self.health_with(&System)
+	}
+
+	fn health_with<B: RlimitOps>(&self, backend: &B) -> Result<Health, Error> {
+		crate::health_with(&self.cached(backend), &self.thresholds)
+	}
+
+	/// Thresholds used by [`health`](Self::health)
+	pub fn thresholds(&self) -> Thresholds {
+		self.thresholds
+	}
+
+	/// Record the open descriptors in the growth monitor, returning the
+	/// sustained growth it alerts on, see [`GrowthWatch`]. Meant to be called
+	/// periodically.
+	pub fn sample(&self) -> Result<Option<Growth>, Error> {
+		self.sample_with(&System)
+	}
+
+	fn sample_with<B: RlimitOps>(&self, backend: &B) -> Result<Option<Growth>, Error> {
+		let cached = self.cached(backend);
+		self.state().growth.sample_with(&cached)
+	}
+
+	/// Replace the growth monitor, which by default alerts on more than one
+	/// descriptor per second opened over five minutes
+	pub fn set_growth_watch(&self, watch: GrowthWatch) {
+		self.state().growth = watch;
+	}
+
+	/// Limit changes made through this crate, see [`audit_log`](crate::audit_log)
+	pub fn audit_log(&self) -> Vec<AuditEntry> {
+		crate::audit_log()
+	}
+
+	fn cached<'a, B: RlimitOps>(&self, backend: &'a B) -> Cached<'a, B> {
+		Cached { snapshot: self.limits(), backend }
+	}
+
+	fn state(&self) -> MutexGuard<'_, State> {
+		self.state.lock().unwrap_or_else(|e| e.into_inner())
+	}
+}
+
+fn apply(policy: &Policy, raise: impl Fn(&Builder) -> Result<Outcome, Error>) -> Report {
+	let results = crate::policy::apply_each(policy, raise);
+	Report { results: results.collect() }
+}
+
+/// Limits of the snapshot when there is one, descriptors counted by the
+/// backend
+struct Cached<'a, B> {
+	snapshot: Option<LimitsSnapshot>,
+	backend: &'a B,
+}
+
+impl<B: RlimitOps> RlimitOps for Cached<'_, B> {
+	fn getrlimit(&self, resource: Resource) -> io::Result<Rlimit> {
+		let Some(snapshot) = &self.snapshot else { return self.backend.getrlimit(resource) };
+		let entry = snapshot.get(resource).ok_or(io::ErrorKind::Unsupported)?;
+		Ok(Rlimit { soft: entry.soft.to_raw(), hard: entry.hard.to_raw() })
+	}
+
+	fn setrlimit(&self, resource: Resource, rlim: Rlimit) -> io::Result<()> {
+		self.backend.setrlimit(resource, rlim)
+	}
+
+	fn sysctl_maxfiles(&self) -> io::Result<Option<u64>> {
+		self.backend.sysctl_maxfiles()
+	}
+
+	fn system_maxfiles(&self) -> io::Result<Option<u64>> {
+		self.backend.system_maxfiles()
+	}
+
+	fn open_fds(&self) -> io::Result<u64> {
+		self.backend.open_fds()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::simulated::Simulated;
+	use crate::{Limit, Rule, State as HealthState, Strategy};
+
+	fn policy(target: Strategy) -> Policy {
+		Policy { rules: [(Resource::NoFile, Rule { target, minimum: None })].into() }
+	}
+
+	fn manager(backend: &Simulated) -> LimitsManager {
+		let policy = policy(Strategy::Exact(1024));
+		LimitsManager::new(backend, policy, Thresholds::default(), |b| b.raise_with(backend))
+	}
+
+	#[test]
+	fn answers_from_the_snapshot() {
+		let backend = Simulated::new(256, 4096).with_open_fds(100);
+		let limits = manager(&backend);
+		assert_eq!(
+			limits.limits().unwrap().get(Resource::NoFile).unwrap().soft,
+			Limit::Finite(1024)
+		);
+
+		// Changed behind the manager's back
+		backend.setrlimit(Resource::NoFile, Rlimit { soft: 128, hard: 4096 }).unwrap();
+		let reserved = crate::reserved_headroom();
+		assert_eq!(limits.budget_with(&backend).unwrap(), (1024 - 100u64).saturating_sub(reserved));
+		assert_eq!(limits.health_with(&backend).unwrap().limit, Limit::Finite(1024));
+	}
+
+	#[test]
+	fn sets_policies() {
+		let backend = Simulated::new(256, 4096).with_open_fds(1000);
+		let limits = manager(&backend);
+		assert_eq!(limits.health_with(&backend).unwrap().state, HealthState::Critical);
+
+		let report = limits
+			.set_policy_with(&backend, policy(Strategy::MaxAvailable), |b| b.raise_with(&backend));
+		assert!(report.is_ok());
+		assert_eq!(limits.policy(), policy(Strategy::MaxAvailable));
+		assert_eq!(
+			limits.limits().unwrap().get(Resource::NoFile).unwrap().soft,
+			Limit::Finite(4096)
+		);
+		assert_eq!(limits.health_with(&backend).unwrap().state, HealthState::Healthy);
+	}
+
+	#[test]
+	fn monitors_growth() {
+		let backend = Simulated::new(256, 4096);
+		let limits = manager(&backend);
+		limits.set_growth_watch(GrowthWatch::new(0.0, Duration::from_millis(5)));
+		assert_eq!(limits.sample_with(&backend).unwrap(), None);
+		std::thread::sleep(Duration::from_millis(10));
+		let backend = backend.with_open_fds(100);
+		let growth = limits.sample_with(&backend).unwrap().unwrap();
+		assert_eq!(growth.usage, 100);
+		assert!(growth.exhaustion_in.is_some());
+	}
+}
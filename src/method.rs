@@ -0,0 +1,61 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+
+/// Operation that failed, for handling errors without matching on messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Method {
+	/// Reading the max number of open files per process from sysctl
+	Sysctl,
+	/// Fetching the current limit
+	GetRLimit,
+	/// Setting the new limit
+	SetRLimit,
+	/// Counting the open file descriptors
+	CountFds,
+	/// Reading from procfs
+	ReadProc,
+	/// Opening file descriptors
+	OpenFds,
+	/// Reading the policy file
+	ReadPolicy,
+	/// Reading or writing the state file
+	PersistState,
+	/// Switching to an unprivileged user and group
+	DropPrivileges,
+	/// Registering the fork handler
+	PthreadAtfork,
+	/// Computing or checking the new limit against the strategy or policy
+	Strategy,
+}
+
+impl fmt::Display for Method {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Method::Sysctl => "sysctl",
+			Method::GetRLimit => "getrlimit",
+			Method::SetRLimit => "setrlimit",
+			Method::CountFds => "count open fds",
+			Method::ReadProc => "read procfs",
+			Method::OpenFds => "open fds",
+			Method::ReadPolicy => "read policy",
+			Method::PersistState => "persist state",
+			Method::DropPrivileges => "drop privileges",
+			Method::PthreadAtfork => "pthread_atfork",
+			Method::Strategy => "strategy",
+		})
+	}
+}
@@ -0,0 +1,103 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Conversions from and to the `nix::sys::resource` types.
+//!
+//! Single limit values are `rlim_t` on both sides, see [`Limit::from_raw`]
+//! and [`Limit::to_raw`](crate::Limit::to_raw).
+
+use crate::backend::{from_rlim, to_rlim, ConversionError, Rlimit};
+use crate::{Error, Resource};
+use nix::sys::resource::{rlim_t, Resource as NixResource};
+
+/// Fails with [`Error::ResourceNotAvailable`] for resources nix doesn't have
+/// on the platform
+impl TryFrom<Resource> for NixResource {
+	type Error = Error;
+
+	fn try_from(resource: Resource) -> Result<Self, Error> {
+		Ok(match resource {
+			Resource::NoFile => NixResource::RLIMIT_NOFILE,
+			Resource::Core => NixResource::RLIMIT_CORE,
+			Resource::FSize => NixResource::RLIMIT_FSIZE,
+			Resource::Stack => NixResource::RLIMIT_STACK,
+			Resource::Cpu => NixResource::RLIMIT_CPU,
+			Resource::Data => NixResource::RLIMIT_DATA,
+			#[cfg(not(target_os = "freebsd"))]
+			Resource::As => NixResource::RLIMIT_AS,
+			// Same constant as RLIMIT_AS
+			#[cfg(target_os = "freebsd")]
+			Resource::As => NixResource::RLIMIT_VMEM,
+			#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+			Resource::NProc => NixResource::RLIMIT_NPROC,
+			#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+			Resource::MemLock => NixResource::RLIMIT_MEMLOCK,
+			#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+			Resource::Rss => NixResource::RLIMIT_RSS,
+			#[cfg(target_os = "linux")]
+			Resource::MsgQueue => NixResource::RLIMIT_MSGQUEUE,
+			#[cfg(target_os = "linux")]
+			Resource::Nice => NixResource::RLIMIT_NICE,
+			#[cfg(target_os = "linux")]
+			Resource::RtPrio => NixResource::RLIMIT_RTPRIO,
+			#[cfg(target_os = "linux")]
+			Resource::SigPending => NixResource::RLIMIT_SIGPENDING,
+			#[cfg(target_os = "linux")]
+			Resource::Locks => NixResource::RLIMIT_LOCKS,
+			#[cfg(target_os = "linux")]
+			Resource::RtTime => NixResource::RLIMIT_RTTIME,
+			#[cfg(target_os = "freebsd")]
+			Resource::KQueues => NixResource::RLIMIT_KQUEUES,
+			#[cfg(target_os = "freebsd")]
+			Resource::Npts => NixResource::RLIMIT_NPTS,
+			#[cfg(target_os = "freebsd")]
+			Resource::SbSize => NixResource::RLIMIT_SBSIZE,
+			#[allow(unreachable_patterns)]
+			_ => return Err(Error::ResourceNotAvailable(resource)),
+		})
+	}
+}
+
+/// Fails with [`Error::InvalidValue`] for resources this crate doesn't have,
+/// e.g. `RLIMIT_SWAP` on FreeBSD
+impl TryFrom<NixResource> for Resource {
+	type Error = Error;
+
+	fn try_from(resource: NixResource) -> Result<Self, Error> {
+		let raw = resource as crate::raw::RawResource;
+		Resource::ALL
+			.iter()
+			.copied()
+			.find(|&candidate| crate::raw::resource(candidate) == Some(raw))
+			.ok_or_else(|| Error::InvalidValue(format!("{resource:?}")))
+	}
+}
+
+/// Soft and hard limits as returned by `nix::sys::resource::getrlimit`
+impl TryFrom<(rlim_t, rlim_t)> for Rlimit {
+	type Error = ConversionError;
+
+	fn try_from((soft, hard): (rlim_t, rlim_t)) -> Result<Self, ConversionError> {
+		Ok(Rlimit { soft: from_rlim(soft)?, hard: from_rlim(hard)? })
+	}
+}
+
+/// Soft and hard limits in the order taken by `nix::sys::resource::setrlimit`
+impl TryFrom<Rlimit> for (rlim_t, rlim_t) {
+	type Error = ConversionError;
+
+	fn try_from(rlim: Rlimit) -> Result<Self, ConversionError> {
+		Ok((to_rlim(rlim.soft)?, to_rlim(rlim.hard)?))
+	}
+}
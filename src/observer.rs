@@ -0,0 +1,66 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{Outcome, Resource};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+type Callback = Arc<dyn Fn(Resource, &Outcome) + Send + Sync>;
+
+static OBSERVERS: Mutex<Vec<(ObserverId, Callback)>> = Mutex::new(Vec::new());
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Handle of a registered [`on_raise`] callback
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObserverId(u64);
+
+/// Register a callback fired after every limit change made through this crate.
+///
+/// The callback runs on the thread that changed the limit. Changes made by the
+/// fork handler in child processes or to the limits of other processes don't
+/// fire it.
+pub fn on_raise<F>(callback: F) -> ObserverId
+where
+	F: Fn(Resource, &Outcome) + Send + Sync + 'static,
+{
+	let id = ObserverId(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+	observers().push((id, Arc::new(callback)));
+	id
+}
+
+/// Unregister a callback, returns whether it was registered
+pub fn remove_on_raise(id: ObserverId) -> bool {
+	let mut observers = observers();
+	let len = observers.len();
+	observers.retain(|(other, _)| *other != id);
+	observers.len() != len
+}
+
+/// Fire the callbacks if the limit changed
+pub(crate) fn notify(resource: Resource, outcome: &Outcome) {
+	let Outcome::LimitRaised { from, to } = *outcome else { return };
+	if from == to {
+		return;
+	}
+
+	// Callbacks may register or remove others, don't hold the lock while calling them
+	let callbacks: Vec<Callback> = observers().iter().map(|(_, f)| f.clone()).collect();
+	for callback in callbacks {
+		callback(resource, outcome);
+	}
+}
+
+fn observers() -> MutexGuard<'static, Vec<(ObserverId, Callback)>> {
+	OBSERVERS.lock().unwrap_or_else(|e| e.into_inner())
+}
@@ -0,0 +1,79 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! OpenTelemetry metrics of the open files limits and usage.
+
+use crate::{Limit, Resource};
+use opentelemetry::metrics::Meter;
+use opentelemetry::KeyValue;
+
+/// Attributes identifying the process, `process.pid` and
+/// `process.executable.name`, to add to the meter provider's resource
+pub fn process_attributes() -> Vec<KeyValue> {
+	let mut attributes = vec![KeyValue::new("process.pid", i64::from(std::process::id()))];
+	let name = std::env::current_exe()
+		.ok()
+		.and_then(|exe| Some(exe.file_name()?.to_string_lossy().into_owned()));
+	if let Some(name) = name {
+		attributes.push(KeyValue::new("process.executable.name", name));
+	}
+	attributes
+}
+
+/// Register observable gauges reporting the open files usage and limits on each
+/// collection:
+///
+/// - `process.open_file_descriptor.count`, the open descriptors
+/// - `process.open_file_descriptor.limit`, the soft and hard limits told apart
+///   by the `limit` attribute, left out when unlimited
+/// - `process.open_file_descriptor.headroom`, see [`fd_headroom`](crate::fd_headroom)
+///
+/// Values that can't be read are left out of the collection.
+pub fn register_metrics(meter: &Meter) {
+	let unit = "{file_descriptor}";
+	meter
+		.u64_observable_gauge("process.open_file_descriptor.count")
+		.with_description("Number of open file descriptors")
+		.with_unit(unit)
+		.with_callback(|observer| {
+			use crate::backend::RlimitOps;
+			if let Ok(open) = crate::backend::System.open_fds() {
+				observer.observe(open, &[]);
+			}
+		})
+		.build();
+	meter
+		.u64_observable_gauge("process.open_file_descriptor.limit")
+		.with_description("Soft and hard limits of open file descriptors")
+		.with_unit(unit)
+		.with_callback(|observer| {
+			let Ok(rlim) = crate::get_limit(Resource::NoFile) else { return };
+			for (kind, limit) in [("soft", rlim.soft_limit()), ("hard", rlim.hard_limit())] {
+				if let Limit::Finite(limit) = limit {
+					observer.observe(limit, &[KeyValue::new("limit", kind)]);
+				}
+			}
+		})
+		.build();
+	meter
+		.u64_observable_gauge("process.open_file_descriptor.headroom")
+		.with_description("File descriptors that can still be opened, minus the reserved headroom")
+		.with_unit(unit)
+		.with_callback(|observer| {
+			if let Ok(headroom) = crate::fd_headroom() {
+				observer.observe(headroom, &[]);
+			}
+		})
+		.build();
+}
@@ -0,0 +1,404 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Limits of login sessions set by `pam_limits`, from
+//! `/etc/security/limits.conf`.
+
+use crate::{Limit, Resource};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+const LIMITS_CONF: &str = "/etc/security/limits.conf";
+const LIMITS_DIR: &str = "/etc/security/limits.d";
+
+/// Limit a `limits.conf` entry sets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PamLimitKind {
+	/// `soft`
+	Soft,
+	/// `hard`
+	Hard,
+	/// `-`, both limits
+	Both,
+}
+
+impl PamLimitKind {
+	/// Whether the entry sets the soft limit
+	pub fn is_soft(self) -> bool {
+		self != PamLimitKind::Hard
+	}
+
+	/// Whether the entry sets the hard limit
+	pub fn is_hard(self) -> bool {
+		self != PamLimitKind::Soft
+	}
+}
+
+/// Entry of `limits.conf` or of a `limits.d` file applying to the current
+/// user, see [`pam_limits`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PamLimit {
+	/// User, `@group`, uid range or `*` the entry applies to
+	pub domain: String,
+	/// Limit set
+	pub kind: PamLimitKind,
+	/// Resource limited
+	pub resource: Resource,
+	/// Value, in the resource's units rather than the KiB and minutes of the file
+	pub value: Limit,
+	/// File of the entry
+	pub path: PathBuf,
+	/// Line of the entry, from 1
+	pub line: usize,
+}
+
+/// As written in the file, e.g. `@dev soft nofile 4096
+/// (/etc/security/limits.conf:56)`
+impl fmt::Display for PamLimit {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let kind = match self.kind {
+			PamLimitKind::Soft => "soft",
+			PamLimitKind::Hard => "hard",
+			PamLimitKind::Both => "-",
+		};
+		write!(
+			f,
+			"{} {kind} {} {} ({}:{})",
+			self.domain,
+			self.resource,
+			file_value(self.resource, self.value),
+			self.path.display(),
+			self.line
+		)
+	}
+}
+
+/// Entries of `/etc/security/limits.conf` then of the `.conf` files of
+/// `/etc/security/limits.d` sorted by name applying to the current user, in
+/// the order `pam_limits` reads them.
+///
+/// For the same limit, entries of the user override the ones of its groups,
+/// which override the `*` ones, and later entries override earlier ones of
+/// the same kind of domain. As with `pam_limits`, `*` and group entries don't
+/// apply to root. Files that can't be read and invalid lines are
+/// skipped. The entries only apply to sessions whose PAM stack loads
+/// `pam_limits`, see [`pam_limits_enabled`].
+pub fn pam_limits() -> Vec<PamLimit> {
+	let Some(user) = User::current() else { return Vec::new() };
+	let mut files = vec![PathBuf::from(LIMITS_CONF)];
+	if let Ok(entries) = std::fs::read_dir(LIMITS_DIR) {
+		let mut drop_ins: Vec<PathBuf> = entries
+			.flatten()
+			.map(|entry| entry.path())
+			.filter(|path| path.extension().is_some_and(|extension| extension == "conf"))
+			.collect();
+		drop_ins.sort();
+		files.extend(drop_ins);
+	}
+	let mut limits = Vec::new();
+	for path in files {
+		if let Ok(contents) = std::fs::read_to_string(&path) {
+			parse(&contents, &path, &user, &mut limits);
+		}
+	}
+	limits
+}
+
+/// Collect the entries of a limits file applying to the user
+fn parse(contents: &str, path: &Path, user: &User, limits: &mut Vec<PamLimit>) {
+	for (index, line) in contents.lines().enumerate() {
+		let line = line.split('#').next().unwrap_or_default();
+		let [domain, kind, item, value] = match line.split_whitespace().collect::<Vec<_>>()[..] {
+			[domain, kind, item, value] => [domain, kind, item, value],
+			_ => continue,
+		};
+		let kind = match kind {
+			"soft" => PamLimitKind::Soft,
+			"hard" => PamLimitKind::Hard,
+			"-" => PamLimitKind::Both,
+			_ => continue,
+		};
+		let Some(resource) = pam_item(item) else { continue };
+		let Some(value) = parse_value(resource, value) else { continue };
+		if user.matches(domain) {
+			limits.push(PamLimit {
+				domain: domain.to_owned(),
+				kind,
+				resource,
+				value,
+				path: path.to_owned(),
+				line: index + 1,
+			});
+		}
+	}
+}
+
+/// Resource of a `pam_limits` item, items that aren't a resource limit such as
+/// `maxlogins` are `None`
+fn pam_item(item: &str) -> Option<Resource> {
+	item.parse().ok().filter(|&resource| pam_item_name(resource).is_some())
+}
+
+/// Name of the resource's `pam_limits` item, `None` for the resources it can't
+/// limit
+pub(crate) fn pam_item_name(resource: Resource) -> Option<&'static str> {
+	match resource {
+		Resource::RtTime | Resource::KQueues | Resource::Npts | Resource::SbSize => None,
+		resource => Some(resource.name()),
+	}
+}
+
+/// Value in the resource's units, sizes are in KiB in the file, CPU time in
+/// minutes and nice as the lowest nice value allowed
+fn parse_value(resource: Resource, value: &str) -> Option<Limit> {
+	if matches!(value, "unlimited" | "infinity" | "-1") {
+		return Some(Limit::Unlimited);
+	}
+	let value = match resource {
+		Resource::Nice => 20u64.checked_add_signed(-value.parse::<i64>().ok()?.clamp(-20, 19))?,
+		Resource::Cpu => value.parse::<u64>().ok()?.checked_mul(60)?,
+		resource if crate::remediation::in_kib(resource) => {
+			value.parse::<u64>().ok()?.checked_mul(1024)?
+		}
+		_ => value.parse().ok()?,
+	};
+	Some(Limit::Finite(value))
+}
+
+/// Value as written in a limits file, the opposite of [`parse_value`]
+pub(crate) fn file_value(resource: Resource, limit: Limit) -> String {
+	match (resource, limit) {
+		(_, Limit::Unlimited) => "unlimited".to_owned(),
+		(Resource::Nice, Limit::Finite(value)) => (20 - value.min(40) as i64).to_string(),
+		(Resource::Cpu, Limit::Finite(value)) => value.div_ceil(60).to_string(),
+		(resource, limit) => crate::remediation::limits_conf_value(resource, limit),
+	}
+}
+
+/// Whether the PAM stack of the service, e.g. `sshd` or `login`, loads
+/// `pam_limits` for its sessions, following the files it includes
+pub fn pam_limits_enabled(service: &str) -> bool {
+	loads_pam_limits(service, 0)
+}
+
+fn loads_pam_limits(service: &str, depth: usize) -> bool {
+	// Distributions ship their defaults in /usr/lib/pam.d, overridden in /etc/pam.d
+	let contents = ["/etc/pam.d", "/usr/lib/pam.d"]
+		.iter()
+		.find_map(|dir| std::fs::read_to_string(Path::new(dir).join(service)).ok());
+	let Some(contents) = contents else { return false };
+	contents.lines().any(|line| {
+		let line = line.split('#').next().unwrap_or_default();
+		let words: Vec<_> = line.split_whitespace().collect();
+		match words[..] {
+			["@include", file, ..] => depth < 8 && loads_pam_limits(file, depth + 1),
+			[kind, control, module, ..] if kind.trim_start_matches('-') == "session" => {
+				if matches!(control, "include" | "substack") {
+					depth < 8 && loads_pam_limits(module, depth + 1)
+				} else {
+					module.rsplit('/').next() == Some("pam_limits.so")
+				}
+			}
+			_ => false,
+		}
+	})
+}
+
+/// User of the process, with what `limits.conf` domains match
+struct User {
+	name: Option<String>,
+	uid: u32,
+	groups: Vec<(u32, Option<String>)>,
+}
+
+impl User {
+	#[cfg(unix)]
+	fn current() -> Option<Self> {
+		let uid = unsafe { libc::getuid() };
+		let count = unsafe { libc::getgroups(0, std::ptr::null_mut()) };
+		let mut gids = vec![0; usize::try_from(count).ok()?];
+		let count = unsafe { libc::getgroups(count, gids.as_mut_ptr()) };
+		gids.truncate(usize::try_from(count).ok()?);
+		gids.push(unsafe { libc::getgid() });
+		let groups = gids.into_iter().map(|gid| (gid, group_name(gid))).collect();
+		Some(User { name: user_name(uid), uid, groups })
+	}
+
+	#[cfg(not(unix))]
+	fn current() -> Option<Self> {
+		None
+	}
+
+	/// Whether a `limits.conf` domain applies to the user, `*` and group
+	/// entries never apply to root
+	fn matches(&self, domain: &str) -> bool {
+		if domain == "*" {
+			return self.uid != 0;
+		}
+		if let Some(group) = domain.strip_prefix('@') {
+			if self.uid == 0 {
+				return false;
+			}
+			return match parse_range(group) {
+				Some(range) => self.groups.iter().any(|(gid, _)| range.contains(gid)),
+				None => self.groups.iter().any(|(_, name)| name.as_deref() == Some(group)),
+			};
+		}
+		match parse_range(domain) {
+			Some(range) => range.contains(&self.uid),
+			None => self.name.as_deref() == Some(domain),
+		}
+	}
+}
+
+/// `<min>:<max>` or `<min>:` id range, or `:<id>` matching exactly the id, a
+/// lone id is a name
+fn parse_range(domain: &str) -> Option<std::ops::RangeInclusive<u32>> {
+	let (min, max) = domain.split_once(':')?;
+	let max = if max.is_empty() { u32::MAX } else { max.parse().ok()? };
+	let min = if min.is_empty() { max } else { min.parse().ok()? };
+	Some(min..=max)
+}
+
+/// Name of the real user of the process
+#[cfg(unix)]
+pub(crate) fn current_user_name() -> Option<String> {
+	user_name(unsafe { libc::getuid() })
+}
+
+#[cfg(not(unix))]
+pub(crate) fn current_user_name() -> Option<String> {
+	None
+}
+
+/// Name of the user, looked up through NSS
+#[cfg(unix)]
+fn user_name(uid: u32) -> Option<String> {
+	let mut buffer = vec![0; 4096];
+	let mut entry: libc::passwd = unsafe { std::mem::zeroed() };
+	let mut result = std::ptr::null_mut();
+	let code = unsafe {
+		libc::getpwuid_r(uid, &mut entry, buffer.as_mut_ptr(), buffer.len(), &mut result)
+	};
+	if code != 0 || result.is_null() {
+		return None;
+	}
+	let name = unsafe { std::ffi::CStr::from_ptr(entry.pw_name) };
+	Some(name.to_string_lossy().into_owned())
+}
+
+#[cfg(unix)]
+fn group_name(gid: u32) -> Option<String> {
+	let mut buffer = vec![0; 4096];
+	let mut entry: libc::group = unsafe { std::mem::zeroed() };
+	let mut result = std::ptr::null_mut();
+	let code = unsafe {
+		libc::getgrgid_r(gid, &mut entry, buffer.as_mut_ptr(), buffer.len(), &mut result)
+	};
+	if code != 0 || result.is_null() {
+		return None;
+	}
+	let name = unsafe { std::ffi::CStr::from_ptr(entry.gr_name) };
+	Some(name.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn user(uid: u32) -> User {
+		User { name: Some("alice".to_owned()), uid, groups: vec![(100, Some("dev".to_owned()))] }
+	}
+
+	#[test]
+	fn matches_domains() {
+		let alice = user(1000);
+		for domain in ["*", "alice", "@dev", "1000:", ":1000", "500:1500", "@100:", "@:100"] {
+			assert!(alice.matches(domain), "{domain}");
+		}
+		for domain in ["bob", "1000", "@ops", "1001:", ":1500", "0:999", "@101:", "@:99", "@100"] {
+			assert!(!alice.matches(domain), "{domain}");
+		}
+	}
+
+	#[test]
+	fn wildcards_and_groups_skip_root() {
+		let root = User { name: Some("root".to_owned()), ..user(0) };
+		for domain in ["*", "@dev", "@100:", "@0:"] {
+			assert!(!root.matches(domain), "{domain}");
+		}
+		for domain in ["root", "0:", ":0"] {
+			assert!(root.matches(domain), "{domain}");
+		}
+	}
+
+	#[test]
+	fn parses_values() {
+		assert_eq!(parse_value(Resource::NoFile, "4096"), Some(Limit::Finite(4096)));
+		assert_eq!(parse_value(Resource::Stack, "8192"), Some(Limit::Finite(8 << 20)));
+		assert_eq!(parse_value(Resource::Cpu, "2"), Some(Limit::Finite(120)));
+		assert_eq!(parse_value(Resource::Nice, "-5"), Some(Limit::Finite(25)));
+		assert_eq!(parse_value(Resource::Nice, "-40"), Some(Limit::Finite(40)));
+		for value in ["unlimited", "infinity", "-1"] {
+			assert_eq!(parse_value(Resource::NoFile, value), Some(Limit::Unlimited));
+		}
+		assert_eq!(parse_value(Resource::NoFile, "many"), None);
+		assert_eq!(parse_value(Resource::Stack, &u64::MAX.to_string()), None);
+	}
+
+	#[test]
+	fn file_values_round_trip() {
+		for (resource, value) in [
+			(Resource::NoFile, "4096"),
+			(Resource::Stack, "8192"),
+			(Resource::Cpu, "2"),
+			(Resource::Nice, "-5"),
+			(Resource::NoFile, "unlimited"),
+		] {
+			let limit = parse_value(resource, value).unwrap();
+			assert_eq!(file_value(resource, limit), value);
+		}
+	}
+
+	#[test]
+	fn parses_entries_of_the_user() {
+		let contents = "\
+# <domain> <type> <item> <value>
+*        soft nofile   4096
+@dev     hard nofile   65536 # comment
+bob      -    nofile   1024
+alice    -    stack    unlimited
+alice    soft maxlogins 4
+alice    soft nofile
+alice    both nofile   10
+";
+		let mut limits = Vec::new();
+		parse(contents, Path::new("limits.conf"), &user(1000), &mut limits);
+		let entries: Vec<_> = limits
+			.iter()
+			.map(|limit| {
+				(limit.domain.as_str(), limit.kind, limit.resource, limit.value, limit.line)
+			})
+			.collect();
+		assert_eq!(
+			entries,
+			[
+				("*", PamLimitKind::Soft, Resource::NoFile, Limit::Finite(4096), 2),
+				("@dev", PamLimitKind::Hard, Resource::NoFile, Limit::Finite(65536), 3),
+				("alice", PamLimitKind::Both, Resource::Stack, Limit::Unlimited, 5),
+			]
+		);
+		assert_eq!(limits[1].to_string(), "@dev hard nofile 65536 (limits.conf:3)");
+	}
+}
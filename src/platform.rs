@@ -0,0 +1,152 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Facts about the platform, probed once.
+
+use crate::backend::{RlimitOps, System};
+use std::sync::OnceLock;
+
+/// Facts about the platform the process runs on, probed on first use, see
+/// [`platform_info`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PlatformInfo {
+	/// Kernel release, e.g. `6.8.0-45-generic` (`uname -r`), `None` on
+	/// Windows
+	pub kernel_release: Option<String>,
+	/// Width in bits of `rlim_t`, `None` on platforms without rlimits
+	pub rlim_bits: Option<u32>,
+	/// Whether the kernel has the `prlimit64` system call, Linux 2.6.36 and later
+	pub has_prlimit: bool,
+	/// Whether the kernel has the `close_range` system call, Linux 5.9 and later
+	pub has_close_range: bool,
+	/// Per-process kernel ceiling of open files (`kern.maxfilesperproc`,
+	/// `fs.nr_open` on Linux) at the time of the probe
+	pub max_files_per_process: Option<u64>,
+	/// Max number of open files in the whole system at the time of the probe
+	pub max_files: Option<u64>,
+	/// Version of the Windows Subsystem for Linux running the process, `None`
+	/// elsewhere
+	pub wsl: Option<WslVersion>,
+}
+
+/// Version of the Windows Subsystem for Linux
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum WslVersion {
+	/// WSL 1, translating Linux system calls to the Windows kernel, which only
+	/// partly enforces resource limits
+	V1,
+	/// WSL 2, running a real Linux kernel in a virtual machine
+	V2,
+}
+
+/// Facts about the platform, probed on the first call and cached.
+///
+/// The ceilings aren't updated afterwards, use
+/// [`refresh_kernel_ceiling`](crate::refresh_kernel_ceiling) and
+/// [`system_max_files`](crate::system_max_files) for current values.
+pub fn platform_info() -> &'static PlatformInfo {
+	static INFO: OnceLock<PlatformInfo> = OnceLock::new();
+	INFO.get_or_init(|| {
+		let kernel_release = kernel_release();
+		let wsl = wsl_version(kernel_release.as_deref());
+		PlatformInfo {
+			kernel_release,
+			rlim_bits: rlim_bits(),
+			has_prlimit: has_syscall(Syscall::Prlimit),
+			has_close_range: has_syscall(Syscall::CloseRange),
+			max_files_per_process: System.sysctl_maxfiles().ok().flatten(),
+			max_files: System.system_maxfiles().ok().flatten(),
+			wsl,
+		}
+	})
+}
+
+/// WSL version from the kernel release, `4.4.0-19041-Microsoft` on WSL 1 and
+/// `5.15.153.1-microsoft-standard-WSL2` on WSL 2, custom WSL 2 kernels are
+/// told apart by the variable every WSL process gets
+fn wsl_version(kernel_release: Option<&str>) -> Option<WslVersion> {
+	if !cfg!(target_os = "linux") {
+		return None;
+	}
+	let release = kernel_release?;
+	if release.ends_with("-Microsoft") {
+		Some(WslVersion::V1)
+	} else if release.to_ascii_lowercase().contains("microsoft")
+		|| std::env::var_os("WSL_DISTRO_NAME").is_some()
+	{
+		Some(WslVersion::V2)
+	} else {
+		None
+	}
+}
+
+#[cfg(unix)]
+fn kernel_release() -> Option<String> {
+	let mut name = unsafe { std::mem::zeroed::<libc::utsname>() };
+	if unsafe { libc::uname(&mut name) } != 0 {
+		return None;
+	}
+	let release = unsafe { std::ffi::CStr::from_ptr(name.release.as_ptr()) };
+	Some(release.to_string_lossy().into_owned())
+}
+
+#[cfg(not(unix))]
+fn kernel_release() -> Option<String> {
+	None
+}
+
+#[cfg(unix)]
+fn rlim_bits() -> Option<u32> {
+	Some(libc::rlim_t::BITS)
+}
+
+#[cfg(not(unix))]
+fn rlim_bits() -> Option<u32> {
+	None
+}
+
+/// System call probed for, see [`has_syscall`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Syscall {
+	Prlimit,
+	CloseRange,
+}
+
+/// Whether the kernel has the system call, probed with arguments it rejects
+/// before doing anything so the probe has no effect: old kernels fail with
+/// `ENOSYS` instead of `EINVAL` or `EFAULT`
+#[cfg(target_os = "linux")]
+pub(crate) fn has_syscall(syscall: Syscall) -> bool {
+	let ret = unsafe {
+		match syscall {
+			// Resource out of range
+			Syscall::Prlimit => libc::syscall(
+				libc::SYS_prlimit64,
+				0,
+				libc::c_int::MAX,
+				std::ptr::null::<libc::c_void>(),
+				std::ptr::null_mut::<libc::c_void>(),
+			),
+			// First descriptor after the last one
+			Syscall::CloseRange => libc::syscall(libc::SYS_close_range, 1, 0, 0),
+		}
+	};
+	ret == 0 || std::io::Error::last_os_error().raw_os_error() != Some(libc::ENOSYS)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn has_syscall(_syscall: Syscall) -> bool {
+	false
+}
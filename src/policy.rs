@@ -0,0 +1,220 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::backend::RlimitOps;
+use crate::{Builder, Error, Limit, Outcome, Resource, Strategy};
+use std::collections::BTreeMap;
+
+/// Limit targets and minimums for a set of resources, typically described in
+/// a service's config file.
+///
+/// With the `serde` feature it (de)serializes as a table keyed by resource name,
+/// for example in TOML:
+///
+/// ```toml
+/// [nofile]
+/// target = "max"
+/// minimum = 4096
+///
+/// [memlock]
+/// target = 65536
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(transparent))]
+pub struct Policy {
+	/// Rule applied to each resource
+	pub rules: BTreeMap<Resource, Rule>,
+}
+
+/// How a single resource limit is raised
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rule {
+	/// Strategy picking the new soft limit, in its textual form when
+	/// serialized, see [`Strategy::from_str`](std::str::FromStr::from_str)
+	#[cfg_attr(feature = "serde", serde(default))]
+	pub target: Strategy,
+	/// Fail if the soft limit ends up below this value
+	#[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+	pub minimum: Option<u64>,
+}
+
+impl Policy {
+	/// Read the policy from `FDLIMIT_<RESOURCE>` variables holding the target
+	/// and `FDLIMIT_<RESOURCE>_MIN` variables holding the minimum, for example
+	/// `FDLIMIT_NOFILE=max` and `FDLIMIT_NOFILE_MIN=4k`.
+	///
+	/// Resources with neither variable set are left out.
+	pub fn from_env() -> Result<Self, Error> {
+		Self::from_vars(|key| std::env::var(key).ok())
+	}
+
+	fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, Error> {
+		let mut rules = BTreeMap::new();
+		for &resource in Resource::ALL {
+			let key = format!("FDLIMIT_{}", resource.name().to_uppercase());
+			let target = var(&key);
+			let minimum = var(&format!("{key}_MIN"));
+			if target.is_none() && minimum.is_none() {
+				continue;
+			}
+
+			let target = target.map(|target| target.parse()).transpose()?.unwrap_or_default();
+			let minimum = minimum.map(|minimum| minimum.parse().map(Limit::to_raw)).transpose()?;
+			rules.insert(resource, Rule { target, minimum });
+		}
+
+		Ok(Policy { rules })
+	}
+
+	/// Sensible limits for servers: open files, processes and locked memory all
+	/// raised to the maximum available
+	pub fn recommended() -> Self {
+		let rules = [Resource::NoFile, Resource::NProc, Resource::MemLock]
+			.into_iter()
+			.map(|resource| (resource, Rule::default()))
+			.collect();
+		Policy { rules }
+	}
+}
+
+/// Outcome of raising every resource of a policy, carrying on after failures
+#[derive(Debug)]
+pub struct Report {
+	/// Result for each resource, in the policy's order
+	pub results: Vec<(Resource, Result<Outcome, Error>)>,
+}
+
+impl Report {
+	/// Whether every limit was raised
+	pub fn is_ok(&self) -> bool {
+		self.results.iter().all(|(_, result)| result.is_ok())
+	}
+
+	/// Resources that failed and why
+	pub fn errors(&self) -> impl Iterator<Item = (Resource, &Error)> + '_ {
+		self.results
+			.iter()
+			.filter_map(|(resource, result)| Some((*resource, result.as_ref().err()?)))
+	}
+}
+
+/// Raise open files, processes and locked memory limits to the
+/// [recommended](Policy::recommended) server values, reporting each failure
+/// rather than stopping at the first one
+pub fn raise_all_recommended() -> Report {
+	let policy = Policy::recommended();
+	Report { results: apply_each(&policy, |builder| builder.raise()).collect() }
+}
+
+/// Same as [`raise_all_recommended`] using the given backend
+pub fn raise_all_recommended_with<B: RlimitOps>(backend: &B) -> Report {
+	let policy = Policy::recommended();
+	Report { results: apply_each(&policy, |builder| builder.raise_with(backend)).collect() }
+}
+
+/// Raise every resource limit described by the policy, stopping at the first
+/// failure
+pub fn apply_policy(policy: &Policy) -> Result<Vec<(Resource, Outcome)>, Error> {
+	apply(policy, |builder| builder.raise())
+}
+
+/// Same as [`apply_policy`] using the given backend
+pub fn apply_policy_with<B: RlimitOps>(
+	backend: &B,
+	policy: &Policy,
+) -> Result<Vec<(Resource, Outcome)>, Error> {
+	apply(policy, |builder| builder.raise_with(backend))
+}
+
+fn apply(
+	policy: &Policy,
+	raise: impl Fn(&Builder) -> Result<Outcome, Error>,
+) -> Result<Vec<(Resource, Outcome)>, Error> {
+	apply_each(policy, raise).map(|(resource, result)| Ok((resource, result?))).collect()
+}
+
+pub(crate) fn apply_each<'a>(
+	policy: &'a Policy,
+	raise: impl Fn(&Builder) -> Result<Outcome, Error> + 'a,
+) -> impl Iterator<Item = (Resource, Result<Outcome, Error>)> + 'a {
+	policy.rules.iter().map(move |(&resource, rule)| {
+		let result =
+			raise(&Builder::new().resource(resource).strategy(rule.target)).and_then(|outcome| {
+				match (outcome, rule.minimum) {
+					(Outcome::LimitRaised { to, .. }, Some(minimum)) if to < minimum => {
+						Err(Error::BelowMinimum { resource, minimum, limit: to })
+					}
+					_ => Ok(outcome),
+				}
+			});
+		(resource, result)
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::collections::HashMap;
+
+	fn from_vars(vars: &[(&str, &str)]) -> Result<Policy, Error> {
+		let vars: HashMap<_, _> = vars.iter().copied().collect();
+		Policy::from_vars(|key| vars.get(key).map(|value| value.to_string()))
+	}
+
+	#[test]
+	fn reads_variables() {
+		let policy = from_vars(&[
+			("FDLIMIT_NOFILE", "max"),
+			("FDLIMIT_NOFILE_MIN", "4k"),
+			("FDLIMIT_MEMLOCK", ">=64k"),
+			("FDLIMIT_NPROC", "50%"),
+			("FDLIMIT_CORE_MIN", "unlimited"),
+			("FDLIMIT_UNKNOWN", "1"),
+		])
+		.unwrap();
+		assert_eq!(
+			policy.rules,
+			BTreeMap::from([
+				(Resource::NoFile, Rule { target: Strategy::MaxAvailable, minimum: Some(4096) }),
+				(Resource::NProc, Rule { target: Strategy::FractionOfHard(0.5), minimum: None }),
+				(Resource::MemLock, Rule { target: Strategy::AtLeast(64 << 10), minimum: None }),
+				(
+					Resource::Core,
+					Rule {
+						target: Strategy::MaxAvailable,
+						minimum: Some(crate::backend::RLIM_INFINITY)
+					}
+				),
+			])
+		);
+		assert_eq!(from_vars(&[]).unwrap(), Policy::default());
+	}
+
+	#[test]
+	fn rejects_invalid_values() {
+		for (key, value) in [
+			("FDLIMIT_NOFILE", "lots"),
+			("FDLIMIT_NOFILE", ">=lots"),
+			("FDLIMIT_NOFILE_MIN", "4x"),
+			("FDLIMIT_NOFILE_MIN", "-1"),
+			("FDLIMIT_MEMLOCK", "much%"),
+		] {
+			assert!(
+				matches!(from_vars(&[(key, value)]), Err(Error::InvalidValue(_))),
+				"{key}={value}"
+			);
+		}
+	}
+}
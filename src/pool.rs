@@ -0,0 +1,97 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::backend::RlimitOps;
+use crate::Error;
+
+/// Kind of pool sized by [`suggest_pool_size`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum PoolKind {
+	/// Database connections: a quarter of the headroom, at most 512
+	Database,
+	/// Outgoing HTTP connections: half of the headroom, at most 4096
+	Http,
+	/// Cached open files: half of the headroom
+	Files,
+}
+
+impl PoolKind {
+	/// Divisor of the headroom giving the pool's share, and its maximum size
+	fn sizing(self) -> (u64, u64) {
+		match self {
+			PoolKind::Database => (4, 512),
+			PoolKind::Http => (2, 4096),
+			PoolKind::Files => (2, u64::MAX),
+		}
+	}
+}
+
+/// Recommended max size of a pool of the given kind, from the file descriptors
+/// left under the soft limit minus the reserved headroom, see
+/// [`fd_headroom`](crate::fd_headroom).
+///
+/// The rest of the headroom is left for the other needs of the process.
+pub fn suggest_pool_size(kind: PoolKind) -> Result<u64, Error> {
+	suggest_pool_size_with(&crate::backend::System, kind)
+}
+
+/// Same as [`suggest_pool_size`] using the given backend
+pub fn suggest_pool_size_with<B: RlimitOps>(backend: &B, kind: PoolKind) -> Result<u64, Error> {
+	let headroom = crate::fd_headroom_with(backend)?;
+	let (divisor, maximum) = kind.sizing();
+	// Rounding down could leave no room in small budgets
+	let size = (headroom / divisor).max(headroom.min(1));
+	Ok(size.min(maximum))
+}
+
+/// Descriptors each connection takes, for [`connection_budget`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionCost {
+	/// Sockets per connection, e.g. 2 for a proxy
+	pub sockets: u64,
+	/// Timers per connection, such as a `timerfd` for timeouts
+	pub timers: u64,
+	/// Event descriptors per connection, such as an `eventfd` for wakeups
+	pub events: u64,
+}
+
+impl Default for ConnectionCost {
+	/// A single socket
+	fn default() -> Self {
+		ConnectionCost { sockets: 1, timers: 0, events: 0 }
+	}
+}
+
+impl ConnectionCost {
+	/// Total descriptors per connection, at least 1
+	pub fn per_connection(&self) -> u64 {
+		self.sockets.saturating_add(self.timers).saturating_add(self.events).max(1)
+	}
+}
+
+/// Number of connections that can still be registered with epoll or kqueue
+/// given what each costs, from the file descriptors left under the soft limit
+/// minus the reserved headroom.
+pub fn connection_budget(cost: &ConnectionCost) -> Result<u64, Error> {
+	connection_budget_with(&crate::backend::System, cost)
+}
+
+/// Same as [`connection_budget`] using the given backend
+pub fn connection_budget_with<B: RlimitOps>(
+	backend: &B,
+	cost: &ConnectionCost,
+) -> Result<u64, Error> {
+	Ok(crate::fd_headroom_with(backend)? / cost.per_connection())
+}
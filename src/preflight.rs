@@ -0,0 +1,199 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::Resource;
+use std::fmt;
+
+/// Setting a requirement applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Setting {
+	/// Soft limit of the resource of the current process
+	Limit(Resource),
+	/// Hard limit of the resource of the current process, the ceiling of the
+	/// soft limit without privileges
+	HardLimit(Resource),
+	/// Kernel setting, named as for the `sysctl` command, e.g. `vm.max_map_count`
+	Sysctl(&'static str),
+}
+
+/// Minimum and recommended values of a setting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Requirement {
+	/// Setting checked
+	pub setting: Setting,
+	/// Below this the finding is an error
+	pub minimum: u64,
+	/// Below this the finding is a warning
+	pub recommended: u64,
+}
+
+/// How much a finding matters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+	/// The setting meets the recommendation
+	Ok,
+	/// The setting works but is below the recommendation, or couldn't be read
+	Warning,
+	/// The setting is below the minimum
+	Error,
+}
+
+/// Result of checking one requirement
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+	/// How much the finding matters
+	pub severity: Severity,
+	/// Setting checked
+	pub setting: Setting,
+	/// Value found, `None` if it couldn't be read
+	pub observed: Option<u64>,
+	/// Value that wasn't met, the recommendation when the finding is ok
+	pub required: u64,
+	/// How to fix the setting, `None` when the finding is ok
+	pub remediation: Option<String>,
+}
+
+/// Check the current settings against the requirements, in order
+pub fn preflight(requirements: &[Requirement]) -> Vec<Finding> {
+	requirements.iter().map(check).collect()
+}
+
+fn check(requirement: &Requirement) -> Finding {
+	let Requirement { setting, minimum, recommended } = *requirement;
+	let observed = match setting {
+		Setting::Limit(resource) => crate::get_limit(resource).ok().map(|rlim| rlim.soft),
+		Setting::HardLimit(resource) => crate::get_limit(resource).ok().map(|rlim| rlim.hard),
+		Setting::Sysctl(name) => read_sysctl(name),
+	};
+	let (severity, required) = match observed {
+		Some(observed) if observed < minimum => (Severity::Error, minimum),
+		Some(observed) if observed >= recommended => (Severity::Ok, recommended),
+		_ => (Severity::Warning, recommended),
+	};
+	let remediation = (severity != Severity::Ok).then(|| match setting {
+		Setting::Limit(resource) | Setting::HardLimit(resource) => {
+			crate::remediation(resource, crate::Limit::Finite(required)).to_string()
+		}
+		Setting::Sysctl(name) => sysctl_command(name, required).join(" "),
+	});
+
+	Finding { severity, setting, observed, required, remediation }
+}
+
+/// `sysctl` command setting the value, to be run as root. Linux needs `-w`,
+/// the BSD `sysctl` of macOS and FreeBSD takes `name=value` alone.
+pub fn sysctl_command(name: &str, value: u64) -> Vec<String> {
+	let mut command = vec!["sysctl".to_owned()];
+	if cfg!(target_os = "linux") {
+		command.push("-w".to_owned());
+	}
+	command.push(format!("{name}={value}"));
+	command
+}
+
+#[cfg(target_os = "linux")]
+fn read_sysctl(name: &str) -> Option<u64> {
+	let value = std::fs::read_to_string(format!("/proc/sys/{}", name.replace('.', "/"))).ok()?;
+	value.trim().parse().ok()
+}
+
+#[cfg(any(target_vendor = "apple", target_os = "freebsd"))]
+fn read_sysctl(name: &str) -> Option<u64> {
+	let name = std::ffi::CString::new(name).ok()?;
+	crate::backend::sysctl_int(&name).ok().and_then(|value| crate::backend::from_c_int(value).ok())
+}
+
+#[cfg(not(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd")))]
+fn read_sysctl(_name: &str) -> Option<u64> {
+	None
+}
+
+/// Findings of a preflight, printed one per line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Summary {
+	/// Findings in the order the requirements were checked
+	pub findings: Vec<Finding>,
+}
+
+impl Summary {
+	/// Worst severity of all findings
+	pub fn severity(&self) -> Severity {
+		self.findings.iter().map(|finding| finding.severity).max().unwrap_or(Severity::Ok)
+	}
+}
+
+impl fmt::Display for Setting {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Setting::Limit(resource) => write!(f, "{resource} limit"),
+			Setting::HardLimit(resource) => write!(f, "{resource} hard limit"),
+			Setting::Sysctl(name) => f.write_str(name),
+		}
+	}
+}
+
+impl fmt::Display for Severity {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.pad(match self {
+			Severity::Ok => "ok",
+			Severity::Warning => "warning",
+			Severity::Error => "error",
+		})
+	}
+}
+
+/// For example `[warning] vm.max_map_count: 65530 (required 262144), sysctl -w
+/// vm.max_map_count=262144`
+impl fmt::Display for Finding {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "[{}] {}: ", self.severity, self.setting)?;
+		match self.observed {
+			Some(observed) => write!(f, "{observed}")?,
+			None => f.write_str("unknown")?,
+		}
+		write!(f, " (required {})", self.required)?;
+		if let Some(remediation) = &self.remediation {
+			write!(f, ", {remediation}")?;
+		}
+		Ok(())
+	}
+}
+
+impl fmt::Display for Summary {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for finding in &self.findings {
+			writeln!(f, "{finding}")?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+mod tests {
+	use super::*;
+
+	#[test]
+	#[cfg(target_os = "linux")]
+	fn sysctl_command_writes() {
+		assert_eq!(sysctl_command("fs.nr_open", 1024), ["sysctl", "-w", "fs.nr_open=1024"]);
+	}
+
+	#[test]
+	#[cfg(any(target_vendor = "apple", target_os = "freebsd"))]
+	fn sysctl_command_sets() {
+		let command = sysctl_command("kern.maxfilesperproc", 1024);
+		assert_eq!(command, ["sysctl", "kern.maxfilesperproc=1024"]);
+	}
+}
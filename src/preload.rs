@@ -0,0 +1,227 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Spawning children with the `fdlimit-preload` library injected, so the
+//! descriptors opened anywhere in their process tree are tracked whatever
+//! language they're written in.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::unix::net::UnixDatagram;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Child, Command};
+
+/// Child process reporting the descriptors it opens and closes.
+///
+/// Only descriptors opened after the library was loaded are counted, not the
+/// inherited ones such as the standard streams. The children never block on
+/// the report socket, they drop their reports when its buffer is full, so
+/// [`update`](Self::update) must be called regularly and the counts may be off
+/// by the [`lost`](Self::lost) reports.
+#[derive(Debug)]
+pub struct PreloadChild {
+	child: Child,
+	socket: UnixDatagram,
+	processes: BTreeMap<u32, Process>,
+	/// Reports lost by processes that exited
+	lost: u64,
+}
+
+/// Reports of a process of the tree
+#[derive(Debug, Default)]
+struct Process {
+	open: BTreeSet<i32>,
+	received: u64,
+	/// One past the highest sequence number received
+	sent: u64,
+}
+
+impl Process {
+	fn lost(&self) -> u64 {
+		self.sent.saturating_sub(self.received)
+	}
+}
+
+/// Spawn the command with the `fdlimit-preload` shared library at the given
+/// path preloaded
+pub fn spawn_with_preload(
+	command: &mut Command,
+	library: impl AsRef<Path>,
+) -> io::Result<PreloadChild> {
+	let (socket, child_socket) = UnixDatagram::pair()?;
+	socket.set_nonblocking(true)?;
+	let child_fd = child_socket.as_raw_fd();
+
+	let mut preload = library.as_ref().as_os_str().to_owned();
+	if let Some(existing) = std::env::var_os("LD_PRELOAD") {
+		preload.push(":");
+		preload.push(existing);
+	}
+	command.env("LD_PRELOAD", preload).env("FDLIMIT_PRELOAD_FD", child_fd.to_string());
+	// The socket is close-on-exec, keep it open in the child
+	unsafe {
+		command.pre_exec(move || {
+			if libc::fcntl(child_fd, libc::F_SETFD, 0) != 0 {
+				return Err(io::Error::last_os_error());
+			}
+			Ok(())
+		});
+	}
+
+	let child = command.spawn()?;
+	Ok(PreloadChild { child, socket, processes: BTreeMap::new(), lost: 0 })
+}
+
+impl PreloadChild {
+	/// Spawned child process
+	pub fn child(&mut self) -> &mut Child {
+		&mut self.child
+	}
+
+	/// Process the reports received so far, forgetting processes that exited
+	pub fn update(&mut self) -> io::Result<()> {
+		let mut record = [0u8; 20];
+		loop {
+			match self.socket.recv(&mut record) {
+				Ok(20) => {}
+				Ok(_) => continue,
+				Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+				Err(e) => return Err(e),
+			}
+
+			let pid = u32::from_ne_bytes(record[..4].try_into().expect("4 bytes"));
+			let fd = i32::from_ne_bytes(record[4..8].try_into().expect("4 bytes"));
+			let opened = u32::from_ne_bytes(record[8..12].try_into().expect("4 bytes")) != 0;
+			let seq = u64::from_ne_bytes(record[12..].try_into().expect("8 bytes"));
+			let process = self.processes.entry(pid).or_default();
+			// Threads may send out of order, count the gaps once all arrived
+			process.received += 1;
+			process.sent = process.sent.max(seq.saturating_add(1));
+			if opened {
+				process.open.insert(fd);
+			} else {
+				process.open.remove(&fd);
+			}
+		}
+
+		// Exiting closes the descriptors without reporting them
+		let lost = &mut self.lost;
+		self.processes.retain(|&pid, process| {
+			let alive = is_alive(pid);
+			if !alive {
+				*lost += process.lost();
+			}
+			alive
+		});
+		Ok(())
+	}
+
+	/// Number of tracked descriptors open in each process of the tree, as of
+	/// the last [`update`](Self::update)
+	pub fn usage(&self) -> impl Iterator<Item = (u32, u64)> + '_ {
+		self.processes.iter().map(|(&pid, process)| (pid, process.open.len() as u64))
+	}
+
+	/// Number of tracked descriptors open in the whole tree
+	pub fn total(&self) -> u64 {
+		self.usage().map(|(_, open)| open).sum()
+	}
+
+	/// Number of reports the processes of the tree dropped because the socket
+	/// was full, as of the last [`update`](Self::update). A drop is only noticed
+	/// once a later report of the same process arrives.
+	pub fn lost(&self) -> u64 {
+		self.lost + self.processes.values().map(Process::lost).sum::<u64>()
+	}
+}
+
+fn is_alive(pid: u32) -> bool {
+	let Ok(pid) = libc::pid_t::try_from(pid) else { return false };
+	// EPERM means the process exists but belongs to someone else
+	let alive = unsafe { libc::kill(pid, 0) } == 0;
+	alive || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::path::PathBuf;
+	use std::process::Stdio;
+	use std::time::{Duration, Instant};
+
+	/// Build the preload library, in its own target directory so the build
+	/// doesn't wait for the lock of the one running the tests
+	fn library() -> PathBuf {
+		let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+		let target = root.join("target").join("preload-test");
+		let status = Command::new(env!("CARGO"))
+			.args(["build", "--quiet", "-p", "fdlimit-preload", "--target-dir"])
+			.arg(&target)
+			.current_dir(root)
+			.status()
+			.unwrap();
+		assert!(status.success());
+		target.join("debug").join("libfdlimit_preload.so")
+	}
+
+	fn record(pid: u32, fd: i32, opened: bool, seq: u64) -> [u8; 20] {
+		let mut record = [0; 20];
+		record[..4].copy_from_slice(&pid.to_ne_bytes());
+		record[4..8].copy_from_slice(&fd.to_ne_bytes());
+		record[8..12].copy_from_slice(&u32::from(opened).to_ne_bytes());
+		record[12..].copy_from_slice(&seq.to_ne_bytes());
+		record
+	}
+
+	#[test]
+	fn counts_lost_reports() {
+		let (socket, sender) = UnixDatagram::pair().unwrap();
+		socket.set_nonblocking(true).unwrap();
+		let child = Command::new("true").spawn().unwrap();
+		let mut tracked = PreloadChild { child, socket, processes: BTreeMap::new(), lost: 0 };
+
+		// This process is alive for the whole test, the reports look like its own
+		let pid = std::process::id();
+		for (fd, opened, seq) in [(5, true, 0), (6, true, 1), (5, false, 4), (7, true, 3)] {
+			sender.send(&record(pid, fd, opened, seq)).unwrap();
+		}
+		tracked.update().unwrap();
+		assert_eq!(tracked.usage().collect::<Vec<_>>(), [(pid, 2)]);
+		assert_eq!(tracked.lost(), 1);
+		tracked.child().wait().unwrap();
+	}
+
+	#[test]
+	fn tracks_the_descriptors_of_the_child() {
+		let mut command = Command::new("tail");
+		command.args(["-f", "/proc/self/status"]).stdout(Stdio::null());
+		let mut tracked = spawn_with_preload(&mut command, library()).unwrap();
+
+		let deadline = Instant::now() + Duration::from_secs(10);
+		while tracked.total() == 0 && Instant::now() < deadline {
+			std::thread::sleep(Duration::from_millis(20));
+			tracked.update().unwrap();
+		}
+		let pid = tracked.child().id();
+		assert!(tracked.usage().any(|(other, open)| other == pid && open > 0));
+		assert_eq!(tracked.lost(), 0);
+
+		tracked.child().kill().unwrap();
+		tracked.child().wait().unwrap();
+		tracked.update().unwrap();
+		assert_eq!(tracked.total(), 0);
+	}
+}
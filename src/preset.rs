@@ -0,0 +1,172 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::backend::RlimitOps;
+use crate::{Error, Outcome, Policy, Requirement, Resource, Rule, Strategy, Summary};
+
+/// Limits suited to a common workload shape, see [`apply_preset`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Preset {
+	/// Database server, many open table files and locked memory
+	Database,
+	/// Reverse proxy or load balancer, two sockets per proxied connection
+	Proxy,
+	/// Peer-to-peer node, many peer connections and database files
+	P2pNode,
+}
+
+/// Target and minimum of a resource in a preset
+type Entry = (Resource, Strategy, Option<u64>);
+
+// Tune the values here, the presets only expose the resulting policies
+const DATABASE: &[Entry] = &[
+	(Resource::NoFile, Strategy::MaxAvailable, Some(65536)),
+	(Resource::NProc, Strategy::MaxAvailable, Some(4096)),
+	(Resource::MemLock, Strategy::MaxAvailable, None),
+];
+const PROXY: &[Entry] = &[
+	(Resource::NoFile, Strategy::MaxAvailable, Some(65536)),
+	(Resource::NProc, Strategy::MaxAvailable, None),
+];
+const P2P_NODE: &[Entry] = &[
+	(Resource::NoFile, Strategy::MaxAvailable, Some(16384)),
+	(Resource::NProc, Strategy::MaxAvailable, None),
+];
+
+/// Requirement on a kernel setting, checked by [`preset_preflight`]
+#[cfg(target_os = "linux")]
+const fn sysctl(name: &'static str, minimum: u64, recommended: u64) -> Requirement {
+	Requirement { setting: crate::Setting::Sysctl(name), minimum, recommended }
+}
+
+#[cfg(target_os = "linux")]
+const DATABASE_SYSCTLS: &[Requirement] = &[sysctl("vm.max_map_count", 65_530, 262_144)];
+#[cfg(target_os = "linux")]
+const PROXY_SYSCTLS: &[Requirement] =
+	&[sysctl("net.core.somaxconn", 128, 4096), sysctl("net.ipv4.tcp_max_syn_backlog", 128, 4096)];
+#[cfg(target_os = "linux")]
+const P2P_NODE_SYSCTLS: &[Requirement] = &[
+	sysctl("fs.inotify.max_user_watches", 8192, 524_288),
+	sysctl("fs.inotify.max_user_instances", 128, 512),
+	sysctl("net.core.somaxconn", 128, 1024),
+];
+// The settings are named differently elsewhere, if they exist
+#[cfg(not(target_os = "linux"))]
+const DATABASE_SYSCTLS: &[Requirement] = &[];
+#[cfg(not(target_os = "linux"))]
+const PROXY_SYSCTLS: &[Requirement] = &[];
+#[cfg(not(target_os = "linux"))]
+const P2P_NODE_SYSCTLS: &[Requirement] = &[];
+
+impl Preset {
+	/// Every preset
+	pub const ALL: &'static [Preset] = &[Preset::Database, Preset::Proxy, Preset::P2pNode];
+
+	/// Policy raising the limits of the preset, failing when they end up below
+	/// the minimums the workload needs
+	pub fn policy(self) -> Policy {
+		let entries = match self {
+			Preset::Database => DATABASE,
+			Preset::Proxy => PROXY,
+			Preset::P2pNode => P2P_NODE,
+		};
+		let rules = entries
+			.iter()
+			.map(|&(resource, target, minimum)| (resource, Rule { target, minimum }))
+			.collect();
+		Policy { rules }
+	}
+
+	/// Kernel settings the workload needs that no limit covers, such as the
+	/// inotify limits of a peer-to-peer node, on Linux only
+	pub fn requirements(self) -> &'static [Requirement] {
+		match self {
+			Preset::Database => DATABASE_SYSCTLS,
+			Preset::Proxy => PROXY_SYSCTLS,
+			Preset::P2pNode => P2P_NODE_SYSCTLS,
+		}
+	}
+}
+
+/// Check the [requirements](Preset::requirements) of the preset, meant to be
+/// printed at startup after [`apply_preset`]
+pub fn preset_preflight(preset: Preset) -> Summary {
+	Summary { findings: crate::preflight(preset.requirements()) }
+}
+
+/// Raise the limits of the preset, stopping at the first failure
+pub fn apply_preset(preset: Preset) -> Result<Vec<(Resource, Outcome)>, Error> {
+	crate::apply_policy(&preset.policy())
+}
+
+/// Same as [`apply_preset`] using the given backend
+pub fn apply_preset_with<B: RlimitOps>(
+	backend: &B,
+	preset: Preset,
+) -> Result<Vec<(Resource, Outcome)>, Error> {
+	crate::apply_policy_with(backend, &preset.policy())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::backend::Rlimit;
+	use crate::simulated::Simulated;
+	#[cfg(target_os = "linux")]
+	use crate::Setting;
+
+	#[test]
+	fn applies_the_policy() {
+		let backend = Simulated::new(1024, 1 << 20).with_limit(Resource::NProc, 100, 8192);
+		let outcomes = apply_preset_with(&backend, Preset::Proxy).unwrap();
+		assert_eq!(
+			outcomes,
+			[
+				(Resource::NoFile, Outcome::LimitRaised { from: 1024, to: 1 << 20 }),
+				(Resource::NProc, Outcome::LimitRaised { from: 100, to: 8192 }),
+			]
+		);
+		assert_eq!(backend.limit(Resource::NProc), Some(Rlimit { soft: 8192, hard: 8192 }));
+	}
+
+	#[test]
+	fn fails_below_the_minimum() {
+		let backend = Simulated::new(1024, 4096).with_limit(Resource::NProc, 100, 8192);
+		let error = apply_preset_with(&backend, Preset::P2pNode).unwrap_err();
+		assert!(matches!(
+			error,
+			Error::BelowMinimum { resource: Resource::NoFile, minimum: 16384, limit: 4096 }
+		));
+	}
+
+	#[test]
+	#[cfg(target_os = "linux")]
+	fn requires_kernel_settings() {
+		let settings = |preset: Preset| -> Vec<_> {
+			preset.requirements().iter().map(|requirement| requirement.setting).collect()
+		};
+		assert!(settings(Preset::P2pNode).contains(&Setting::Sysctl("fs.inotify.max_user_watches")));
+		assert!(settings(Preset::Database).contains(&Setting::Sysctl("vm.max_map_count")));
+		for &preset in Preset::ALL {
+			let requirements = preset.requirements();
+			assert!(!requirements.is_empty());
+			assert!(requirements.iter().all(|r| r.minimum <= r.recommended));
+
+			let summary = preset_preflight(preset);
+			let checked: Vec<_> = summary.findings.iter().map(|finding| finding.setting).collect();
+			assert_eq!(checked, settings(preset));
+		}
+	}
+}
@@ -0,0 +1,103 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Raising limits while privileged, before switching to an unprivileged user.
+
+use crate::backend::Rlimit;
+use crate::{Error, Policy, Resource};
+use std::io;
+
+/// Limits of a resource before and after dropping privileges
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DropEntry {
+	/// Resource of the limits
+	pub resource: Resource,
+	/// Limits once raised, while still privileged
+	pub before: Rlimit,
+	/// Limits after dropping privileges
+	pub after: Rlimit,
+}
+
+impl DropEntry {
+	/// Whether the soft or the hard limit went down
+	pub fn is_lost(&self) -> bool {
+		self.after.soft < self.before.soft || self.after.hard < self.before.hard
+	}
+}
+
+/// Limits of each resource of the policy around a privilege drop
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DropReport {
+	/// Limits of each resource, in the policy's order
+	pub entries: Vec<DropEntry>,
+}
+
+impl DropReport {
+	/// Whether every limit survived the drop
+	pub fn is_ok(&self) -> bool {
+		self.entries.iter().all(|entry| !entry.is_lost())
+	}
+
+	/// Resources whose limits went down
+	pub fn lost(&self) -> impl Iterator<Item = &DropEntry> + '_ {
+		self.entries.iter().filter(|entry| entry.is_lost())
+	}
+}
+
+/// Raise the limits of the policy, then drop privileges with `drop` and
+/// fetch the limits again to check they survived.
+///
+/// Limits are kept across `setuid` itself, but they're lost when `drop` also
+/// applies the new user's limits, for example by opening a PAM session with
+/// `pam_limits` or calling `setusercontext` with `LOGIN_SETRESOURCES`, and the
+/// unprivileged process can't raise its hard limits back. Since the limits are
+/// checked by this process once `drop` returns, a `drop` that re-executes it
+/// never gets them checked.
+pub fn raise_across_privilege_drop(
+	policy: &Policy,
+	drop: impl FnOnce() -> io::Result<()>,
+) -> Result<DropReport, Error> {
+	crate::apply_policy(policy)?;
+	let before = policy
+		.rules
+		.keys()
+		.map(|&resource| Ok((resource, crate::get_limit(resource)?)))
+		.collect::<Result<Vec<_>, Error>>()?;
+	drop().map_err(Error::FailedToDropPrivileges)?;
+	let entries = before
+		.into_iter()
+		.map(|(resource, before)| {
+			Ok(DropEntry { resource, before, after: crate::get_limit(resource)? })
+		})
+		.collect::<Result<_, Error>>()?;
+
+	Ok(DropReport { entries })
+}
+
+/// Switch to the given user and group, leaving the supplementary groups when
+/// running as root, for use with [`raise_across_privilege_drop`]
+pub fn drop_privileges(uid: u32, gid: u32) -> io::Result<()> {
+	let check = |result: libc::c_int| match result {
+		0 => Ok(()),
+		_ => Err(io::Error::last_os_error()),
+	};
+	unsafe {
+		if libc::geteuid() == 0 {
+			check(libc::setgroups(1, &gid))?;
+		}
+		// The group goes first, an unprivileged user can't change it anymore
+		check(libc::setgid(gid))?;
+		check(libc::setuid(uid))
+	}
+}
@@ -0,0 +1,51 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// Capability allowing to raise hard limits on Linux
+#[cfg(target_os = "linux")]
+const CAP_SYS_RESOURCE: u32 = 24;
+
+/// Whether the process is allowed to raise its hard limits, checked without
+/// trying to.
+///
+/// On Linux this takes `CAP_SYS_RESOURCE` in the effective set, which root has
+/// unless a container runtime dropped it, falling back to the effective user
+/// being root when /proc is missing. The capability only counts in the
+/// initial [user namespace](crate::user_namespace), and the open files hard
+/// limit still can't go above `fs.nr_open`. Other Unix platforms need the effective user to be
+/// root, and Windows has no hard limits to raise.
+pub fn can_raise_hard_limit() -> bool {
+	if crate::user_namespace().is_some() {
+		return false;
+	}
+
+	#[cfg(target_os = "linux")]
+	if let Some(effective) = effective_capabilities() {
+		return effective & (1 << CAP_SYS_RESOURCE) != 0;
+	}
+
+	#[cfg(unix)]
+	return unsafe { libc::geteuid() } == 0;
+
+	#[cfg(not(unix))]
+	false
+}
+
+/// Effective capability set of the process, from /proc/self/status
+#[cfg(target_os = "linux")]
+fn effective_capabilities() -> Option<u64> {
+	let status = std::fs::read_to_string("/proc/self/status").ok()?;
+	let caps = status.lines().find_map(|line| line.strip_prefix("CapEff:"))?;
+	u64::from_str_radix(caps.trim(), 16).ok()
+}
@@ -0,0 +1,192 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Parsing of the Linux `/proc/<pid>/limits` table.
+
+use crate::backend::{Rlimit, RlimitOps, RLIM_INFINITY};
+use crate::{Error, Resource};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Limits of every resource as listed in `/proc/<pid>/limits`.
+///
+/// Rows for resources this crate doesn't know are skipped.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProcLimits {
+	limits: BTreeMap<Resource, Rlimit>,
+}
+
+/// Resource whose limits in `/proc/self/limits` differ from `getrlimit`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Discrepancy {
+	/// Resource in question
+	pub resource: Resource,
+	/// Limits listed in `/proc/self/limits`
+	pub proc: Rlimit,
+	/// Limits returned by `getrlimit`
+	pub getrlimit: Rlimit,
+}
+
+impl ProcLimits {
+	/// Read the limits of the current process
+	pub fn current() -> Result<Self, Error> {
+		Self::read("/proc/self/limits")
+	}
+
+	/// Read a limits table at the given path
+	pub(crate) fn read(path: impl AsRef<Path>) -> Result<Self, Error> {
+		std::fs::read_to_string(path).map_err(Error::FailedToReadProc)?.parse()
+	}
+
+	/// Limits of the resource, if listed
+	pub fn get(&self, resource: Resource) -> Option<Rlimit> {
+		self.limits.get(&resource).copied()
+	}
+
+	/// Iterate over the listed resources and their limits
+	pub fn iter(&self) -> impl Iterator<Item = (Resource, Rlimit)> + '_ {
+		self.limits.iter().map(|(&resource, &rlim)| (resource, rlim))
+	}
+
+	/// Compare against the limits returned by `getrlimit`, some sandboxes make
+	/// them disagree
+	pub fn cross_check(&self) -> Result<Vec<Discrepancy>, Error> {
+		self.cross_check_with(&crate::backend::System)
+	}
+
+	/// Same as [`cross_check`](Self::cross_check) using the given backend
+	pub fn cross_check_with<B: RlimitOps>(&self, backend: &B) -> Result<Vec<Discrepancy>, Error> {
+		let mut discrepancies = Vec::new();
+		for (resource, proc) in self.iter() {
+			let getrlimit = crate::get_limit_with(backend, resource)?;
+			if proc != getrlimit {
+				discrepancies.push(Discrepancy { resource, proc, getrlimit });
+			}
+		}
+
+		Ok(discrepancies)
+	}
+}
+
+impl FromIterator<(Resource, Rlimit)> for ProcLimits {
+	fn from_iter<I: IntoIterator<Item = (Resource, Rlimit)>>(iter: I) -> Self {
+		ProcLimits { limits: iter.into_iter().collect() }
+	}
+}
+
+impl std::str::FromStr for ProcLimits {
+	type Err = Error;
+
+	fn from_str(s: &str) -> Result<Self, Error> {
+		let invalid = |line: &str| Error::InvalidValue(line.to_owned());
+
+		// Columns are aligned to the header
+		let mut lines = s.lines();
+		let header = lines.next().ok_or_else(|| invalid(s))?;
+		let soft_at = header.find("Soft Limit").ok_or_else(|| invalid(header))?;
+		let hard_at = header.find("Hard Limit").ok_or_else(|| invalid(header))?;
+		let units_at = header.find("Units").unwrap_or(usize::MAX);
+
+		let mut limits = BTreeMap::new();
+		for line in lines.filter(|line| !line.trim().is_empty()) {
+			let column = |from: usize, to: usize| line.get(from..to.min(line.len())).map(str::trim);
+			let (Some(name), Some(soft), Some(hard)) =
+				(column(0, soft_at), column(soft_at, hard_at), column(hard_at, units_at))
+			else {
+				return Err(invalid(line));
+			};
+			let Some(resource) = resource_of(name) else { continue };
+
+			let value = |value: &str| match value {
+				"unlimited" => Ok(RLIM_INFINITY),
+				value => value.parse().map_err(|_| invalid(line)),
+			};
+			limits.insert(resource, Rlimit { soft: value(soft)?, hard: value(hard)? });
+		}
+
+		Ok(ProcLimits { limits })
+	}
+}
+
+fn resource_of(name: &str) -> Option<Resource> {
+	Some(match name {
+		"Max cpu time" => Resource::Cpu,
+		"Max file size" => Resource::FSize,
+		"Max data size" => Resource::Data,
+		"Max stack size" => Resource::Stack,
+		"Max core file size" => Resource::Core,
+		"Max resident set" => Resource::Rss,
+		"Max processes" => Resource::NProc,
+		"Max open files" => Resource::NoFile,
+		"Max locked memory" => Resource::MemLock,
+		"Max address space" => Resource::As,
+		"Max msgqueue size" => Resource::MsgQueue,
+		"Max nice priority" => Resource::Nice,
+		"Max file locks" => Resource::Locks,
+		"Max realtime priority" => Resource::RtPrio,
+		"Max realtime timeout" => Resource::RtTime,
+		"Max pending signals" => Resource::SigPending,
+		_ => return None,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const LIMITS: &str = "\
+Limit                     Soft Limit           Hard Limit           Units     
+Max cpu time              unlimited            unlimited            seconds   
+Max stack size            8388608              unlimited            bytes     
+Max processes             63304                63304                processes 
+Max open files            1024                 524288               files     
+Max nice priority         0                    0                    
+Max realtime timeout      unlimited            unlimited            us        
+";
+
+	#[test]
+	fn parses_limits() {
+		let limits: ProcLimits = LIMITS.parse().unwrap();
+		assert_eq!(
+			limits.iter().collect::<Vec<_>>(),
+			vec![
+				(Resource::NoFile, Rlimit { soft: 1024, hard: 524288 }),
+				(Resource::NProc, Rlimit { soft: 63304, hard: 63304 }),
+				(Resource::Stack, Rlimit { soft: 8388608, hard: RLIM_INFINITY }),
+				(Resource::Cpu, Rlimit { soft: RLIM_INFINITY, hard: RLIM_INFINITY }),
+				(Resource::Nice, Rlimit { soft: 0, hard: 0 }),
+				(Resource::RtTime, Rlimit { soft: RLIM_INFINITY, hard: RLIM_INFINITY }),
+			]
+		);
+		assert_eq!(limits.get(Resource::Core), None);
+	}
+
+	#[test]
+	fn skips_unknown_rows() {
+		let unknown =
+			"Max widgets               3                    4                    widgets   ";
+		let limits: ProcLimits = format!("{LIMITS}{unknown}\n").parse().unwrap();
+		assert_eq!(limits, LIMITS.parse().unwrap());
+	}
+
+	#[test]
+	fn rejects_invalid_tables() {
+		let invalid = LIMITS.replace("1024     ", "1k       ");
+		assert!(matches!(invalid.parse::<ProcLimits>(), Err(Error::InvalidValue(_))));
+
+		let headless = LIMITS.lines().skip(1).collect::<Vec<_>>().join("\n");
+		assert!(matches!(headless.parse::<ProcLimits>(), Err(Error::InvalidValue(_))));
+		assert!(matches!("".parse::<ProcLimits>(), Err(Error::InvalidValue(_))));
+	}
+}
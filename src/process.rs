@@ -0,0 +1,334 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Limits and usage of other processes, read through procfs and `prlimit`
+//! without needing to ptrace them.
+
+use crate::backend::{Rlimit, RlimitOps, System};
+use crate::{Error, HardLimitLowering, Limit, Outcome, Policy, ProcLimits, Resource};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Open file descriptors of a process against its limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProcessUsage {
+	/// Process ID
+	pub pid: u32,
+	/// Number of open file descriptors
+	pub open_fds: u64,
+	/// Open files limits of the process
+	pub limit: Rlimit,
+}
+
+impl ProcessUsage {
+	/// Open descriptors divided by the soft limit, 0 when it's unlimited and 1
+	/// when it's 0. Above 1 when the limit was lowered below the descriptors
+	/// already open.
+	pub fn ratio(&self) -> f64 {
+		match self.limit.soft_limit() {
+			Limit::Finite(0) => 1.0,
+			Limit::Finite(soft) => self.open_fds as f64 / soft as f64,
+			Limit::Unlimited => 0.0,
+		}
+	}
+}
+
+/// Fetch the limits of every resource of the process.
+///
+/// `/proc/<pid>/limits` is read first, when procfs isn't mounted the limits
+/// are queried one by one with `prlimit`, which needs the same permissions as
+/// sending a signal to the process.
+pub fn limits_for_pid(pid: u32) -> Result<ProcLimits, Error> {
+	match ProcLimits::read(format!("/proc/{pid}/limits")) {
+		Ok(limits) => Ok(limits),
+		Err(Error::FailedToReadProc(_)) => Resource::ALL
+			.iter()
+			.filter(|resource| resource.is_available())
+			.map(|&resource| {
+				crate::backend::prlimit(pid, resource, None)
+					.map(|rlim| (resource, rlim))
+					.map_err(Error::FailedToGetLimit)
+			})
+			.collect(),
+		Err(error) => Err(error),
+	}
+}
+
+/// Set the soft and hard limits of the resource of the process with
+/// `prlimit`, returning the previous limits.
+///
+/// Raising the hard limit, or changing the limits of a process running under
+/// other user or group IDs, needs `CAP_SYS_RESOURCE`. Lowering the hard limit
+/// fails unless `lowering` is [`HardLimitLowering::Allow`].
+pub fn set_limits_for_pid(
+	pid: u32,
+	resource: Resource,
+	rlim: Rlimit,
+	lowering: HardLimitLowering,
+) -> Result<Rlimit, Error> {
+	let current =
+		crate::backend::prlimit(pid, resource, None).map_err(|error| match error.kind() {
+			std::io::ErrorKind::Unsupported => Error::ResourceNotAvailable(resource),
+			_ => Error::FailedToGetLimit(error),
+		})?;
+	crate::setter::check_lowering(resource, current.hard, rlim.hard, lowering)?;
+	let previous = crate::backend::prlimit(pid, resource, Some(rlim)).map_err(|error| {
+		crate::setter::set_limit_error(resource, current, rlim, current.soft, rlim.soft, error)
+	})?;
+	crate::audit::record(Some(pid), resource, previous, rlim);
+	Ok(previous)
+}
+
+/// Number of file descriptors the process has open, counted from
+/// `/proc/<pid>/fd`
+pub fn fd_usage_for_pid(pid: u32) -> Result<u64, Error> {
+	let entries = std::fs::read_dir(format!("/proc/{pid}/fd")).map_err(Error::FailedToCountFds)?;
+	Ok(entries.count() as u64)
+}
+
+/// File descriptor open in a process
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenFd {
+	/// Descriptor number
+	pub fd: u32,
+	/// What the descriptor refers to, a path or a description such as
+	/// `socket:[1234]`, `pipe:[1234]` or `anon_inode:[eventfd]`
+	pub target: PathBuf,
+}
+
+/// List the file descriptors the process has open, ordered by number
+pub fn open_fds_for_pid(pid: u32) -> Result<Vec<OpenFd>, Error> {
+	let dir = format!("/proc/{pid}/fd");
+	let mut fds = Vec::new();
+	for entry in std::fs::read_dir(&dir).map_err(Error::FailedToCountFds)? {
+		let entry = entry.map_err(Error::FailedToCountFds)?;
+		let Some(fd) = entry.file_name().to_str().and_then(|name| name.parse().ok()) else {
+			continue;
+		};
+		// The descriptor may be closed between listing and reading the link
+		if let Ok(target) = std::fs::read_link(entry.path()) {
+			fds.push(OpenFd { fd, target });
+		}
+	}
+
+	fds.sort_by_key(|open_fd| open_fd.fd);
+	Ok(fds)
+}
+
+/// Walk `/proc` and return the `top` processes using the largest share of their
+/// soft open files limit, highest first.
+///
+/// Processes that can't be inspected, because they exited during the scan or
+/// belong to another user, are skipped.
+pub fn scan_processes(top: usize) -> Result<Vec<ProcessUsage>, Error> {
+	let mut usages = Vec::new();
+	for pid in pids()? {
+		let Ok(open_fds) = fd_usage_for_pid(pid) else { continue };
+		let Some(limit) = nofile_limit(pid) else { continue };
+		usages.push(ProcessUsage { pid, open_fds, limit });
+	}
+
+	usages.sort_by(|a, b| b.ratio().total_cmp(&a.ratio()));
+	usages.truncate(top);
+	Ok(usages)
+}
+
+/// Open files limits and usage of a process, see [`all_process_limits`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProcessLimits {
+	/// Process ID
+	pub pid: u32,
+	/// Open files limits of the process
+	pub limit: Rlimit,
+	/// Number of open file descriptors, `None` when not allowed to list them
+	pub open_fds: Option<u64>,
+}
+
+/// Open files limits of every visible process, with their usage where it can
+/// be read, ordered by PID.
+///
+/// Limits are readable by everyone, listing the descriptors of processes of
+/// other users needs `CAP_SYS_PTRACE`. Processes exiting during the scan, or
+/// hidden by procfs' `hidepid` option, are left out.
+pub fn all_process_limits() -> Result<Vec<ProcessLimits>, Error> {
+	let mut processes = Vec::new();
+	for pid in pids()? {
+		let Some(limit) = nofile_limit(pid) else { continue };
+		processes.push(ProcessLimits { pid, limit, open_fds: fd_usage_for_pid(pid).ok() });
+	}
+
+	processes.sort_by_key(|process| process.pid);
+	Ok(processes)
+}
+
+/// Backend changing the limits of another process with `prlimit`, for
+/// [`Builder::raise_with`](crate::Builder::raise_with) and
+/// [`apply_policy_with`](crate::apply_policy_with).
+///
+/// Kernel ceilings are the system's, usage is counted from `/proc/<pid>/fd`.
+/// The [`on_raise`](crate::on_raise) callbacks fire for its changes as well.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Prlimit(pub u32);
+
+impl RlimitOps for Prlimit {
+	fn getrlimit(&self, resource: Resource) -> io::Result<Rlimit> {
+		crate::backend::prlimit(self.0, resource, None)
+	}
+
+	fn setrlimit(&self, resource: Resource, rlim: Rlimit) -> io::Result<()> {
+		crate::backend::prlimit(self.0, resource, Some(rlim)).map(drop)
+	}
+
+	fn swap_rlimit(&self, resource: Resource, rlim: Rlimit) -> io::Result<Option<Rlimit>> {
+		crate::backend::prlimit(self.0, resource, Some(rlim)).map(Some)
+	}
+
+	fn sysctl_maxfiles(&self) -> io::Result<Option<u64>> {
+		System.sysctl_maxfiles()
+	}
+
+	fn system_maxfiles(&self) -> io::Result<Option<u64>> {
+		System.system_maxfiles()
+	}
+
+	fn open_fds(&self) -> io::Result<u64> {
+		Ok(std::fs::read_dir(format!("/proc/{}/fd", self.0))?.count() as u64)
+	}
+
+	fn pid(&self) -> Option<u32> {
+		Some(self.0)
+	}
+}
+
+type Outcomes = Result<Vec<(Resource, Outcome)>, Error>;
+
+/// Outcome of applying a policy to several processes, carrying on after
+/// failures
+#[derive(Debug)]
+pub struct ProcessReport {
+	/// Result for each process, ordered by PID
+	pub results: Vec<(u32, Outcomes)>,
+}
+
+impl ProcessReport {
+	/// Whether the policy was applied to every process
+	pub fn is_ok(&self) -> bool {
+		self.results.iter().all(|(_, result)| result.is_ok())
+	}
+
+	/// Processes that failed and why
+	pub fn errors(&self) -> impl Iterator<Item = (u32, &Error)> + '_ {
+		self.results.iter().filter_map(|(pid, result)| Some((*pid, result.as_ref().err()?)))
+	}
+
+	/// Number of processes the policy was applied to
+	pub fn succeeded(&self) -> usize {
+		self.results.iter().filter(|(_, result)| result.is_ok()).count()
+	}
+
+	fn apply(mut pids: Vec<u32>, policy: &Policy) -> Self {
+		pids.sort_unstable();
+		let results =
+			pids.into_iter().map(|pid| (pid, crate::apply_policy_with(&Prlimit(pid), policy)));
+		ProcessReport { results: results.collect() }
+	}
+}
+
+/// Count of successes then one line per failure, e.g.
+/// `applied to 2 of 3 processes` and `1234: Failed to set new limit ...`
+impl fmt::Display for ProcessReport {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "applied to {} of {} processes", self.succeeded(), self.results.len())?;
+		for (pid, error) in self.errors() {
+			write!(f, "\n{pid}: {error}")?;
+		}
+		Ok(())
+	}
+}
+
+/// Apply the policy to every member of the process group, the caller's when
+/// `pgid` is `None`, with `prlimit`.
+///
+/// Changing the limits of processes running under other user or group IDs
+/// needs `CAP_SYS_RESOURCE`. Processes exiting during the scan are left out.
+pub fn apply_policy_to_process_group(
+	pgid: Option<u32>,
+	policy: &Policy,
+) -> Result<ProcessReport, Error> {
+	let pgid = pgid.unwrap_or_else(|| unsafe { libc::getpgrp() } as u32);
+	let members = pids()?.into_iter().filter(|&pid| process_group(pid) == Some(pgid)).collect();
+	Ok(ProcessReport::apply(members, policy))
+}
+
+/// Apply the policy to every process of the cgroup with `prlimit`, listed in
+/// its `cgroup.procs`.
+///
+/// Relative paths are taken from `/sys/fs/cgroup`, e.g.
+/// `system.slice/node.service`, include the controller with cgroup v1, e.g.
+/// `pids/docker/<id>`. Changing the limits of processes running
+/// under other user or group IDs needs `CAP_SYS_RESOURCE`.
+pub fn apply_policy_to_cgroup(
+	cgroup: impl AsRef<Path>,
+	policy: &Policy,
+) -> Result<ProcessReport, Error> {
+	let procs = Path::new("/sys/fs/cgroup").join(cgroup).join("cgroup.procs");
+	let procs = std::fs::read_to_string(procs).map_err(Error::FailedToReadProc)?;
+	let members = procs.lines().filter_map(|line| line.trim().parse().ok()).collect();
+	Ok(ProcessReport::apply(members, policy))
+}
+
+/// Process group of the process, the fifth field of `/proc/<pid>/stat`
+fn process_group(pid: u32) -> Option<u32> {
+	let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+	// The command name in parentheses may contain spaces and parentheses
+	let (_, fields) = stat.rsplit_once(')')?;
+	fields.split_whitespace().nth(2)?.parse().ok()
+}
+
+fn pids() -> Result<Vec<u32>, Error> {
+	let mut pids = Vec::new();
+	for entry in std::fs::read_dir("/proc").map_err(Error::FailedToReadProc)? {
+		let entry = entry.map_err(Error::FailedToReadProc)?;
+		if let Some(pid) = entry.file_name().to_str().and_then(|name| name.parse().ok()) {
+			pids.push(pid);
+		}
+	}
+	Ok(pids)
+}
+
+fn nofile_limit(pid: u32) -> Option<Rlimit> {
+	ProcLimits::read(format!("/proc/{pid}/limits")).ok()?.get(Resource::NoFile)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn usage(open_fds: u64, soft: u64) -> ProcessUsage {
+		ProcessUsage {
+			pid: 1,
+			open_fds,
+			limit: Rlimit { soft, hard: crate::backend::RLIM_INFINITY },
+		}
+	}
+
+	#[test]
+	fn ratio_of_the_soft_limit() {
+		assert_eq!(usage(256, 1024).ratio(), 0.25);
+		assert_eq!(usage(2048, 1024).ratio(), 2.0);
+		assert_eq!(usage(3, 0).ratio(), 1.0);
+		assert_eq!(usage(3, crate::backend::RLIM_INFINITY).ratio(), 0.0);
+	}
+}
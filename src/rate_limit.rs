@@ -0,0 +1,128 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::{Duration, Instant};
+
+/// Token bucket limiting how often a limit is raised again, so a persistent
+/// failure doesn't turn into a storm of syscalls and log lines.
+///
+/// Up to `burst` attempts can be made at once, then one more every
+/// `interval`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+	/// Attempts allowed back to back
+	pub burst: u32,
+	/// Time to earn one more attempt
+	pub interval: Duration,
+}
+
+impl RateLimit {
+	/// Allow `burst` attempts at once and one more every `interval`
+	pub fn new(burst: u32, interval: Duration) -> Self {
+		RateLimit { burst, interval }
+	}
+}
+
+/// Five attempts at once, then one per minute
+impl Default for RateLimit {
+	fn default() -> Self {
+		RateLimit::new(5, Duration::from_secs(60))
+	}
+}
+
+#[derive(Debug)]
+pub(crate) struct TokenBucket {
+	limit: RateLimit,
+	tokens: u32,
+	refilled: Instant,
+}
+
+impl TokenBucket {
+	pub(crate) fn new(limit: RateLimit) -> Self {
+		TokenBucket { limit, tokens: limit.burst, refilled: Instant::now() }
+	}
+
+	/// Take a token if one is left
+	pub(crate) fn try_acquire(&mut self, now: Instant) -> bool {
+		self.refill(now);
+		if self.tokens == 0 {
+			return false;
+		}
+		self.tokens -= 1;
+		true
+	}
+
+	fn refill(&mut self, now: Instant) {
+		if self.tokens >= self.limit.burst {
+			self.refilled = now;
+			return;
+		}
+		let elapsed = now.saturating_duration_since(self.refilled);
+		let earned = match self.limit.interval.as_nanos() {
+			0 => u128::from(self.limit.burst),
+			interval => elapsed.as_nanos() / interval,
+		};
+		if earned == 0 {
+			return;
+		}
+		let missing = self.limit.burst - self.tokens;
+		if earned >= u128::from(missing) {
+			self.tokens = self.limit.burst;
+			self.refilled = now;
+		} else {
+			// Keep the time towards the next token
+			self.tokens += earned as u32;
+			self.refilled += self.limit.interval * earned as u32;
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn denies_past_the_burst() {
+		let mut bucket = TokenBucket::new(RateLimit::new(3, Duration::from_secs(60)));
+		let now = bucket.refilled;
+		assert!((0..3).all(|_| bucket.try_acquire(now)));
+		assert!(!bucket.try_acquire(now));
+		assert!(!bucket.try_acquire(now + Duration::from_secs(59)));
+	}
+
+	#[test]
+	fn refills_over_time() {
+		let mut bucket = TokenBucket::new(RateLimit::new(3, Duration::from_secs(60)));
+		let start = bucket.refilled;
+		let at = |secs| start + Duration::from_secs(secs);
+		assert!((0..3).all(|_| bucket.try_acquire(start)));
+
+		// One token per interval, keeping the time towards the next one
+		assert!(bucket.try_acquire(at(90)));
+		assert!(!bucket.try_acquire(at(90)));
+		assert!(bucket.try_acquire(at(120)));
+		assert!(!bucket.try_acquire(at(150)));
+
+		// Never more than the burst
+		assert!((0..3).all(|_| bucket.try_acquire(at(3600))));
+		assert!(!bucket.try_acquire(at(3600)));
+	}
+
+	#[test]
+	fn zero_interval_never_denies() {
+		let mut bucket = TokenBucket::new(RateLimit::new(1, Duration::ZERO));
+		let now = bucket.refilled;
+		assert!((0..10).all(|_| bucket.try_acquire(now)));
+	}
+}
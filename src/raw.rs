@@ -0,0 +1,81 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Platform rlimit types and thin wrappers over the system calls, for what
+//! the rest of the crate doesn't cover.
+//!
+//! Nothing here checks the values, caches the kernel ceiling or fires the
+//! [`on_raise`](crate::on_raise) callbacks. These go through libc even with the
+//! `raw-syscalls` feature.
+
+use crate::Resource;
+use std::io;
+
+pub use libc::{rlim_t, rlimit, RLIM_INFINITY};
+
+/// Type of the resource constants taken by `getrlimit` and `setrlimit`
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+pub type RawResource = libc::__rlimit_resource_t;
+/// Type of the resource constants taken by `getrlimit` and `setrlimit`
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+pub type RawResource = libc::c_int;
+
+/// Platform constant of the resource, e.g. `RLIMIT_NOFILE`, `None` if the
+/// platform doesn't have it
+pub fn resource(resource: Resource) -> Option<RawResource> {
+	crate::backend::raw_resource(resource)
+}
+
+/// Call `getrlimit`, failing with the OS error.
+///
+/// # Safety
+///
+/// `rlim` must be valid for writes of an `rlimit`.
+pub unsafe fn getrlimit(resource: RawResource, rlim: *mut rlimit) -> io::Result<()> {
+	check(libc::getrlimit(resource, rlim))
+}
+
+/// Call `setrlimit`, failing with the OS error.
+///
+/// # Safety
+///
+/// `rlim` must be valid for reads of an `rlimit`.
+pub unsafe fn setrlimit(resource: RawResource, rlim: *const rlimit) -> io::Result<()> {
+	check(libc::setrlimit(resource, rlim))
+}
+
+/// Call `prlimit`, setting the limits of the process `pid` (0 for the
+/// current one) when `new` isn't null and storing the previous ones when
+/// `old` isn't null.
+///
+/// # Safety
+///
+/// `new` must be null or valid for reads of an `rlimit`, `old` null or valid
+/// for writes of one.
+#[cfg(target_os = "linux")]
+pub unsafe fn prlimit(
+	pid: libc::pid_t,
+	resource: RawResource,
+	new: *const rlimit,
+	old: *mut rlimit,
+) -> io::Result<()> {
+	check(libc::prlimit(pid, resource, new, old))
+}
+
+fn check(ret: libc::c_int) -> io::Result<()> {
+	match ret {
+		0 => Ok(()),
+		_ => Err(io::Error::last_os_error()),
+	}
+}
@@ -0,0 +1,220 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Allocation-free error representation for minimal builds.
+
+use crate::infallible::Failure;
+use crate::strategy::TargetError;
+use crate::{Error, Method, Resource};
+use core::fmt;
+
+/// Error holding only the failed step and the OS error code, or the limits
+/// involved when the failure doesn't come from the OS, without
+/// `std::io::Error` or any allocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum RawError {
+	/// A system call failed
+	Failed {
+		/// Failed step
+		method: Method,
+		/// OS error code, `EINVAL` for errors converted from an [`Error`] not
+		/// coming from the OS
+		errno: i32,
+	},
+	/// The platform doesn't have the resource
+	ResourceNotAvailable(Resource),
+	/// The strategy asked for a limit above the maximum available
+	LimitUnavailable {
+		/// Limit requested
+		requested: u64,
+		/// Maximum available
+		available: u64,
+	},
+	/// Fraction of the hard limit is not above 0 and at most 1
+	InvalidFraction,
+	/// A value read from or passed to the OS is out of range, e.g. a limit
+	/// too large for the platform's `rlim_t`
+	OutOfRange {
+		/// Failed step
+		method: Method,
+	},
+}
+
+impl RawError {
+	/// Failed step
+	pub fn method(&self) -> Method {
+		match *self {
+			RawError::Failed { method, .. } | RawError::OutOfRange { method } => method,
+			RawError::ResourceNotAvailable(_) => Method::GetRLimit,
+			RawError::LimitUnavailable { .. } | RawError::InvalidFraction => Method::Strategy,
+		}
+	}
+
+	/// OS error code, if the failure comes from the OS
+	pub fn errno(&self) -> Option<i32> {
+		match *self {
+			RawError::Failed { errno, .. } => Some(errno),
+			_ => None,
+		}
+	}
+
+	fn from_io(method: Method, error: &std::io::Error) -> Self {
+		match error.raw_os_error() {
+			Some(errno) => RawError::Failed { method, errno },
+			None => RawError::OutOfRange { method },
+		}
+	}
+}
+
+impl From<TargetError> for RawError {
+	fn from(error: TargetError) -> Self {
+		match error {
+			TargetError::LimitUnavailable { requested, available } => {
+				RawError::LimitUnavailable { requested, available }
+			}
+			TargetError::InvalidFraction(_) => RawError::InvalidFraction,
+		}
+	}
+}
+
+impl From<Failure> for RawError {
+	fn from(failure: Failure) -> Self {
+		match failure {
+			Failure::Sysctl(error) => RawError::from_io(Method::Sysctl, &error),
+			Failure::ResourceNotAvailable(resource) => RawError::ResourceNotAvailable(resource),
+			Failure::GetLimit(error) => RawError::from_io(Method::GetRLimit, &error),
+			Failure::Strategy(error) => error.into(),
+			Failure::SetLimit { error, .. } => RawError::from_io(Method::SetRLimit, &error),
+		}
+	}
+}
+
+impl From<&Error> for RawError {
+	fn from(error: &Error) -> Self {
+		match *error {
+			Error::ResourceNotAvailable(resource) => RawError::ResourceNotAvailable(resource),
+			Error::LimitUnavailable { requested, available } => {
+				RawError::LimitUnavailable { requested, available }
+			}
+			Error::InvalidFraction(_) => RawError::InvalidFraction,
+			_ => {
+				let errno = error.io_error().and_then(|e| e.raw_os_error()).unwrap_or(libc::EINVAL);
+				RawError::Failed { method: error.method(), errno }
+			}
+		}
+	}
+}
+
+impl From<Error> for RawError {
+	fn from(error: Error) -> Self {
+		RawError::from(&error)
+	}
+}
+
+impl fmt::Display for RawError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match *self {
+			RawError::Failed { method, errno } => {
+				write!(f, "{method} failed with OS error {errno}")?;
+				match crate::errno_name(errno) {
+					Some(name) => write!(f, " ({name})"),
+					None => Ok(()),
+				}
+			}
+			RawError::ResourceNotAvailable(resource) => {
+				write!(f, "Resource {resource} is not available on this platform")
+			}
+			RawError::LimitUnavailable { requested, available } => {
+				write!(f, "Requested limit {requested} is above the maximum available {available}")
+			}
+			RawError::InvalidFraction => f.write_str("Invalid fraction of the hard limit"),
+			RawError::OutOfRange { method } => {
+				write!(f, "{method} failed with a value out of range")
+			}
+		}
+	}
+}
+
+impl std::error::Error for RawError {}
+
+/// Raise the limit like [`raise`](crate::Builder::raise) without building an
+/// [`Error`]. The [`on_raise`](crate::on_raise) callbacks aren't fired and the
+/// change isn't recorded in the [audit log](crate::audit_log).
+#[cfg(feature = "raw-error")]
+#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+pub(crate) fn raise<B: crate::backend::RlimitOps>(
+	backend: &B,
+	resource: Resource,
+	strategy: &crate::Strategy,
+) -> Result<crate::Outcome, RawError> {
+	let (before, after) = crate::infallible::raise_with(backend, resource, strategy)?;
+	Ok(crate::Outcome::LimitRaised { from: before.soft, to: after.soft })
+}
+
+#[cfg(test)]
+#[cfg(feature = "raw-error")]
+#[cfg(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd"))]
+mod tests {
+	use super::*;
+	use crate::simulated::Simulated;
+	use crate::{Outcome, Strategy};
+	use std::io;
+
+	#[test]
+	fn raises_to_hard() {
+		let backend = Simulated::new(256, 4096);
+		let outcome = raise(&backend, Resource::NoFile, &Strategy::MaxAvailable).unwrap();
+		assert_eq!(outcome, Outcome::LimitRaised { from: 256, to: 4096 });
+	}
+
+	#[test]
+	fn reports_setrlimit_eperm() {
+		let backend = Simulated::new(256, 4096).fail_setrlimit(libc::EPERM);
+		let error = raise(&backend, Resource::NoFile, &Strategy::MaxAvailable).unwrap_err();
+		assert_eq!(error, RawError::Failed { method: Method::SetRLimit, errno: libc::EPERM });
+		assert_eq!(error.errno(), Some(libc::EPERM));
+	}
+
+	#[test]
+	fn reports_strategy_failures_without_errno() {
+		let backend = Simulated::new(256, 1024);
+		let error = raise(&backend, Resource::NoFile, &Strategy::Exact(4096)).unwrap_err();
+		assert_eq!(error, RawError::LimitUnavailable { requested: 4096, available: 1024 });
+		assert_eq!(error.errno(), None);
+
+		let strategy = Strategy::FractionOfHard(0.0);
+		let error = raise(&backend, Resource::NoFile, &strategy).unwrap_err();
+		assert_eq!(error, RawError::InvalidFraction);
+	}
+
+	#[test]
+	fn reports_missing_resource() {
+		let backend = Simulated::new(256, 4096);
+		let error = raise(&backend, Resource::NProc, &Strategy::MaxAvailable).unwrap_err();
+		assert_eq!(error, RawError::ResourceNotAvailable(Resource::NProc));
+	}
+
+	#[test]
+	fn converts_errors() {
+		let error = Error::LimitUnavailable { requested: 2, available: 1 };
+		assert_eq!(
+			RawError::from(error),
+			RawError::LimitUnavailable { requested: 2, available: 1 }
+		);
+		let error = Error::FailedToGetLimit(io::Error::from_raw_os_error(libc::EACCES));
+		let expected = RawError::Failed { method: Method::GetRLimit, errno: libc::EACCES };
+		assert_eq!(RawError::from(error), expected);
+	}
+}
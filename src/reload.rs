@@ -0,0 +1,330 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::backend::RlimitOps;
+use crate::{Error, Policy, Report, Rule};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+type Parse = Box<dyn Fn(&str) -> Result<Policy, String> + Send + Sync>;
+
+/// Policy read from a config file, re-applied when the file changes so
+/// long-running services adopt new limits without a restart.
+///
+/// The format is up to the caller, e.g. `toml::from_str` with the `serde`
+/// feature, or one `<resource> <target>` rule per line:
+///
+/// ```no_run
+/// # use fdlimit::{apply_policy, Error, Policy, PolicyFile, Rule};
+/// fn parse(s: &str) -> Result<Policy, Error> {
+///     let mut policy = Policy::default();
+///     for line in s.lines().filter(|line| !line.trim().is_empty()) {
+///         let invalid = || Error::InvalidValue(line.to_owned());
+///         let (resource, target) = line.split_once(' ').ok_or_else(invalid)?;
+///         let rule = Rule { target: target.parse()?, minimum: None };
+///         policy.rules.insert(resource.parse()?, rule);
+///     }
+///     Ok(policy)
+/// }
+///
+/// let mut file = PolicyFile::load("/etc/my-node/limits.conf", parse)?;
+/// apply_policy(file.policy())?;
+/// // later, on SIGHUP or periodically
+/// if let Some(reload) = file.reload_if_modified()? {
+///     println!("{reload}");
+/// }
+/// # Ok::<(), Error>(())
+/// ```
+pub struct PolicyFile {
+	path: PathBuf,
+	parse: Parse,
+	policy: Policy,
+	modified: Option<SystemTime>,
+}
+
+impl fmt::Debug for PolicyFile {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("PolicyFile")
+			.field("path", &self.path)
+			.field("policy", &self.policy)
+			.field("modified", &self.modified)
+			.finish_non_exhaustive()
+	}
+}
+
+/// Rule of a resource that differs between two policies
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+	/// Resource whose rule changed
+	pub resource: crate::Resource,
+	/// Rule before, `None` if the resource was added
+	pub before: Option<Rule>,
+	/// Rule after, `None` if the resource was removed
+	pub after: Option<Rule>,
+}
+
+/// Changes found by [`PolicyFile::reload`] and the outcome of applying them
+#[derive(Debug)]
+pub struct Reload {
+	/// Rules added, changed or removed, by resource
+	pub changes: Vec<Change>,
+	/// Result of raising the added and changed resources, the limits of removed
+	/// ones are left as they are
+	pub report: Report,
+}
+
+impl PolicyFile {
+	/// Read and parse the policy, without applying it
+	pub fn load<E: fmt::Display>(
+		path: impl AsRef<Path>,
+		parse: impl Fn(&str) -> Result<Policy, E> + Send + Sync + 'static,
+	) -> Result<Self, Error> {
+		let parse: Parse = Box::new(move |s| parse(s).map_err(|error| error.to_string()));
+		let path = path.as_ref().to_owned();
+		let (policy, modified) = read(&path, &parse)?;
+		Ok(PolicyFile { path, parse, policy, modified })
+	}
+
+	/// Path of the config file
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	/// Policy last read from the file
+	pub fn policy(&self) -> &Policy {
+		&self.policy
+	}
+
+	/// Read the file again and raise the resources whose rules were added or
+	/// changed. The current policy is kept if the file can't be read or parsed.
+	pub fn reload(&mut self) -> Result<Reload, Error> {
+		self.reload_with(&crate::backend::System)
+	}
+
+	/// Same as [`reload`](Self::reload) using the given backend
+	pub fn reload_with<B: RlimitOps>(&mut self, backend: &B) -> Result<Reload, Error> {
+		let (policy, modified) = read(&self.path, &self.parse)?;
+		let changes = diff_policies(&self.policy, &policy);
+		let changed = Policy {
+			rules: changes
+				.iter()
+				.filter_map(|change| Some((change.resource, change.after.clone()?)))
+				.collect(),
+		};
+		let results = crate::policy::apply_each(&changed, |builder| builder.raise_with(backend));
+		let report = Report { results: results.collect() };
+
+		self.policy = policy;
+		self.modified = modified;
+		Ok(Reload { changes, report })
+	}
+
+	/// [Reload](Self::reload) if the file was modified since it was last read,
+	/// to call periodically as a file watch
+	pub fn reload_if_modified(&mut self) -> Result<Option<Reload>, Error> {
+		self.reload_if_modified_with(&crate::backend::System)
+	}
+
+	/// Same as [`reload_if_modified`](Self::reload_if_modified) using the
+	/// given backend
+	pub fn reload_if_modified_with<B: RlimitOps>(
+		&mut self,
+		backend: &B,
+	) -> Result<Option<Reload>, Error> {
+		let modified = modified(&self.path)?;
+		if modified.is_some() && modified == self.modified {
+			return Ok(None);
+		}
+		self.reload_with(backend).map(Some)
+	}
+}
+
+fn read(path: &Path, parse: &Parse) -> Result<(Policy, Option<SystemTime>), Error> {
+	let modified = modified(path)?;
+	let contents = std::fs::read_to_string(path).map_err(Error::FailedToReadPolicy)?;
+	let policy = parse(&contents)
+		.map_err(|error| Error::InvalidValue(format!("{}: {error}", path.display())))?;
+	Ok((policy, modified))
+}
+
+// Filesystems without modification times always count as modified
+fn modified(path: &Path) -> Result<Option<SystemTime>, Error> {
+	let metadata = std::fs::metadata(path).map_err(Error::FailedToReadPolicy)?;
+	Ok(metadata.modified().ok())
+}
+
+/// Rules that differ between the two policies, by resource
+pub fn diff_policies(before: &Policy, after: &Policy) -> Vec<Change> {
+	let mut resources: Vec<_> = before.rules.keys().chain(after.rules.keys()).copied().collect();
+	resources.sort();
+	resources.dedup();
+	resources
+		.into_iter()
+		.filter_map(|resource| {
+			let before = before.rules.get(&resource);
+			let after = after.rules.get(&resource);
+			(before != after).then(|| Change {
+				resource,
+				before: before.cloned(),
+				after: after.cloned(),
+			})
+		})
+		.collect()
+}
+
+impl fmt::Display for Rule {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.target)?;
+		if let Some(minimum) = self.minimum {
+			write!(f, " (minimum {minimum})")?;
+		}
+		Ok(())
+	}
+}
+
+/// For example `nofile: max -> 65536 (minimum 4096)`
+impl fmt::Display for Change {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}: ", self.resource)?;
+		match (&self.before, &self.after) {
+			(Some(before), Some(after)) => write!(f, "{before} -> {after}"),
+			(None, Some(after)) => write!(f, "added {after}"),
+			(Some(before), None) => write!(f, "removed {before}"),
+			(None, None) => f.write_str("unchanged"),
+		}
+	}
+}
+
+/// One line per change followed by the failures, `policy unchanged` when
+/// nothing changed
+impl fmt::Display for Reload {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if self.changes.is_empty() {
+			return f.write_str("policy unchanged");
+		}
+		for (i, change) in self.changes.iter().enumerate() {
+			if i > 0 {
+				f.write_str("\n")?;
+			}
+			write!(f, "{change}")?;
+		}
+		for (resource, error) in self.report.errors() {
+			write!(f, "\nfailed to raise {resource} limit: {error}")?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::backend::Rlimit;
+	use crate::simulated::Simulated;
+	use crate::{Outcome, Resource, Strategy};
+
+	fn rule(target: Strategy) -> Rule {
+		Rule { target, minimum: None }
+	}
+
+	fn policy(rules: &[(Resource, Strategy)]) -> Policy {
+		Policy { rules: rules.iter().map(|&(resource, target)| (resource, rule(target))).collect() }
+	}
+
+	/// `<resource> <target>` per line
+	fn parse(s: &str) -> Result<Policy, Error> {
+		let mut policy = Policy::default();
+		for line in s.lines() {
+			let (resource, target) = line.split_once(' ').expect("rule");
+			policy.rules.insert(resource.parse()?, rule(target.parse()?));
+		}
+		Ok(policy)
+	}
+
+	#[test]
+	fn diffs_policies() {
+		let before = policy(&[
+			(Resource::NoFile, Strategy::MaxAvailable),
+			(Resource::Stack, Strategy::Exact(8192)),
+		]);
+		let after = policy(&[
+			(Resource::NoFile, Strategy::Exact(4096)),
+			(Resource::NProc, Strategy::MaxAvailable),
+		]);
+		let changes = diff_policies(&before, &after);
+		assert_eq!(
+			changes,
+			[
+				Change {
+					resource: Resource::NoFile,
+					before: Some(rule(Strategy::MaxAvailable)),
+					after: Some(rule(Strategy::Exact(4096))),
+				},
+				Change {
+					resource: Resource::NProc,
+					before: None,
+					after: Some(rule(Strategy::MaxAvailable))
+				},
+				Change {
+					resource: Resource::Stack,
+					before: Some(rule(Strategy::Exact(8192))),
+					after: None
+				},
+			]
+		);
+		let lines: Vec<_> = changes.iter().map(Change::to_string).collect();
+		assert_eq!(lines, ["nofile: max -> 4096", "nproc: added max", "stack: removed 8192"]);
+		assert!(diff_policies(&after, &after).is_empty());
+	}
+
+	#[test]
+	fn reloads_changed_resources_only() {
+		let path = std::env::temp_dir().join(format!("fdlimit-reload-{}", std::process::id()));
+		std::fs::write(&path, "nofile 1024\nnproc max").unwrap();
+		let mut file = PolicyFile::load(&path, parse).unwrap();
+		std::fs::write(&path, "nofile 2048\nnproc max").unwrap();
+
+		// nproc isn't raised again, it would be changed from 100 to 200 otherwise
+		let backend = Simulated::new(256, 4096).with_limit(Resource::NProc, 100, 200);
+		let reload = file.reload_with(&backend).unwrap();
+		std::fs::remove_file(&path).unwrap();
+		assert_eq!(reload.report.results.len(), 1);
+		assert!(matches!(
+			reload.report.results[0],
+			(Resource::NoFile, Ok(Outcome::LimitRaised { from: 256, to: 2048 }))
+		));
+		assert_eq!(backend.limit(Resource::NProc), Some(Rlimit { soft: 100, hard: 200 }));
+		assert_eq!(
+			file.policy(),
+			&policy(&[
+				(Resource::NoFile, Strategy::Exact(2048)),
+				(Resource::NProc, Strategy::MaxAvailable)
+			])
+		);
+		assert_eq!(reload.to_string(), "nofile: 1024 -> 2048");
+	}
+
+	#[test]
+	fn keeps_the_policy_when_invalid() {
+		let path =
+			std::env::temp_dir().join(format!("fdlimit-reload-invalid-{}", std::process::id()));
+		std::fs::write(&path, "nofile max").unwrap();
+		let mut file = PolicyFile::load(&path, parse).unwrap();
+		std::fs::write(&path, "nofile lots").unwrap();
+		let result = file.reload_with(&Simulated::new(256, 4096));
+		std::fs::remove_file(&path).unwrap();
+		assert!(matches!(result, Err(Error::InvalidValue(_))));
+		assert_eq!(file.policy(), &policy(&[(Resource::NoFile, Strategy::MaxAvailable)]));
+	}
+}
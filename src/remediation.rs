@@ -0,0 +1,547 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Steps to raise a limit, tailored to how the process was started.
+
+use crate::{Limit, Resource, Side, WslVersion};
+use std::fmt;
+
+/// How the process was started, which decides where its limits come from
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Environment {
+	/// Started from a shell, or by an unknown supervisor
+	Shell,
+	/// Systemd service, with its unit name when known
+	Systemd {
+		/// Unit name, e.g. `node.service`
+		unit: Option<String>,
+	},
+	/// Started by launchd, on Apple platforms
+	Launchd {
+		/// Kind of launchd job
+		session: LaunchdSession,
+		/// Job label, e.g. `com.example.node`
+		label: Option<String>,
+	},
+	/// Docker or Podman container
+	Container,
+	/// Kubernetes pod, whose limits come from the container runtime of the node
+	Kubernetes {
+		/// Namespace of the pod, from the downward API or the service account
+		namespace: Option<String>,
+		/// Name of the pod, from the downward API or the hostname
+		pod: Option<String>,
+	},
+	/// Login session opened through PAM, e.g. over ssh or on a console, whose
+	/// limits are set by `pam_limits` rather than by a service manager
+	LoginSession {
+		/// PAM service of the session, e.g. `sshd`, when known
+		service: Option<String>,
+	},
+	/// Started in the Windows Subsystem for Linux without a service manager,
+	/// inheriting the limits of the WSL init
+	Wsl {
+		/// Version of WSL
+		version: WslVersion,
+		/// Name of the distribution, e.g. `Ubuntu`, from `WSL_DISTRO_NAME`
+		distro: Option<String>,
+	},
+}
+
+/// Kind of launchd job, which decides where its limits are configured
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum LaunchdSession {
+	/// Daemon of the system domain, from /Library/LaunchDaemons
+	Daemon,
+	/// Agent of a user's session, from ~/Library/LaunchAgents
+	Agent,
+	/// Application opened from the Finder, the Dock or `open`
+	App,
+}
+
+/// Whether the process was started by launchd rather than from a shell,
+/// always false on platforms other than Apple's
+pub fn is_running_under_launchd() -> bool {
+	cfg!(target_vendor = "apple") && launchd_parent()
+}
+
+#[cfg(unix)]
+fn launchd_parent() -> bool {
+	unsafe { libc::getppid() == 1 }
+}
+
+#[cfg(not(unix))]
+fn launchd_parent() -> bool {
+	false
+}
+
+/// Kind of launchd job of the current process, `None` when not started by
+/// launchd.
+///
+/// Told apart from `XPC_SERVICE_NAME`, set by launchd to the job label or to
+/// `application.<bundle id>...` for applications, and the effective user,
+/// daemons usually running as root.
+pub fn launchd_session() -> Option<LaunchdSession> {
+	if !is_running_under_launchd() {
+		return None;
+	}
+	let service = std::env::var("XPC_SERVICE_NAME").unwrap_or_default();
+	Some(if service.starts_with("application.") {
+		LaunchdSession::App
+	} else if is_root() {
+		LaunchdSession::Daemon
+	} else {
+		LaunchdSession::Agent
+	})
+}
+
+#[cfg(unix)]
+fn is_root() -> bool {
+	unsafe { libc::geteuid() == 0 }
+}
+
+#[cfg(not(unix))]
+fn is_root() -> bool {
+	false
+}
+
+/// Launchd job label, `XPC_SERVICE_NAME` unless it's an application's or
+/// launchd's placeholder `0` for processes outside of a job
+fn launchd_label() -> Option<String> {
+	std::env::var("XPC_SERVICE_NAME")
+		.ok()
+		.filter(|service| service != "0" && !service.starts_with("application."))
+}
+
+impl Environment {
+	/// Detect the environment of the current process
+	pub fn detect() -> Self {
+		if cfg!(target_vendor = "apple") {
+			return match launchd_session() {
+				Some(session) => Environment::Launchd { session, label: launchd_label() },
+				None => Environment::Shell,
+			};
+		}
+		let exists = |path: &str| std::path::Path::new(path).exists();
+		if is_kubernetes() {
+			let var = |key| std::env::var(key).ok().filter(|value: &String| !value.is_empty());
+			let namespace = var("POD_NAMESPACE").or_else(|| {
+				let path = format!("{SERVICE_ACCOUNT}/namespace");
+				Some(std::fs::read_to_string(path).ok()?.trim().to_owned())
+			});
+			let pod = var("POD_NAME").or_else(|| var("HOSTNAME"));
+			return Environment::Kubernetes { namespace, pod };
+		}
+		if exists("/.dockerenv") || exists("/run/.containerenv") {
+			return Environment::Container;
+		}
+		// Set by systemd for the processes of every unit it starts
+		if std::env::var_os("INVOCATION_ID").is_some() {
+			return Environment::Systemd { unit: systemd_unit() };
+		}
+		if let Some(session) = login_session() {
+			return Environment::LoginSession { service: session_service(&session) };
+		}
+		if let Some(version) = crate::platform_info().wsl {
+			let distro = std::env::var("WSL_DISTRO_NAME").ok().filter(|name| !name.is_empty());
+			return Environment::Wsl { version, distro };
+		}
+		Environment::Shell
+	}
+}
+
+const SERVICE_ACCOUNT: &str = "/var/run/secrets/kubernetes.io/serviceaccount";
+
+/// Whether the process runs in a pod, from the variables set in every container,
+/// the mounted service account or the kubelet's cgroup hierarchy
+fn is_kubernetes() -> bool {
+	std::env::var_os("KUBERNETES_SERVICE_HOST").is_some()
+		|| std::path::Path::new(SERVICE_ACCOUNT).exists()
+		|| std::fs::read_to_string("/proc/self/cgroup")
+			.is_ok_and(|cgroup| cgroup.contains("kubepods"))
+}
+
+/// Service the process belongs to, the last `.service` in its cgroup path
+fn systemd_unit() -> Option<String> {
+	let cgroup = std::fs::read_to_string("/proc/self/cgroup").ok()?;
+	let path = cgroup.lines().find_map(|line| line.splitn(3, ':').nth(2))?;
+	path.rsplit('/').find(|name| name.ends_with(".service")).map(str::to_owned)
+}
+
+/// Id of the logind session of the process, registered by `pam_systemd` when
+/// the session was opened
+fn login_session() -> Option<String> {
+	if !cfg!(target_os = "linux") {
+		return None;
+	}
+	if let Some(id) = std::env::var("XDG_SESSION_ID").ok().filter(|id| !id.is_empty()) {
+		return Some(id);
+	}
+	let cgroup = std::fs::read_to_string("/proc/self/cgroup").ok()?;
+	let path = cgroup.lines().find_map(|line| line.splitn(3, ':').nth(2))?;
+	path.rsplit('/')
+		.find_map(|name| name.strip_prefix("session-")?.strip_suffix(".scope"))
+		.map(str::to_owned)
+}
+
+/// PAM service that opened the session, as recorded by logind
+fn session_service(session: &str) -> Option<String> {
+	let state = std::fs::read_to_string(format!("/run/systemd/sessions/{session}")).ok()?;
+	state.lines().find_map(|line| line.strip_prefix("SERVICE=")).map(str::to_owned)
+}
+
+/// Steps to raise a resource limit in a given environment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remediation {
+	/// Resource to raise
+	pub resource: Resource,
+	/// Desired limit
+	pub limit: Limit,
+	/// Environment the steps apply to
+	pub environment: Environment,
+	/// Commands or configuration changes, in order, empty when the
+	/// environment has no way to change the limit
+	pub steps: Vec<String>,
+}
+
+/// Steps to raise the resource limit to `limit` in the detected
+/// [`Environment`]
+pub fn remediation(resource: Resource, limit: Limit) -> Remediation {
+	Remediation::for_environment(Environment::detect(), resource, limit)
+}
+
+impl Remediation {
+	/// Steps to raise the resource limit to `limit` in the given environment
+	pub fn for_environment(environment: Environment, resource: Resource, limit: Limit) -> Self {
+		let mut steps = Vec::new();
+		match &environment {
+			Environment::Shell => {
+				steps.extend(ulimit(resource, limit));
+				if cfg!(target_os = "linux") {
+					steps.push(format!(
+						"add `* soft {0} {1}` and `* hard {0} {1}` to /etc/security/limits.conf \
+						 and log in again",
+						resource.name(),
+						limits_conf_value(resource, limit),
+					));
+				}
+			}
+			Environment::Systemd { unit } => {
+				if let Some(directive) = systemd_directive(resource) {
+					let unit = unit.as_deref().unwrap_or("<unit>");
+					let value = systemd_value(limit);
+					steps.push(format!("systemctl edit {unit}"));
+					steps.push(format!("add `{directive}={value}` under [Service]"));
+					steps.push(format!("systemctl restart {unit}"));
+					// Units without the directive get the manager's default
+					if let Some(default) =
+						crate::systemd_default_limit(crate::SystemdManager::System, resource)
+							.filter(|default| default.hard < limit)
+					{
+						steps.push(format!(
+							"or raise `Default{directive}={value}` from {}:{} in {} for every \
+							 unit, then systemctl daemon-reexec",
+							default.soft,
+							default.hard,
+							default.path.display()
+						));
+					}
+				}
+			}
+			Environment::LoginSession { service } => {
+				steps.extend(ulimit(resource, limit));
+				if let Some(item) = crate::pam::pam_item_name(resource) {
+					let value = crate::pam::file_value(resource, limit);
+					for entry in crate::pam_limits() {
+						if entry.resource == resource && entry.value < limit {
+							steps.push(format!("raise {entry} to {value}"));
+						}
+					}
+					let domain = crate::pam::current_user_name().unwrap_or_else(|| "*".to_owned());
+					steps.push(format!(
+						"add `{domain} soft {item} {value}` and `{domain} hard {item} {value}` \
+						 to /etc/security/limits.d/90-{item}.conf"
+					));
+					if let Some(service) = service.as_deref() {
+						if !crate::pam_limits_enabled(service) {
+							steps.push(format!(
+								"add `session required pam_limits.so` to /etc/pam.d/{service}"
+							));
+						}
+					}
+					steps.push("log in again".to_owned());
+				}
+			}
+			Environment::Wsl { version, distro } => {
+				let distro = distro.as_deref().unwrap_or("<distro>");
+				match version {
+					WslVersion::V1 => steps.push(format!(
+						"WSL 1 only partly enforces limits, convert the distribution with \
+						 `wsl --set-version {distro} 2` from Windows"
+					)),
+					WslVersion::V2 => {
+						steps.extend(ulimit(resource, limit));
+						// util-linux's prlimit names its options as systemd
+						if systemd_directive(resource).is_some() {
+							let value = systemd_value(limit);
+							steps.push(format!(
+								"sudo prlimit --pid $$ --{}={value}:{value}",
+								resource.name()
+							));
+						}
+						steps.push(
+							"to set it for every process add `systemd=true` under [boot] to \
+							 /etc/wsl.conf, run `wsl --shutdown` from Windows and raise the \
+							 systemd defaults"
+								.to_owned(),
+						);
+					}
+				}
+			}
+			Environment::Launchd { session, label } => {
+				if let Some((name, key)) = launchd_names(resource) {
+					steps.push(format!("sudo launchctl limit {name} {limit} {limit}"));
+					let label = label.as_deref().unwrap_or("<label>");
+					let job = match session {
+						LaunchdSession::Daemon => Some((
+							format!("/Library/LaunchDaemons/{label}.plist"),
+							"sudo launchctl",
+							"system",
+						)),
+						LaunchdSession::Agent => Some((
+							format!("~/Library/LaunchAgents/{label}.plist"),
+							"launchctl",
+							"gui/$(id -u)",
+						)),
+						LaunchdSession::App => None,
+					};
+					match job {
+						Some((plist, launchctl, domain)) => {
+							steps.push(format!(
+								"set {key} in SoftResourceLimits and HardResourceLimits of {plist} \
+								 to keep it across restarts"
+							));
+							steps.push(format!("{launchctl} kickstart -k {domain}/{label}"));
+						}
+						None => steps.push("quit and reopen the application".to_owned()),
+					}
+				}
+			}
+			Environment::Container => {
+				if let Some(name) = docker_name(resource) {
+					let value = docker_value(limit);
+					steps.push(format!("docker run --ulimit {name}={value}:{value}"));
+				}
+			}
+			// Pod specs have no ulimits, they're inherited from the runtime
+			Environment::Kubernetes { namespace, pod } => {
+				if let Some(directive) = systemd_directive(resource) {
+					let value = systemd_value(limit);
+					steps.push(format!(
+						"on every node run `systemctl edit containerd` and add \
+						 `{directive}={value}` under [Service], then restart containerd"
+					));
+				}
+				if let Some(name) = docker_name(resource) {
+					let value = docker_value(limit);
+					steps.push(format!(
+						"with CRI-O add `\"{name}={value}:{value}\"` to default_ulimits in \
+						 /etc/crio/crio.conf instead"
+					));
+				}
+				steps.push(
+					"raising the hard limit from inside the container needs the SYS_RESOURCE \
+					 capability in the container's securityContext"
+						.to_owned(),
+				);
+				if let Some(pod) = pod {
+					let namespace = namespace.as_deref().unwrap_or("default");
+					steps.push(format!("kubectl delete pod {pod} -n {namespace} to recreate it"));
+				}
+			}
+		}
+
+		// Nothing inside a user namespace raises the hard limit above the one it
+		// was entered with
+		if let Some(namespace) = crate::user_namespace() {
+			let hard = crate::get_limit(resource).ok().map(|rlim| rlim.hard_limit());
+			if let Some(hard) = hard.filter(|&hard| hard < limit) {
+				steps.push(format!(
+					"inside a user namespace ({namespace}) the hard limit can't go above {hard}, \
+					 raise it for the user creating the namespace, e.g. in the unit or shell \
+					 starting the rootless container"
+				));
+			}
+		}
+
+		Remediation { resource, limit, environment, steps }
+	}
+}
+
+/// Steps separated by semicolons, on one line, e.g. `ulimit -n 65536; add ...`
+impl fmt::Display for Remediation {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if self.steps.is_empty() {
+			return write!(f, "no known way to raise the {} limit here", self.resource);
+		}
+		f.write_str(&self.steps.join("; "))
+	}
+}
+
+/// Whether `ulimit` and limits.conf take the resource in KiB, message queue
+/// sizes are in bytes there
+pub(crate) fn in_kib(resource: Resource) -> bool {
+	matches!(
+		resource,
+		Resource::MemLock
+			| Resource::Core
+			| Resource::FSize
+			| Resource::Stack
+			| Resource::Data
+			| Resource::As
+			| Resource::Rss
+			| Resource::SbSize
+	)
+}
+
+/// `ulimit` command of the POSIX shells, byte sizes are in KiB
+fn ulimit(resource: Resource, limit: Limit) -> Option<String> {
+	let flag = ulimit_flag(resource)?;
+	Some(format!("ulimit -{flag} {}", limits_conf_value(resource, limit)))
+}
+
+/// Step to lower a limit found too high, which needs no privileges, but only
+/// applies to the processes started afterwards
+pub(crate) fn lowering(resource: Resource, side: Side, limit: Limit) -> String {
+	let side_flag = match side {
+		Side::Soft => 'S',
+		Side::Hard => 'H',
+	};
+	match ulimit_flag(resource) {
+		Some(flag) => format!(
+			"lower it with `ulimit -{side_flag}{flag} {}` before starting the process",
+			limits_conf_value(resource, limit)
+		),
+		None => format!("lower it to {limit} with setrlimit before starting the process"),
+	}
+}
+
+fn ulimit_flag(resource: Resource) -> Option<char> {
+	let flag = match resource {
+		Resource::NoFile => 'n',
+		Resource::NProc => 'u',
+		Resource::MemLock => 'l',
+		Resource::Core => 'c',
+		Resource::FSize => 'f',
+		Resource::Stack => 's',
+		Resource::Cpu => 't',
+		Resource::Data => 'd',
+		Resource::As => 'v',
+		Resource::Rss => 'm',
+		Resource::MsgQueue => 'q',
+		Resource::RtPrio => 'r',
+		Resource::Nice => 'e',
+		Resource::SigPending => 'i',
+		Resource::Locks => 'x',
+		Resource::RtTime => 'R',
+		_ => return None,
+	};
+	Some(flag)
+}
+
+/// Value as written in limits.conf and for `ulimit`, byte sizes are in KiB
+pub(crate) fn limits_conf_value(resource: Resource, limit: Limit) -> String {
+	match limit {
+		Limit::Finite(value) if in_kib(resource) => value.div_ceil(1024).to_string(),
+		limit => limit.to_string(),
+	}
+}
+
+/// Value of a systemd `Limit*=` directive
+fn systemd_value(limit: Limit) -> String {
+	match limit {
+		Limit::Finite(value) => value.to_string(),
+		Limit::Unlimited => "infinity".to_owned(),
+	}
+}
+
+pub(crate) fn systemd_directive(resource: Resource) -> Option<&'static str> {
+	Some(match resource {
+		Resource::NoFile => "LimitNOFILE",
+		Resource::NProc => "LimitNPROC",
+		Resource::MemLock => "LimitMEMLOCK",
+		Resource::Core => "LimitCORE",
+		Resource::FSize => "LimitFSIZE",
+		Resource::Stack => "LimitSTACK",
+		Resource::Cpu => "LimitCPU",
+		Resource::Data => "LimitDATA",
+		Resource::As => "LimitAS",
+		Resource::Rss => "LimitRSS",
+		Resource::MsgQueue => "LimitMSGQUEUE",
+		Resource::RtPrio => "LimitRTPRIO",
+		Resource::Nice => "LimitNICE",
+		Resource::SigPending => "LimitSIGPENDING",
+		Resource::Locks => "LimitLOCKS",
+		Resource::RtTime => "LimitRTTIME",
+		_ => return None,
+	})
+}
+
+/// Names for `launchctl limit` and the plist key
+fn launchd_names(resource: Resource) -> Option<(&'static str, &'static str)> {
+	Some(match resource {
+		Resource::NoFile => ("maxfiles", "NumberOfFiles"),
+		Resource::NProc => ("maxproc", "NumberOfProcesses"),
+		Resource::MemLock => ("memlock", "MemoryLock"),
+		Resource::Core => ("core", "Core"),
+		Resource::FSize => ("filesize", "FileSize"),
+		Resource::Stack => ("stack", "Stack"),
+		Resource::Cpu => ("cpu", "CPU"),
+		Resource::Data => ("data", "Data"),
+		Resource::Rss => ("rss", "ResidentSetSize"),
+		_ => return None,
+	})
+}
+
+/// Value for `docker run --ulimit`
+pub(crate) fn docker_value(limit: Limit) -> String {
+	match limit {
+		Limit::Finite(value) => value.to_string(),
+		Limit::Unlimited => "-1".to_owned(),
+	}
+}
+
+/// Name for `docker run --ulimit`, which has no address space limit
+pub(crate) fn docker_name(resource: Resource) -> Option<&'static str> {
+	match resource {
+		Resource::NoFile
+		| Resource::NProc
+		| Resource::MemLock
+		| Resource::Core
+		| Resource::FSize
+		| Resource::Stack
+		| Resource::Cpu
+		| Resource::Data
+		| Resource::Rss
+		| Resource::MsgQueue
+		| Resource::Nice
+		| Resource::RtPrio
+		| Resource::SigPending
+		| Resource::Locks
+		| Resource::RtTime => Some(resource.name()),
+		_ => None,
+	}
+}
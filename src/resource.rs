@@ -0,0 +1,180 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// Process resource with soft and hard limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+	feature = "serde",
+	derive(serde::Serialize, serde::Deserialize),
+	serde(rename_all = "lowercase")
+)]
+#[non_exhaustive]
+pub enum Resource {
+	/// Number of open file descriptors (`RLIMIT_NOFILE`)
+	NoFile,
+	/// Number of processes of the user (`RLIMIT_NPROC`)
+	NProc,
+	/// Bytes of memory that can be locked in RAM (`RLIMIT_MEMLOCK`)
+	MemLock,
+	/// Size in bytes of core dumps (`RLIMIT_CORE`)
+	Core,
+	/// Size in bytes of the files the process can write (`RLIMIT_FSIZE`)
+	FSize,
+	/// Size in bytes of the main thread's stack (`RLIMIT_STACK`)
+	Stack,
+	/// CPU time in seconds (`RLIMIT_CPU`)
+	Cpu,
+	/// Size in bytes of the data segment (`RLIMIT_DATA`)
+	Data,
+	/// Size in bytes of the virtual address space (`RLIMIT_AS`)
+	As,
+	/// Resident set size in bytes (`RLIMIT_RSS`)
+	Rss,
+	/// Bytes of POSIX message queues of the user (`RLIMIT_MSGQUEUE`), Linux only
+	MsgQueue,
+	/// Ceiling of the nice value as `20 - nice`, 40 allowing -20 (`RLIMIT_NICE`),
+	/// Linux only
+	Nice,
+	/// Ceiling of the real-time scheduling priority (`RLIMIT_RTPRIO`), Linux only
+	RtPrio,
+	/// Number of signals queued for the user (`RLIMIT_SIGPENDING`), Linux only
+	SigPending,
+	/// Number of file locks (`RLIMIT_LOCKS`), Linux only
+	Locks,
+	/// CPU time in microseconds a real-time task can use without a blocking
+	/// system call (`RLIMIT_RTTIME`), Linux only
+	RtTime,
+	/// Number of kqueues of the user (`RLIMIT_KQUEUES`), FreeBSD only
+	KQueues,
+	/// Number of pseudo-terminals of the user (`RLIMIT_NPTS`), FreeBSD only
+	Npts,
+	/// Bytes of socket buffers of the user (`RLIMIT_SBSIZE`), FreeBSD only
+	SbSize,
+}
+
+impl Resource {
+	/// All resources, in the order used by reports
+	pub const ALL: &'static [Resource] = &[
+		Resource::NoFile,
+		Resource::NProc,
+		Resource::MemLock,
+		Resource::Core,
+		Resource::FSize,
+		Resource::Stack,
+		Resource::Cpu,
+		Resource::Data,
+		Resource::As,
+		Resource::Rss,
+		Resource::MsgQueue,
+		Resource::Nice,
+		Resource::RtPrio,
+		Resource::SigPending,
+		Resource::Locks,
+		Resource::RtTime,
+		Resource::KQueues,
+		Resource::Npts,
+		Resource::SbSize,
+	];
+
+	/// Lowercase short name, as used by config files
+	pub(crate) fn name(self) -> &'static str {
+		match self {
+			Resource::NoFile => "nofile",
+			Resource::NProc => "nproc",
+			Resource::MemLock => "memlock",
+			Resource::Core => "core",
+			Resource::FSize => "fsize",
+			Resource::Stack => "stack",
+			Resource::Cpu => "cpu",
+			Resource::Data => "data",
+			Resource::As => "as",
+			Resource::Rss => "rss",
+			Resource::MsgQueue => "msgqueue",
+			Resource::Nice => "nice",
+			Resource::RtPrio => "rtprio",
+			Resource::SigPending => "sigpending",
+			Resource::Locks => "locks",
+			Resource::RtTime => "rttime",
+			Resource::KQueues => "kqueues",
+			Resource::Npts => "npts",
+			Resource::SbSize => "sbsize",
+		}
+	}
+
+	/// Whether the current platform has this resource
+	pub fn is_available(self) -> bool {
+		crate::backend::is_available(self)
+	}
+}
+
+/// Lowercase short name, e.g. `nofile`
+impl fmt::Display for Resource {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.pad(self.name())
+	}
+}
+
+/// Parses the short name or the C constant name, ignoring case, e.g. `nofile`
+/// or `RLIMIT_NOFILE`
+impl FromStr for Resource {
+	type Err = Error;
+
+	fn from_str(s: &str) -> Result<Self, Error> {
+		let trimmed = s.trim();
+		let name = match trimmed.get(..7) {
+			Some(prefix) if prefix.eq_ignore_ascii_case("rlimit_") => &trimmed[7..],
+			_ => trimmed,
+		};
+		Resource::ALL
+			.iter()
+			.copied()
+			.find(|resource| resource.name().eq_ignore_ascii_case(name))
+			.ok_or_else(|| Error::InvalidValue(s.to_owned()))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn round_trips_through_display() {
+		for &resource in Resource::ALL {
+			assert_eq!(resource.to_string().parse::<Resource>().unwrap(), resource);
+		}
+	}
+
+	#[test]
+	fn parses_any_case_and_rlimit_prefix() {
+		for &resource in Resource::ALL {
+			let name = resource.to_string();
+			let upper = name.to_ascii_uppercase();
+			assert_eq!(upper.parse::<Resource>().unwrap(), resource);
+			assert_eq!(format!("RLIMIT_{upper}").parse::<Resource>().unwrap(), resource);
+			assert_eq!(format!("rlimit_{name}").parse::<Resource>().unwrap(), resource);
+		}
+		assert_eq!("NoFile".parse::<Resource>().unwrap(), Resource::NoFile);
+		assert_eq!(" RLIMIT_NOFILE ".parse::<Resource>().unwrap(), Resource::NoFile);
+	}
+
+	#[test]
+	fn rejects_unknown_names() {
+		for name in ["", "rlimit_", "RLIMIT", "files", "nofiles", "RLIMIT_RLIMIT_NOFILE"] {
+			assert!(matches!(name.parse::<Resource>(), Err(Error::InvalidValue(_))), "{name}");
+		}
+	}
+}
@@ -0,0 +1,61 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Conversions from and to the `rlimit` crate's resources.
+//!
+//! Its limit values are the same raw `u64`s as in
+//! [`Rlimit`](crate::backend::Rlimit), [`rlimit::INFINITY`] being
+//! [`RLIM_INFINITY`](crate::backend::RLIM_INFINITY).
+
+use crate::{Error, Resource};
+
+impl From<Resource> for rlimit::Resource {
+	fn from(resource: Resource) -> Self {
+		match resource {
+			Resource::NoFile => rlimit::Resource::NOFILE,
+			Resource::NProc => rlimit::Resource::NPROC,
+			Resource::MemLock => rlimit::Resource::MEMLOCK,
+			Resource::Core => rlimit::Resource::CORE,
+			Resource::FSize => rlimit::Resource::FSIZE,
+			Resource::Stack => rlimit::Resource::STACK,
+			Resource::Cpu => rlimit::Resource::CPU,
+			Resource::Data => rlimit::Resource::DATA,
+			Resource::As => rlimit::Resource::AS,
+			Resource::Rss => rlimit::Resource::RSS,
+			Resource::MsgQueue => rlimit::Resource::MSGQUEUE,
+			Resource::Nice => rlimit::Resource::NICE,
+			Resource::RtPrio => rlimit::Resource::RTPRIO,
+			Resource::SigPending => rlimit::Resource::SIGPENDING,
+			Resource::Locks => rlimit::Resource::LOCKS,
+			Resource::RtTime => rlimit::Resource::RTTIME,
+			Resource::KQueues => rlimit::Resource::KQUEUES,
+			Resource::Npts => rlimit::Resource::NPTS,
+			Resource::SbSize => rlimit::Resource::SBSIZE,
+		}
+	}
+}
+
+/// Fails with [`Error::InvalidValue`] for resources this crate doesn't have,
+/// e.g. `RLIMIT_SWAP`
+impl TryFrom<rlimit::Resource> for Resource {
+	type Error = Error;
+
+	fn try_from(resource: rlimit::Resource) -> Result<Self, Error> {
+		Resource::ALL
+			.iter()
+			.copied()
+			.find(|&candidate| rlimit::Resource::from(candidate) == resource)
+			.ok_or_else(|| Error::InvalidValue(resource.as_name().to_owned()))
+	}
+}
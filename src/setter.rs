@@ -0,0 +1,211 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::backend::{Rlimit, RlimitOps, System};
+use crate::{Error, Resource};
+use std::io;
+
+/// Whether a hard limit may be lowered, which an unprivileged process can't
+/// undo, so that a bad config value doesn't lower it by accident
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HardLimitLowering {
+	/// Fail with [`Error::WouldLowerHardLimit`] rather than lowering the hard
+	/// limit
+	#[default]
+	Refuse,
+	/// Lower the hard limit when asked to
+	Allow,
+}
+
+/// Set the soft open files limit, raising the hard limit along if it's below
+/// `limit`, which needs privileges. Returns the previous limits.
+pub fn set_soft_fd_limit(limit: u64) -> Result<Rlimit, Error> {
+	set_soft_limit(Resource::NoFile, limit)
+}
+
+/// Set the hard open files limit, lowering the soft limit along if it's above
+/// `limit`. Returns the previous limits.
+///
+/// Raising the hard limit needs privileges, and an unprivileged process can't
+/// raise it back after lowering it, so lowering it fails unless `lowering` is
+/// [`HardLimitLowering::Allow`].
+pub fn set_hard_fd_limit(limit: u64, lowering: HardLimitLowering) -> Result<Rlimit, Error> {
+	set_hard_limit(Resource::NoFile, limit, lowering)
+}
+
+/// Same as [`set_soft_fd_limit`] for any resource
+pub fn set_soft_limit(resource: Resource, limit: u64) -> Result<Rlimit, Error> {
+	set_soft_limit_with(&System, resource, limit)
+}
+
+/// Same as [`set_soft_limit`] using the given backend
+pub fn set_soft_limit_with<B: RlimitOps>(
+	backend: &B,
+	resource: Resource,
+	limit: u64,
+) -> Result<Rlimit, Error> {
+	let previous = crate::get_limit_with(backend, resource)?;
+	let rlim = Rlimit { soft: limit, hard: previous.hard.max(limit) };
+	swap(backend, resource, previous, rlim, previous.soft, limit)
+}
+
+/// Same as [`set_hard_fd_limit`] for any resource
+pub fn set_hard_limit(
+	resource: Resource,
+	limit: u64,
+	lowering: HardLimitLowering,
+) -> Result<Rlimit, Error> {
+	set_hard_limit_with(&System, resource, limit, lowering)
+}
+
+/// Same as [`set_hard_limit`] using the given backend
+pub fn set_hard_limit_with<B: RlimitOps>(
+	backend: &B,
+	resource: Resource,
+	limit: u64,
+	lowering: HardLimitLowering,
+) -> Result<Rlimit, Error> {
+	let previous = crate::get_limit_with(backend, resource)?;
+	check_lowering(resource, previous.hard, limit, lowering)?;
+	let rlim = Rlimit { soft: previous.soft.min(limit), hard: limit };
+	swap(backend, resource, previous, rlim, previous.hard, limit)
+}
+
+/// Fail if the hard limit would be lowered without being allowed to
+pub(crate) fn check_lowering(
+	resource: Resource,
+	hard: u64,
+	requested: u64,
+	lowering: HardLimitLowering,
+) -> Result<(), Error> {
+	match lowering {
+		HardLimitLowering::Refuse if requested < hard => {
+			Err(Error::WouldLowerHardLimit { resource, hard, requested })
+		}
+		_ => Ok(()),
+	}
+}
+
+// Both limits are set by one call, so the soft limit never ends up above the
+// hard one in between
+fn swap<B: RlimitOps>(
+	backend: &B,
+	resource: Resource,
+	previous: Rlimit,
+	rlim: Rlimit,
+	from: u64,
+	to: u64,
+) -> Result<Rlimit, Error> {
+	let replaced = backend
+		.swap_rlimit(resource, rlim)
+		.map_err(|error| set_limit_error(resource, previous, rlim, from, to, error))?;
+	let previous = replaced.unwrap_or(previous);
+	crate::audit::record(backend.pid(), resource, previous, rlim);
+	Ok(previous)
+}
+
+/// Error of setting limits, telling apart the hard limits a user namespace
+/// doesn't let raise from other failures
+pub(crate) fn set_limit_error(
+	resource: Resource,
+	previous: Rlimit,
+	rlim: Rlimit,
+	from: u64,
+	to: u64,
+	error: io::Error,
+) -> Error {
+	if rlim.hard > previous.hard
+		&& error.kind() == io::ErrorKind::PermissionDenied
+		&& crate::user_namespace().is_some()
+	{
+		return Error::HardLimitCeilingInUserNamespace {
+			resource,
+			ceiling: previous.hard,
+			requested: rlim.hard,
+			error,
+		};
+	}
+	Error::FailedToSetLimit { from, to, error }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::simulated::Simulated;
+
+	#[test]
+	fn soft_above_hard_raises_hard() {
+		let backend = Simulated::new(256, 1024).privileged();
+		let previous = set_soft_limit_with(&backend, Resource::NoFile, 4096).unwrap();
+		assert_eq!(previous, Rlimit { soft: 256, hard: 1024 });
+		assert_eq!(backend.limit(Resource::NoFile), Some(Rlimit { soft: 4096, hard: 4096 }));
+	}
+
+	#[test]
+	fn soft_below_hard_keeps_hard() {
+		let backend = Simulated::new(256, 1024);
+		let previous = set_soft_limit_with(&backend, Resource::NoFile, 512).unwrap();
+		assert_eq!(previous, Rlimit { soft: 256, hard: 1024 });
+		assert_eq!(backend.limit(Resource::NoFile), Some(Rlimit { soft: 512, hard: 1024 }));
+	}
+
+	#[test]
+	fn hard_below_soft_lowers_soft() {
+		let backend = Simulated::new(1024, 4096);
+		let previous =
+			set_hard_limit_with(&backend, Resource::NoFile, 512, HardLimitLowering::Allow).unwrap();
+		assert_eq!(previous, Rlimit { soft: 1024, hard: 4096 });
+		assert_eq!(backend.limit(Resource::NoFile), Some(Rlimit { soft: 512, hard: 512 }));
+	}
+
+	#[test]
+	fn raising_hard_keeps_soft() {
+		let backend = Simulated::new(256, 1024).privileged();
+		let previous =
+			set_hard_limit_with(&backend, Resource::NoFile, 4096, HardLimitLowering::Refuse)
+				.unwrap();
+		assert_eq!(previous, Rlimit { soft: 256, hard: 1024 });
+		assert_eq!(backend.limit(Resource::NoFile), Some(Rlimit { soft: 256, hard: 4096 }));
+	}
+
+	#[test]
+	fn refuses_lowering_hard() {
+		let backend = Simulated::new(256, 4096);
+		let error =
+			set_hard_limit_with(&backend, Resource::NoFile, 1024, HardLimitLowering::Refuse)
+				.unwrap_err();
+		assert!(matches!(
+			error,
+			Error::WouldLowerHardLimit { resource: Resource::NoFile, hard: 4096, requested: 1024 }
+		));
+		assert_eq!(backend.limit(Resource::NoFile), Some(Rlimit { soft: 256, hard: 4096 }));
+	}
+
+	#[test]
+	fn allows_lowering_hard() {
+		let backend = Simulated::new(256, 4096);
+		set_hard_limit_with(&backend, Resource::NoFile, 1024, HardLimitLowering::Allow).unwrap();
+		assert_eq!(backend.limit(Resource::NoFile), Some(Rlimit { soft: 256, hard: 1024 }));
+	}
+
+	#[test]
+	fn checks_lowering() {
+		let check =
+			|requested, lowering| check_lowering(Resource::NoFile, 4096, requested, lowering);
+		assert!(check(1024, HardLimitLowering::Refuse).is_err());
+		assert!(check(4096, HardLimitLowering::Refuse).is_ok());
+		assert!(check(8192, HardLimitLowering::Refuse).is_ok());
+		assert!(check(1024, HardLimitLowering::Allow).is_ok());
+	}
+}
@@ -0,0 +1,181 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! In-memory backend for exercising limit handling deterministically on any
+//! platform.
+
+use crate::backend::{Rlimit, RlimitOps};
+use crate::Resource;
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::{Mutex, MutexGuard};
+
+/// Simulated process resource limits.
+///
+/// Mimics the kernel rules: the soft limit can't exceed the hard limit nor, for
+/// open files, the kernel ceiling when one is configured, and the hard limit can
+/// only be raised when privileged. Resources without configured limits are
+/// reported as unsupported.
+#[derive(Debug)]
+pub struct Simulated {
+	state: Mutex<State>,
+}
+
+#[derive(Debug, Clone)]
+struct State {
+	limits: BTreeMap<Resource, Rlimit>,
+	maxfiles: Option<u64>,
+	system_maxfiles: Option<u64>,
+	open_fds: u64,
+	pid: Option<u32>,
+	privileged: bool,
+	fail_getrlimit: Option<i32>,
+	fail_setrlimit: Option<i32>,
+	fail_sysctl: Option<i32>,
+}
+
+impl Simulated {
+	/// Create a backend with the given soft and hard open file limits and no
+	/// kernel ceiling
+	pub fn new(soft: u64, hard: u64) -> Self {
+		Simulated {
+			state: Mutex::new(State {
+				limits: BTreeMap::from([(Resource::NoFile, Rlimit { soft, hard })]),
+				maxfiles: None,
+				system_maxfiles: None,
+				open_fds: 0,
+				pid: None,
+				privileged: false,
+				fail_getrlimit: None,
+				fail_setrlimit: None,
+				fail_sysctl: None,
+			}),
+		}
+	}
+
+	/// Set the soft and hard limits of a resource
+	pub fn with_limit(self, resource: Resource, soft: u64, hard: u64) -> Self {
+		self.update(|state| {
+			state.limits.insert(resource, Rlimit { soft, hard });
+		})
+	}
+
+	/// Set the kernel ceiling reported as the sysctl max files per process
+	pub fn with_maxfiles(self, maxfiles: u64) -> Self {
+		self.update(|state| state.maxfiles = Some(maxfiles))
+	}
+
+	/// Set the system-wide max number of open files
+	pub fn with_system_maxfiles(self, maxfiles: u64) -> Self {
+		self.update(|state| state.system_maxfiles = Some(maxfiles))
+	}
+
+	/// Set the number of file descriptors reported as open
+	pub fn with_open_fds(self, open_fds: u64) -> Self {
+		self.update(|state| state.open_fds = open_fds)
+	}
+
+	/// Report the limits as the ones of another process with the given PID
+	pub fn with_pid(self, pid: u32) -> Self {
+		self.update(|state| state.pid = Some(pid))
+	}
+
+	/// Allow raising the hard limit, as a privileged process could
+	pub fn privileged(self) -> Self {
+		self.update(|state| state.privileged = true)
+	}
+
+	/// Make fetching the limits fail with the given OS error code
+	pub fn fail_getrlimit(self, errno: i32) -> Self {
+		self.update(|state| state.fail_getrlimit = Some(errno))
+	}
+
+	/// Make setting the limits fail with the given OS error code
+	pub fn fail_setrlimit(self, errno: i32) -> Self {
+		self.update(|state| state.fail_setrlimit = Some(errno))
+	}
+
+	/// Make reading the sysctl value fail with the given OS error code
+	pub fn fail_sysctl(self, errno: i32) -> Self {
+		self.update(|state| state.fail_sysctl = Some(errno))
+	}
+
+	/// Current simulated limits of the resource
+	pub fn limit(&self, resource: Resource) -> Option<Rlimit> {
+		self.state().limits.get(&resource).copied()
+	}
+
+	fn update(self, f: impl FnOnce(&mut State)) -> Self {
+		f(&mut self.state());
+		self
+	}
+
+	fn state(&self) -> MutexGuard<'_, State> {
+		self.state.lock().unwrap_or_else(|e| e.into_inner())
+	}
+}
+
+impl RlimitOps for Simulated {
+	fn getrlimit(&self, resource: Resource) -> io::Result<Rlimit> {
+		let state = self.state();
+		if let Some(errno) = state.fail_getrlimit {
+			return Err(io::Error::from_raw_os_error(errno));
+		}
+		state.limits.get(&resource).copied().ok_or_else(|| io::ErrorKind::Unsupported.into())
+	}
+
+	fn setrlimit(&self, resource: Resource, rlim: Rlimit) -> io::Result<()> {
+		let mut state = self.state();
+		if let Some(errno) = state.fail_setrlimit {
+			return Err(io::Error::from_raw_os_error(errno));
+		}
+		let Some(current) = state.limits.get(&resource).copied() else {
+			return Err(io::ErrorKind::Unsupported.into());
+		};
+		let maxfiles = state.maxfiles.filter(|_| resource == Resource::NoFile);
+		if rlim.soft > rlim.hard || maxfiles.is_some_and(|max| rlim.soft > max) {
+			return Err(io::Error::from_raw_os_error(libc::EINVAL));
+		}
+		if rlim.hard > current.hard && !state.privileged {
+			return Err(io::Error::from_raw_os_error(libc::EPERM));
+		}
+
+		state.limits.insert(resource, rlim);
+		Ok(())
+	}
+
+	fn sysctl_maxfiles(&self) -> io::Result<Option<u64>> {
+		let state = self.state();
+		match state.fail_sysctl {
+			Some(errno) => Err(io::Error::from_raw_os_error(errno)),
+			None => Ok(state.maxfiles),
+		}
+	}
+
+	fn system_maxfiles(&self) -> io::Result<Option<u64>> {
+		let state = self.state();
+		match state.fail_sysctl {
+			Some(errno) => Err(io::Error::from_raw_os_error(errno)),
+			None => Ok(state.system_maxfiles),
+		}
+	}
+
+	fn open_fds(&self) -> io::Result<u64> {
+		Ok(self.state().open_fds)
+	}
+
+	fn pid(&self) -> Option<u32> {
+		self.state().pid
+	}
+}
@@ -0,0 +1,243 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::backend::RlimitOps;
+use crate::{Error, Limit, LimitEntry, Resource};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Limits of every resource the platform has at one point in time, compared
+/// with [`diff`](Self::diff) to check what changed, e.g. that only the open
+/// files limit was touched:
+///
+/// ```no_run
+/// # use fdlimit::{raise_fd_limit, LimitsSnapshot, Resource};
+/// let before = LimitsSnapshot::capture()?;
+/// raise_fd_limit()?;
+/// let diff = before.diff(&LimitsSnapshot::capture()?);
+/// assert!(diff.resources().all(|resource| resource == Resource::NoFile));
+/// # Ok::<(), fdlimit::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LimitsSnapshot {
+	/// Limits by resource
+	pub limits: BTreeMap<Resource, LimitEntry>,
+}
+
+/// Which way a limit moved
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+	/// The limit is higher
+	Raised,
+	/// The limit is lower
+	Lowered,
+	/// The limit is the same
+	Unchanged,
+}
+
+/// Value of a limit in two snapshots
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ValueChange {
+	/// Value in the earlier snapshot
+	pub before: Limit,
+	/// Value in the later snapshot
+	pub after: Limit,
+}
+
+/// Resource whose soft or hard limit differs between two snapshots
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LimitChange {
+	/// Resource that changed
+	pub resource: Resource,
+	/// Soft limits in both snapshots
+	pub soft: ValueChange,
+	/// Hard limits in both snapshots
+	pub hard: ValueChange,
+}
+
+/// Resources that changed between two snapshots, see [`LimitsSnapshot::diff`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SnapshotDiff {
+	/// Changes by resource, resources missing from either snapshot are left
+	/// out
+	pub changes: Vec<LimitChange>,
+}
+
+impl LimitsSnapshot {
+	/// Read the limits of the current process
+	pub fn capture() -> Result<Self, Error> {
+		Self::capture_with(&crate::backend::System)
+	}
+
+	/// Same as [`capture`](Self::capture) using the given backend
+	pub fn capture_with<B: RlimitOps>(backend: &B) -> Result<Self, Error> {
+		let limits = crate::supported_limits_with(backend)?
+			.into_iter()
+			.map(|entry| (entry.resource, entry))
+			.collect();
+		Ok(LimitsSnapshot { limits })
+	}
+
+	/// Limits of the resource, `None` if the platform doesn't have it
+	pub fn get(&self, resource: Resource) -> Option<&LimitEntry> {
+		self.limits.get(&resource)
+	}
+
+	/// Resources whose limits differ in the later snapshot
+	pub fn diff(&self, later: &LimitsSnapshot) -> SnapshotDiff {
+		let changes = self
+			.limits
+			.values()
+			.filter_map(|before| {
+				let after = later.limits.get(&before.resource)?;
+				(before != after).then_some(LimitChange {
+					resource: before.resource,
+					soft: ValueChange { before: before.soft, after: after.soft },
+					hard: ValueChange { before: before.hard, after: after.hard },
+				})
+			})
+			.collect();
+		SnapshotDiff { changes }
+	}
+}
+
+impl ValueChange {
+	/// Which way the value moved
+	pub fn direction(&self) -> Direction {
+		match self.after.cmp(&self.before) {
+			Ordering::Greater => Direction::Raised,
+			Ordering::Less => Direction::Lowered,
+			Ordering::Equal => Direction::Unchanged,
+		}
+	}
+}
+
+impl SnapshotDiff {
+	/// Whether no limit changed
+	pub fn is_empty(&self) -> bool {
+		self.changes.is_empty()
+	}
+
+	/// Resources that changed
+	pub fn resources(&self) -> impl Iterator<Item = Resource> + '_ {
+		self.changes.iter().map(|change| change.resource)
+	}
+
+	/// Change of the resource, `None` if its limits are the same
+	pub fn get(&self, resource: Resource) -> Option<&LimitChange> {
+		self.changes.iter().find(|change| change.resource == resource)
+	}
+}
+
+impl fmt::Display for Direction {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.pad(match self {
+			Direction::Raised => "raised",
+			Direction::Lowered => "lowered",
+			Direction::Unchanged => "unchanged",
+		})
+	}
+}
+
+/// For example `1024 -> 65536 (raised)`, or just the value when unchanged
+impl fmt::Display for ValueChange {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self.direction() {
+			Direction::Unchanged => write!(f, "{}", self.before),
+			direction => write!(f, "{} -> {} ({direction})", self.before, self.after),
+		}
+	}
+}
+
+/// For example `nofile soft 1024 -> 65536 (raised), hard 524288`
+impl fmt::Display for LimitChange {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{} soft {}, hard {}", self.resource, self.soft, self.hard)
+	}
+}
+
+/// One line per change, `no limit changed` when empty
+impl fmt::Display for SnapshotDiff {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if self.changes.is_empty() {
+			return f.write_str("no limit changed");
+		}
+		for (i, change) in self.changes.iter().enumerate() {
+			if i > 0 {
+				f.write_str("\n")?;
+			}
+			write!(f, "{change}")?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::backend::{Rlimit, RLIM_INFINITY};
+	use crate::simulated::Simulated;
+
+	fn change(before: Limit, after: Limit) -> ValueChange {
+		ValueChange { before, after }
+	}
+
+	#[test]
+	fn directions() {
+		let (low, high) = (Limit::Finite(1024), Limit::Finite(4096));
+		assert_eq!(change(low, high).direction(), Direction::Raised);
+		assert_eq!(change(high, low).direction(), Direction::Lowered);
+		assert_eq!(change(low, low).direction(), Direction::Unchanged);
+		assert_eq!(change(high, Limit::Unlimited).direction(), Direction::Raised);
+		assert_eq!(change(Limit::Unlimited, high).direction(), Direction::Lowered);
+	}
+
+	#[test]
+	fn diffs_changed_resources() {
+		let backend = Simulated::new(1024, 4096).with_limit(Resource::NProc, 100, 200).with_limit(
+			Resource::Stack,
+			8192,
+			RLIM_INFINITY,
+		);
+		let before = LimitsSnapshot::capture_with(&backend).unwrap();
+		assert!(before.diff(&before).is_empty());
+		assert_eq!(before.diff(&before).to_string(), "no limit changed");
+
+		backend.setrlimit(Resource::NoFile, Rlimit { soft: 4096, hard: 4096 }).unwrap();
+		backend.setrlimit(Resource::NProc, Rlimit { soft: 50, hard: 200 }).unwrap();
+		let diff = before.diff(&LimitsSnapshot::capture_with(&backend).unwrap());
+		assert_eq!(diff.resources().collect::<Vec<_>>(), [Resource::NoFile, Resource::NProc]);
+		assert_eq!(diff.get(Resource::Stack), None);
+
+		let nofile = diff.get(Resource::NoFile).unwrap();
+		assert_eq!(nofile.soft.direction(), Direction::Raised);
+		assert_eq!(nofile.hard.direction(), Direction::Unchanged);
+		assert_eq!(diff.get(Resource::NProc).unwrap().soft.direction(), Direction::Lowered);
+		assert_eq!(
+			diff.to_string(),
+			"nofile soft 1024 -> 4096 (raised), hard 4096\n\
+			 nproc soft 100 -> 50 (lowered), hard 200"
+		);
+	}
+
+	#[test]
+	fn leaves_out_missing_resources() {
+		let before = LimitsSnapshot::capture_with(&Simulated::new(1024, 4096)).unwrap();
+		let backend = Simulated::new(1024, 4096).with_limit(Resource::NProc, 100, 200);
+		let after = LimitsSnapshot::capture_with(&backend).unwrap();
+		assert!(before.diff(&after).is_empty());
+		assert!(after.diff(&before).is_empty());
+	}
+}
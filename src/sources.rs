@@ -0,0 +1,215 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{Policy, Resource, Rule};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Where a policy comes from, in increasing precedence: command line flags
+/// override environment variables, which override the config file, which
+/// overrides the defaults built into the program
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Source {
+	/// Defaults built into the program, e.g. set on a [`Builder`](crate::Builder)
+	Default,
+	/// Config file
+	Config,
+	/// `FDLIMIT_*` environment variables, see [`Policy::from_env`]
+	Env,
+	/// Command line flags
+	Cli,
+}
+
+/// Policies from several sources, merged by [`resolve`](Self::resolve).
+///
+/// Each resource takes its whole rule, target and minimum, from the source
+/// with the highest precedence that has one.
+#[derive(Debug, Clone, Default)]
+pub struct PolicySources {
+	layers: BTreeMap<Source, Policy>,
+}
+
+/// Policy merged from its sources, remembering where each rule comes from
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ResolvedPolicy {
+	/// Merged policy
+	pub policy: Policy,
+	/// Origin of each resource's rule
+	pub origins: BTreeMap<Resource, Origin>,
+}
+
+/// Where a resource's rule comes from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Origin {
+	/// Source whose rule won
+	pub source: Source,
+	/// Rules of the sources with lower precedence, overridden, highest first
+	pub overridden: Vec<(Source, Rule)>,
+}
+
+impl PolicySources {
+	/// No source yet
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Set the policy of a source, replacing the previous one of that source
+	pub fn with(mut self, source: Source, policy: Policy) -> Self {
+		self.layers.insert(source, policy);
+		self
+	}
+
+	/// Merge the sources by precedence
+	pub fn resolve(&self) -> ResolvedPolicy {
+		let mut resolved = ResolvedPolicy::default();
+		for (&source, policy) in self.layers.iter().rev() {
+			for (&resource, rule) in &policy.rules {
+				match resolved.origins.get_mut(&resource) {
+					Some(origin) => origin.overridden.push((source, rule.clone())),
+					None => {
+						resolved.policy.rules.insert(resource, rule.clone());
+						resolved
+							.origins
+							.insert(resource, Origin { source, overridden: Vec::new() });
+					}
+				}
+			}
+		}
+		resolved
+	}
+}
+
+impl ResolvedPolicy {
+	/// Source whose rule the resource follows, `None` if no source has one
+	pub fn source(&self, resource: Resource) -> Option<Source> {
+		self.origins.get(&resource).map(|origin| origin.source)
+	}
+}
+
+impl fmt::Display for Source {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.pad(match self {
+			Source::Default => "default",
+			Source::Config => "config",
+			Source::Env => "env",
+			Source::Cli => "cli",
+		})
+	}
+}
+
+/// One line per resource, e.g. `nofile: 4096 from env, overriding config
+/// (max), default (max)`
+impl fmt::Display for ResolvedPolicy {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for (i, (resource, rule)) in self.policy.rules.iter().enumerate() {
+			if i > 0 {
+				f.write_str("\n")?;
+			}
+			write!(f, "{resource}: {rule}")?;
+			let Some(origin) = self.origins.get(resource) else { continue };
+			write!(f, " from {}", origin.source)?;
+			for (i, (source, rule)) in origin.overridden.iter().enumerate() {
+				f.write_str(if i == 0 { ", overriding " } else { ", " })?;
+				write!(f, "{source} ({rule})")?;
+			}
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::Strategy;
+
+	fn rule(target: Strategy) -> Rule {
+		Rule { target, minimum: None }
+	}
+
+	fn policy(rules: &[(Resource, Strategy)]) -> Policy {
+		Policy { rules: rules.iter().map(|&(resource, target)| (resource, rule(target))).collect() }
+	}
+
+	fn sources() -> PolicySources {
+		PolicySources::new()
+			.with(Source::Env, policy(&[(Resource::NoFile, Strategy::Exact(4096))]))
+			.with(
+				Source::Default,
+				policy(&[
+					(Resource::NoFile, Strategy::MaxAvailable),
+					(Resource::NProc, Strategy::MaxAvailable),
+				]),
+			)
+			.with(
+				Source::Config,
+				policy(&[
+					(Resource::NoFile, Strategy::MaxAvailable),
+					(Resource::NProc, Strategy::Exact(512)),
+				]),
+			)
+	}
+
+	#[test]
+	fn follows_precedence() {
+		let resolved = sources().resolve();
+		assert_eq!(resolved.source(Resource::NoFile), Some(Source::Env));
+		assert_eq!(resolved.source(Resource::NProc), Some(Source::Config));
+		assert_eq!(resolved.source(Resource::Stack), None);
+		assert_eq!(
+			resolved.policy,
+			policy(&[
+				(Resource::NoFile, Strategy::Exact(4096)),
+				(Resource::NProc, Strategy::Exact(512))
+			])
+		);
+
+		let resolved = sources()
+			.with(Source::Cli, policy(&[(Resource::NoFile, Strategy::Exact(1024))]))
+			.resolve();
+		assert_eq!(resolved.source(Resource::NoFile), Some(Source::Cli));
+		assert_eq!(resolved.policy.rules[&Resource::NoFile], rule(Strategy::Exact(1024)));
+	}
+
+	#[test]
+	fn lists_overridden_rules_highest_first() {
+		let resolved = sources().resolve();
+		assert_eq!(
+			resolved.origins[&Resource::NoFile].overridden,
+			[
+				(Source::Config, rule(Strategy::MaxAvailable)),
+				(Source::Default, rule(Strategy::MaxAvailable))
+			]
+		);
+		assert_eq!(
+			resolved.origins[&Resource::NProc].overridden,
+			[(Source::Default, rule(Strategy::MaxAvailable))]
+		);
+	}
+
+	#[test]
+	fn replaces_the_policy_of_a_source() {
+		let resolved = sources().with(Source::Env, Policy::default()).resolve();
+		assert_eq!(resolved.source(Resource::NoFile), Some(Source::Config));
+	}
+
+	#[test]
+	fn displays_origins() {
+		assert_eq!(
+			sources().resolve().to_string(),
+			"nofile: 4096 from env, overriding config (max), default (max)\n\
+			 nproc: 512 from config, overriding default (max)"
+		);
+		assert_eq!(PolicySources::new().resolve().to_string(), "");
+	}
+}
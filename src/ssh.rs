@@ -0,0 +1,85 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Detection of processes started over SSH, whose limits come from sshd's
+//! session rather than from the service manager.
+
+use std::fmt;
+
+/// SSH session the process was started from, see [`ssh_session`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SshSession {
+	/// Address and port of the client, from `SSH_CONNECTION`
+	pub client: Option<(String, u16)>,
+	/// Process of sshd serving the session, when found among the ancestors
+	pub sshd_pid: Option<u32>,
+}
+
+/// SSH session the current process was started from, `None` when not
+/// started over SSH.
+///
+/// Told from the variables sshd sets, `SSH_CONNECTION` and `SSH_CLIENT`, and
+/// on Linux from an `sshd` ancestor process, which also catches sessions
+/// clearing the environment.
+pub fn ssh_session() -> Option<SshSession> {
+	let connection = std::env::var("SSH_CONNECTION")
+		.or_else(|_| std::env::var("SSH_CLIENT"))
+		.ok()
+		.filter(|connection| !connection.is_empty());
+	let client = connection.as_deref().and_then(|connection| {
+		let mut words = connection.split_whitespace();
+		Some((words.next()?.to_owned(), words.next()?.parse().ok()?))
+	});
+	let sshd_pid = sshd_ancestor();
+	(connection.is_some() || sshd_pid.is_some()).then_some(SshSession { client, sshd_pid })
+}
+
+/// Closest `sshd` or `sshd-session` ancestor of the process
+#[cfg(target_os = "linux")]
+fn sshd_ancestor() -> Option<u32> {
+	let mut pid = std::process::id();
+	// Bounded in case the process tree changes under the walk
+	for _ in 0..64 {
+		let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+		// The command name is in parentheses and may hold spaces and parentheses
+		let (name, fields) = stat.split_once(" (")?.1.rsplit_once(") ")?;
+		if pid != std::process::id() && (name == "sshd" || name == "sshd-session") {
+			return Some(pid);
+		}
+		pid = fields.split_whitespace().nth(1)?.parse().ok()?;
+		if pid <= 1 {
+			return None;
+		}
+	}
+	None
+}
+
+#[cfg(not(target_os = "linux"))]
+fn sshd_ancestor() -> Option<u32> {
+	None
+}
+
+/// For example `ssh from 192.0.2.7 port 52114, sshd 812`
+impl fmt::Display for SshSession {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("ssh")?;
+		if let Some((address, port)) = &self.client {
+			write!(f, " from {address} port {port}")?;
+		}
+		if let Some(pid) = self.sshd_pid {
+			write!(f, ", sshd {pid}")?;
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,297 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::backend::RlimitOps;
+use crate::{Error, Outcome, Policy, Report, Resource, Strategy};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+const HEADER: &str = "# fdlimit state, <resource> <target> <limit> per line";
+
+/// Limits achieved by the last policy applied, saved to a state file so a
+/// restarted process can tell when it gets less than it used to, e.g. after
+/// an OS upgrade changed the defaults.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LimitState {
+	/// Target and soft limit reached for each resource raised
+	pub entries: BTreeMap<Resource, StateEntry>,
+}
+
+/// Limit reached for one resource
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StateEntry {
+	/// Strategy of the policy's rule
+	pub target: Strategy,
+	/// Soft limit it reached
+	pub limit: u64,
+}
+
+/// Resource whose limit is now below what the same target reached before
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Regression {
+	/// Resource whose limit regressed
+	pub resource: Resource,
+	/// Strategy of the policy's rule, the same in both runs
+	pub target: Strategy,
+	/// Soft limit reached by the previous run
+	pub previous: u64,
+	/// Soft limit reached now
+	pub current: u64,
+}
+
+impl LimitState {
+	/// Limits reached by the resources the report raised, taking the targets
+	/// from the policy applied
+	pub fn from_report(policy: &Policy, report: &Report) -> Self {
+		let entries = report
+			.results
+			.iter()
+			.filter_map(|(resource, result)| {
+				let target = policy.rules.get(resource)?.target;
+				match result {
+					Ok(Outcome::LimitRaised { to, .. }) => {
+						Some((*resource, StateEntry { target, limit: *to }))
+					}
+					_ => None,
+				}
+			})
+			.collect();
+		LimitState { entries }
+	}
+
+	/// Read the state file, `None` if it doesn't exist yet
+	pub fn load(path: impl AsRef<Path>) -> Result<Option<Self>, Error> {
+		let contents = match std::fs::read_to_string(path) {
+			Ok(contents) => contents,
+			Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+			Err(error) => return Err(Error::FailedToPersistState(error)),
+		};
+		contents.parse().map(Some)
+	}
+
+	/// Write the state file, replacing it atomically
+	pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+		let path = path.as_ref();
+		let mut tmp = path.as_os_str().to_owned();
+		tmp.push(".tmp");
+		std::fs::write(&tmp, self.to_string()).map_err(Error::FailedToPersistState)?;
+		std::fs::rename(&tmp, path).map_err(Error::FailedToPersistState)
+	}
+
+	/// Resources now below the limit this state recorded for the same target.
+	///
+	/// Resources whose target changed are left out, a lower limit is expected
+	/// then.
+	pub fn regressions(&self, current: &LimitState) -> Vec<Regression> {
+		current
+			.entries
+			.iter()
+			.filter_map(|(&resource, now)| {
+				let before = self.entries.get(&resource)?;
+				(before.target == now.target && now.limit < before.limit).then_some(Regression {
+					resource,
+					target: now.target,
+					previous: before.limit,
+					current: now.limit,
+				})
+			})
+			.collect()
+	}
+}
+
+/// Raise every resource limit of the policy, carrying on after failures, then
+/// compare the limits reached with the ones
+/// saved in the state file by the previous run and save the new ones
+pub fn apply_policy_with_state(
+	policy: &Policy,
+	path: impl AsRef<Path>,
+) -> Result<(Report, Vec<Regression>), Error> {
+	apply_policy_with_state_with(&crate::backend::System, policy, path)
+}
+
+/// Same as [`apply_policy_with_state`] using the given backend
+pub fn apply_policy_with_state_with<B: RlimitOps>(
+	backend: &B,
+	policy: &Policy,
+	path: impl AsRef<Path>,
+) -> Result<(Report, Vec<Regression>), Error> {
+	let path = path.as_ref();
+	let previous = LimitState::load(path)?;
+	let results = crate::policy::apply_each(policy, |builder| builder.raise_with(backend));
+	let report = Report { results: results.collect() };
+
+	let mut state = LimitState::from_report(policy, &report);
+	let regressions = match &previous {
+		Some(previous) => previous.regressions(&state),
+		None => Vec::new(),
+	};
+	// Resources that failed this time keep their previous entries, so the
+	// next run still compares against the best known limit
+	if let Some(previous) = previous {
+		for (resource, entry) in previous.entries {
+			if policy.rules.contains_key(&resource) {
+				state.entries.entry(resource).or_insert(entry);
+			}
+		}
+	}
+	state.save(path)?;
+	Ok((report, regressions))
+}
+
+impl std::str::FromStr for LimitState {
+	type Err = Error;
+
+	fn from_str(s: &str) -> Result<Self, Error> {
+		let mut entries = BTreeMap::new();
+		for line in
+			s.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#'))
+		{
+			let invalid = || Error::InvalidValue(line.to_owned());
+			let mut fields = line.split_whitespace();
+			let (Some(resource), Some(target), Some(limit), None) =
+				(fields.next(), fields.next(), fields.next(), fields.next())
+			else {
+				return Err(invalid());
+			};
+			let resource = resource.parse()?;
+			let target = target.parse()?;
+			let limit = limit.parse().map_err(|_| invalid())?;
+			entries.insert(resource, StateEntry { target, limit });
+		}
+		Ok(LimitState { entries })
+	}
+}
+
+/// The state file's contents
+impl fmt::Display for LimitState {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		writeln!(f, "{HEADER}")?;
+		for (resource, entry) in &self.entries {
+			writeln!(f, "{resource} {} {}", entry.target, entry.limit)?;
+		}
+		Ok(())
+	}
+}
+
+/// For example `nofile limit regressed from 1048576 to 1024 with target max`
+impl fmt::Display for Regression {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"{} limit regressed from {} to {} with target {}",
+			self.resource, self.previous, self.current, self.target
+		)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::backend::RLIM_INFINITY;
+	use crate::simulated::Simulated;
+	use crate::Rule;
+
+	fn state(entries: &[(Resource, Strategy, u64)]) -> LimitState {
+		let entries = entries
+			.iter()
+			.map(|&(resource, target, limit)| (resource, StateEntry { target, limit }))
+			.collect();
+		LimitState { entries }
+	}
+
+	fn policy(resources: &[Resource]) -> Policy {
+		let rule = Rule { target: Strategy::MaxAvailable, minimum: None };
+		Policy { rules: resources.iter().map(|&resource| (resource, rule.clone())).collect() }
+	}
+
+	#[test]
+	fn round_trips() {
+		let saved = state(&[
+			(Resource::NoFile, Strategy::MaxAvailable, 1 << 20),
+			(Resource::NProc, Strategy::FractionOfHard(0.5), 2048),
+			(Resource::Stack, Strategy::Exact(RLIM_INFINITY), RLIM_INFINITY),
+			(Resource::MemLock, Strategy::AtLeast(65536), 65536),
+		]);
+		let contents = saved.to_string();
+		assert!(contents.starts_with(HEADER));
+		assert_eq!(contents.parse::<LimitState>().unwrap(), saved);
+	}
+
+	#[test]
+	fn rejects_invalid_lines() {
+		for contents in ["nofile max", "nofile max 1024 extra", "nofile max many", "files max 1"] {
+			assert!(contents.parse::<LimitState>().is_err(), "{contents}");
+		}
+	}
+
+	#[test]
+	fn regressions_ignore_changed_targets() {
+		let previous = state(&[
+			(Resource::NoFile, Strategy::MaxAvailable, 4096),
+			(Resource::NProc, Strategy::MaxAvailable, 4096),
+			(Resource::Stack, Strategy::MaxAvailable, 4096),
+		]);
+		let current = state(&[
+			(Resource::NoFile, Strategy::MaxAvailable, 1024),
+			(Resource::NProc, Strategy::Exact(1024), 1024),
+			(Resource::Stack, Strategy::MaxAvailable, 8192),
+		]);
+		let regressions = previous.regressions(&current);
+		assert_eq!(
+			regressions,
+			[Regression {
+				resource: Resource::NoFile,
+				target: Strategy::MaxAvailable,
+				previous: 4096,
+				current: 1024,
+			}]
+		);
+		assert_eq!(
+			regressions[0].to_string(),
+			"nofile limit regressed from 4096 to 1024 with target max"
+		);
+	}
+
+	#[test]
+	fn failed_resources_keep_their_entries() {
+		let path = std::env::temp_dir().join(format!("fdlimit-state-{}", std::process::id()));
+		let policy = policy(&[Resource::NoFile, Resource::NProc]);
+
+		let backend = Simulated::new(256, 4096).with_limit(Resource::NProc, 100, 2048);
+		let (report, regressions) = apply_policy_with_state_with(&backend, &policy, &path).unwrap();
+		assert!(report.is_ok() && regressions.is_empty());
+
+		// No process limit this time, and a lower open files one
+		let backend = Simulated::new(256, 1024);
+		let (report, regressions) = apply_policy_with_state_with(&backend, &policy, &path).unwrap();
+		assert!(!report.is_ok());
+		assert_eq!(regressions.len(), 1);
+		let saved = LimitState::load(&path).unwrap().unwrap();
+		std::fs::remove_file(&path).unwrap();
+		assert_eq!(
+			saved,
+			state(&[
+				(Resource::NoFile, Strategy::MaxAvailable, 1024),
+				(Resource::NProc, Strategy::MaxAvailable, 2048),
+			])
+		);
+	}
+
+	#[test]
+	fn missing_state_is_none() {
+		let path = std::env::temp_dir().join("fdlimit-state-missing");
+		assert_eq!(LimitState::load(path).unwrap(), None);
+	}
+}
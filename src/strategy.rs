@@ -0,0 +1,244 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{Error, Limit};
+use std::cmp;
+use std::fmt;
+use std::str::FromStr;
+
+/// Policy deciding which value the soft limit is raised to
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Strategy {
+	/// Raise to the smaller of the kernel limit and the hard limit
+	#[default]
+	MaxAvailable,
+	/// Set exactly the given value, failing if it's above the maximum available
+	Exact(u64),
+	/// Raise to the maximum available, failing if it's below the given value
+	AtLeast(u64),
+	/// Raise to the given fraction (above 0, at most 1) of the hard limit,
+	/// rounding down.
+	///
+	/// When the hard limit is unlimited the fraction is taken of the kernel
+	/// limit, and the soft limit stays unlimited if there is none.
+	FractionOfHard(f64),
+}
+
+/// Why a strategy can't pick a limit, turned into an [`Error`] or a
+/// [`RawError`](crate::RawError)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum TargetError {
+	LimitUnavailable { requested: u64, available: u64 },
+	InvalidFraction(f64),
+}
+
+impl From<TargetError> for Error {
+	fn from(error: TargetError) -> Self {
+		match error {
+			TargetError::LimitUnavailable { requested, available } => {
+				Error::LimitUnavailable { requested, available }
+			}
+			TargetError::InvalidFraction(fraction) => Error::InvalidFraction(fraction),
+		}
+	}
+}
+
+impl Strategy {
+	/// Compute the new soft limit given the hard limit and the maximum value
+	/// the soft limit can be raised to
+	pub(crate) fn target(&self, hard: u64, available: u64) -> Result<u64, Error> {
+		Ok(self.pick(hard, available)?)
+	}
+
+	/// Same as [`target`](Self::target) without building an [`Error`]
+	pub(crate) fn pick(&self, hard: u64, available: u64) -> Result<u64, TargetError> {
+		match *self {
+			Strategy::MaxAvailable => Ok(available),
+			Strategy::Exact(requested) if requested > available => {
+				Err(TargetError::LimitUnavailable { requested, available })
+			}
+			Strategy::Exact(requested) => Ok(requested),
+			Strategy::AtLeast(requested) if requested > available => {
+				Err(TargetError::LimitUnavailable { requested, available })
+			}
+			Strategy::AtLeast(_) => Ok(available),
+			Strategy::FractionOfHard(fraction) if !(fraction > 0.0 && fraction <= 1.0) => {
+				Err(TargetError::InvalidFraction(fraction))
+			}
+			Strategy::FractionOfHard(fraction) => {
+				let base = match Limit::from_raw(hard) {
+					Limit::Unlimited => available,
+					Limit::Finite(hard) => hard,
+				};
+				match Limit::from_raw(base) {
+					Limit::Unlimited => Ok(available),
+					// Never go down to 0, the process couldn't open anything
+					Limit::Finite(base) => {
+						Ok(cmp::min(fraction_of(base, fraction).max(1), available))
+					}
+				}
+			}
+		}
+	}
+}
+
+/// `value * fraction` rounded down, for a fraction in (0, 1].
+///
+/// Computed exactly from the float's mantissa and exponent, going through `f64`
+/// would lose precision for values above 2^53.
+fn fraction_of(value: u64, fraction: f64) -> u64 {
+	const MANTISSA_BITS: u64 = 52;
+	const MANTISSA_MASK: u64 = (1 << MANTISSA_BITS) - 1;
+
+	let bits = fraction.to_bits();
+	let biased_exponent = (bits >> MANTISSA_BITS) & 0x7ff;
+	let (mantissa, shift) = if biased_exponent == 0 {
+		// Subnormal
+		(bits & MANTISSA_MASK, 1074)
+	} else {
+		((bits & MANTISSA_MASK) | (1 << MANTISSA_BITS), 1075 - biased_exponent)
+	};
+
+	// The fraction is at most 1 so the result fits in u64
+	(value as u128 * mantissa as u128).checked_shr(shift as u32).unwrap_or(0) as u64
+}
+
+/// Parses `max`, an exact value such as `4096`, a minimum such as `>=4096` and
+/// a fraction of the hard limit such as `0.8` or `80%`. Values are parsed as a
+/// [`Limit`], so `64k` and `unlimited` work as well.
+impl FromStr for Strategy {
+	type Err = Error;
+
+	fn from_str(s: &str) -> Result<Self, Error> {
+		let s = s.trim();
+		let invalid = || Error::InvalidValue(s.to_owned());
+
+		if s == "max" {
+			Ok(Strategy::MaxAvailable)
+		} else if let Some(value) = s.strip_prefix(">=") {
+			value
+				.parse()
+				.map(|limit: Limit| Strategy::AtLeast(limit.to_raw()))
+				.map_err(|_| invalid())
+		} else if let Some(percent) = s.strip_suffix('%') {
+			let percent: f64 = percent.trim().parse().map_err(|_| invalid())?;
+			Ok(Strategy::FractionOfHard(percent / 100.0))
+		} else if let Ok(limit) = s.parse::<Limit>() {
+			Ok(Strategy::Exact(limit.to_raw()))
+		} else {
+			s.parse().map(Strategy::FractionOfHard).map_err(|_| invalid())
+		}
+	}
+}
+
+impl fmt::Display for Strategy {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Strategy::MaxAvailable => f.write_str("max"),
+			Strategy::Exact(value) => write!(f, "{value}"),
+			Strategy::AtLeast(value) => write!(f, ">={value}"),
+			// Debug formatting keeps the decimal point so it doesn't parse back as
+			// an exact value
+			Strategy::FractionOfHard(fraction) => write!(f, "{fraction:?}"),
+		}
+	}
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Strategy {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_str(self)
+	}
+}
+
+/// Deserializes from the textual form, or from an integer as an exact value
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Strategy {
+	fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		struct Visitor;
+
+		impl serde::de::Visitor<'_> for Visitor {
+			type Value = Strategy;
+
+			fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+				f.write_str("a limit strategy such as \"max\", 4096, \">=4096\" or \"80%\"")
+			}
+
+			fn visit_u64<E: serde::de::Error>(self, value: u64) -> Result<Strategy, E> {
+				Ok(Strategy::Exact(value))
+			}
+
+			fn visit_i64<E: serde::de::Error>(self, value: i64) -> Result<Strategy, E> {
+				u64::try_from(value)
+					.map(Strategy::Exact)
+					.map_err(|_| E::invalid_value(serde::de::Unexpected::Signed(value), &self))
+			}
+
+			fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Strategy, E> {
+				value.parse().map_err(E::custom)
+			}
+		}
+
+		deserializer.deserialize_any(Visitor)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::backend::RLIM_INFINITY;
+
+	#[test]
+	fn fraction_of_is_exact_above_2_pow_53() {
+		let value = (1 << 60) + 3;
+		assert_eq!(fraction_of(value, 0.5), (1 << 59) + 1);
+		assert_eq!(fraction_of(value, 0.25), (1 << 58));
+		assert_eq!(fraction_of(u64::MAX, 0.5), u64::MAX / 2);
+		// Going through f64 would round to 2^53
+		assert_eq!(fraction_of((1 << 53) + 1, 1.0), (1 << 53) + 1);
+	}
+
+	#[test]
+	fn fraction_of_bounds() {
+		assert_eq!(fraction_of(4096, 0.0), 0);
+		assert_eq!(fraction_of(4096, 1.0), 4096);
+		assert_eq!(fraction_of(u64::MAX, 1.0), u64::MAX);
+		assert_eq!(fraction_of(3, 0.5), 1);
+	}
+
+	#[test]
+	fn fraction_of_hard_rejects_zero() {
+		let error = Strategy::FractionOfHard(0.0).target(4096, 4096).unwrap_err();
+		assert!(matches!(error, Error::InvalidFraction(fraction) if fraction == 0.0));
+		assert!(Strategy::FractionOfHard(1.5).target(4096, 4096).is_err());
+	}
+
+	#[test]
+	fn fraction_of_hard() {
+		assert_eq!(Strategy::FractionOfHard(1.0).target(4096, 4096).unwrap(), 4096);
+		assert_eq!(Strategy::FractionOfHard(0.5).target(4096, 4096).unwrap(), 2048);
+		// Capped by the kernel ceiling, never down to 0
+		assert_eq!(Strategy::FractionOfHard(0.5).target(4096, 1024).unwrap(), 1024);
+		assert_eq!(Strategy::FractionOfHard(f64::MIN_POSITIVE).target(4096, 4096).unwrap(), 1);
+	}
+
+	#[test]
+	fn fraction_of_unlimited_hard() {
+		// Taken of the kernel ceiling when there is one
+		let strategy = Strategy::FractionOfHard(0.5);
+		assert_eq!(strategy.target(RLIM_INFINITY, 1 << 20).unwrap(), 1 << 19);
+		// Unlimited otherwise
+		assert_eq!(strategy.target(RLIM_INFINITY, RLIM_INFINITY).unwrap(), RLIM_INFINITY);
+	}
+}
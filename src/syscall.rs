@@ -0,0 +1,176 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `prlimit64` issued as a raw system call, for static binaries built
+//! without a libc. Only the resource constants come from the `libc` crate.
+//!
+//! Kernels older than 2.6.36 don't have it, `getrlimit` and `setrlimit` are
+//! used instead there.
+
+use crate::backend::Rlimit;
+use std::io;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+compile_error!("the raw-syscalls feature supports x86_64 and aarch64 only");
+
+#[cfg(target_arch = "x86_64")]
+const SYS_PRLIMIT64: usize = 302;
+#[cfg(target_arch = "aarch64")]
+const SYS_PRLIMIT64: usize = 261;
+#[cfg(target_arch = "x86_64")]
+const SYS_GETRLIMIT: usize = 97;
+#[cfg(target_arch = "aarch64")]
+const SYS_GETRLIMIT: usize = 163;
+#[cfg(target_arch = "x86_64")]
+const SYS_SETRLIMIT: usize = 160;
+#[cfg(target_arch = "aarch64")]
+const SYS_SETRLIMIT: usize = 164;
+
+/// Same value on every architecture supported
+const ENOSYS: isize = 38;
+
+const UNKNOWN: u8 = 0;
+const PRESENT: u8 = 1;
+const MISSING: u8 = 2;
+
+static PRLIMIT: AtomicU8 = AtomicU8::new(UNKNOWN);
+
+/// Kernel's `struct rlimit64`, 64 bits on every architecture
+#[repr(C)]
+struct Rlimit64 {
+	rlim_cur: u64,
+	rlim_max: u64,
+}
+
+/// Fetch the limits of the resource of the current process
+pub(crate) fn getrlimit(resource: libc::c_int) -> io::Result<Rlimit> {
+	if !has_prlimit() {
+		let mut rlim = Rlimit64 { rlim_cur: 0, rlim_max: 0 };
+		let ptr = &mut rlim as *mut Rlimit64 as usize;
+		check(unsafe { syscall4(SYS_GETRLIMIT, resource as usize, ptr, 0, 0) })?;
+		return Ok(Rlimit { soft: rlim.rlim_cur, hard: rlim.rlim_max });
+	}
+	prlimit(0, resource, None)
+}
+
+/// Set the limits of the resource of the current process
+pub(crate) fn setrlimit(resource: libc::c_int, rlim: Rlimit) -> io::Result<()> {
+	if !has_prlimit() {
+		let rlim = Rlimit64 { rlim_cur: rlim.soft, rlim_max: rlim.hard };
+		let ptr = &rlim as *const Rlimit64 as usize;
+		return check(unsafe { syscall4(SYS_SETRLIMIT, resource as usize, ptr, 0, 0) });
+	}
+	prlimit(0, resource, Some(rlim)).map(drop)
+}
+
+/// Set the limits of the resource of a process (0 for the calling one) if
+/// given, returning the previous limits
+pub(crate) fn prlimit(
+	pid: libc::pid_t,
+	resource: libc::c_int,
+	new: Option<Rlimit>,
+) -> io::Result<Rlimit> {
+	let new = new.map(|rlim| Rlimit64 { rlim_cur: rlim.soft, rlim_max: rlim.hard });
+	let new_ptr = new.as_ref().map_or(std::ptr::null(), |new| new as *const Rlimit64);
+	let mut old = Rlimit64 { rlim_cur: 0, rlim_max: 0 };
+	let ret = unsafe {
+		syscall4(
+			SYS_PRLIMIT64,
+			pid as usize,
+			resource as usize,
+			new_ptr as usize,
+			&mut old as *mut Rlimit64 as usize,
+		)
+	};
+	check(ret)?;
+
+	Ok(Rlimit { soft: old.rlim_cur, hard: old.rlim_max })
+}
+
+/// Whether the kernel has `prlimit64`, probed once with a resource out of
+/// range that it rejects with `EINVAL` before doing anything, old kernels fail
+/// with `ENOSYS` instead
+fn has_prlimit() -> bool {
+	match PRLIMIT.load(Ordering::Relaxed) {
+		PRESENT => true,
+		MISSING => false,
+		_ => {
+			let resource = libc::c_int::MAX as usize;
+			let ret = unsafe { syscall4(SYS_PRLIMIT64, 0, resource, 0, 0) };
+			let present = ret != -ENOSYS;
+			PRLIMIT.store(if present { PRESENT } else { MISSING }, Ordering::Relaxed);
+			present
+		}
+	}
+}
+
+/// Error of a system call returning a negated errno
+fn check(ret: isize) -> io::Result<()> {
+	match ret {
+		0.. => Ok(()),
+		_ => Err(io::Error::from_raw_os_error(-ret as i32)),
+	}
+}
+
+#[cfg(target_arch = "x86_64")]
+unsafe fn syscall4(n: usize, a1: usize, a2: usize, a3: usize, a4: usize) -> isize {
+	let ret: isize;
+	std::arch::asm!(
+		"syscall",
+		inlateout("rax") n as isize => ret,
+		in("rdi") a1,
+		in("rsi") a2,
+		in("rdx") a3,
+		in("r10") a4,
+		lateout("rcx") _,
+		lateout("r11") _,
+		options(nostack),
+	);
+	ret
+}
+
+#[cfg(target_arch = "aarch64")]
+unsafe fn syscall4(n: usize, a1: usize, a2: usize, a3: usize, a4: usize) -> isize {
+	let ret: isize;
+	std::arch::asm!(
+		"svc 0",
+		in("x8") n,
+		inlateout("x0") a1 as isize => ret,
+		in("x1") a2,
+		in("x2") a3,
+		in("x3") a4,
+		options(nostack),
+	);
+	ret
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn detects_prlimit() {
+		assert!(has_prlimit());
+		assert_eq!(PRLIMIT.load(Ordering::Relaxed), PRESENT);
+	}
+
+	#[test]
+	fn matches_libc() {
+		let rlim = getrlimit(libc::RLIMIT_NOFILE as libc::c_int).unwrap();
+		let mut expected = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+		assert_eq!(unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut expected) }, 0);
+		assert_eq!((rlim.soft, rlim.hard), (expected.rlim_cur, expected.rlim_max));
+	}
+}
@@ -0,0 +1,81 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Outcomes reported to systemd through the notification socket.
+
+use crate::{Error, Limit, Outcome, Resource};
+use std::io;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+/// Send a notification such as `READY=1` to the service manager, returns
+/// whether there was one to send it to (`NOTIFY_SOCKET` is set)
+pub fn sd_notify(state: &str) -> io::Result<bool> {
+	let Some(path) = std::env::var_os("NOTIFY_SOCKET") else { return Ok(false) };
+	let path = path.to_string_lossy();
+	let addr = match path.strip_prefix('@') {
+		Some(name) => SocketAddr::from_abstract_name(name)?,
+		None => SocketAddr::from_pathname(&*path)?,
+	};
+	UnixDatagram::unbound()?.send_to_addr(state.as_bytes(), &addr)?;
+	Ok(true)
+}
+
+/// Report the outcome of raising the resource limit so that `systemctl
+/// status` shows it, with `FDLIMIT_RESOURCE`, `FDLIMIT_OLD` and
+/// `FDLIMIT_NEW` fields, or `FDLIMIT_ERROR` on failure.
+///
+/// Returns whether the process runs under a service manager. Needs
+/// `NotifyAccess=all` in the unit unless called from the main process.
+pub fn notify_outcome(resource: Resource, result: &Result<Outcome, Error>) -> io::Result<bool> {
+	sd_notify(&outcome_state(resource, result))
+}
+
+fn outcome_state(resource: Resource, result: &Result<Outcome, Error>) -> String {
+	match result {
+		Ok(Outcome::LimitRaised { from, to }) => {
+			let (from, to) = (Limit::from_raw(*from), Limit::from_raw(*to));
+			format!(
+				"STATUS={resource} limit raised from {from} to {to}\n\
+				 FDLIMIT_RESOURCE={resource}\nFDLIMIT_OLD={from}\nFDLIMIT_NEW={to}"
+			)
+		}
+		Ok(Outcome::Unsupported) => {
+			format!("STATUS={resource} limit can't be raised here\nFDLIMIT_RESOURCE={resource}")
+		}
+		// Notifications are newline-separated assignments
+		Err(error) => {
+			let error = error.to_string().replace('\n', " ");
+			format!(
+				"STATUS=failed to raise {resource} limit: {error}\n\
+				 FDLIMIT_RESOURCE={resource}\nFDLIMIT_ERROR={error}"
+			)
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn outcome_shows_unlimited() {
+		let outcome = Ok(Outcome::LimitRaised { from: 1024, to: crate::backend::RLIM_INFINITY });
+		assert_eq!(
+			outcome_state(Resource::NoFile, &outcome),
+			"STATUS=nofile limit raised from 1024 to unlimited\nFDLIMIT_RESOURCE=nofile\n\
+			 FDLIMIT_OLD=1024\nFDLIMIT_NEW=unlimited"
+		);
+	}
+}
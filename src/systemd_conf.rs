@@ -0,0 +1,222 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Default limits of the systemd managers, from `system.conf` and `user.conf`.
+
+use crate::{Limit, Resource};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Directories of the manager configuration, from the highest priority
+const CONFIG_DIRS: &[&str] =
+	&["/etc/systemd", "/run/systemd", "/usr/local/lib/systemd", "/usr/lib/systemd"];
+
+/// Systemd manager whose defaults are read
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SystemdManager {
+	/// System manager, PID 1, configured in `system.conf`
+	System,
+	/// Per-user manager, `user@.service`, configured in `user.conf`
+	User,
+}
+
+impl SystemdManager {
+	fn file_name(self) -> &'static str {
+		match self {
+			SystemdManager::System => "system.conf",
+			SystemdManager::User => "user.conf",
+		}
+	}
+}
+
+/// Limit a systemd manager gives to its units without their own `Limit*=`
+/// directive, from a `DefaultLimit*=` setting
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SystemdDefault {
+	/// Resource limited
+	pub resource: Resource,
+	/// Default soft limit
+	pub soft: Limit,
+	/// Default hard limit
+	pub hard: Limit,
+	/// File of the setting in effect, the last one read setting it
+	pub path: PathBuf,
+}
+
+/// For example `DefaultLimitNOFILE=1024:524288 (/etc/systemd/system.conf)`
+impl fmt::Display for SystemdDefault {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let directive = crate::remediation::systemd_directive(self.resource).unwrap_or("Limit?");
+		write!(f, "Default{directive}={}:{} ({})", self.soft, self.hard, self.path.display())
+	}
+}
+
+/// Default limits set by the given manager's configuration.
+///
+/// Reads the main file, the first of `/etc/systemd`, `/run/systemd`,
+/// `/usr/local/lib/systemd` and `/usr/lib/systemd` having it, then the
+/// `.conf` drop-ins of the `system.conf.d` or `user.conf.d` directories
+/// there sorted by file name, as the manager does. Later settings win, files
+/// that can't be read and invalid values are skipped. Resources the manager
+/// leaves alone are missing, everything is on systems without systemd.
+pub fn systemd_default_limits(manager: SystemdManager) -> BTreeMap<Resource, SystemdDefault> {
+	let mut defaults = BTreeMap::new();
+	for path in config_files(manager) {
+		if let Ok(contents) = std::fs::read_to_string(&path) {
+			parse(&contents, &path, &mut defaults);
+		}
+	}
+	defaults
+}
+
+/// Default limit of the resource set by the given manager's configuration,
+/// see [`systemd_default_limits`]
+pub fn systemd_default_limit(
+	manager: SystemdManager,
+	resource: Resource,
+) -> Option<SystemdDefault> {
+	systemd_default_limits(manager).remove(&resource)
+}
+
+/// Files read by the manager, in order
+fn config_files(manager: SystemdManager) -> Vec<PathBuf> {
+	let name = manager.file_name();
+	let mut files: Vec<PathBuf> = CONFIG_DIRS
+		.iter()
+		.map(|dir| Path::new(dir).join(name))
+		.find(|path| path.is_file())
+		.into_iter()
+		.collect();
+	// A drop-in overrides the ones of the same name in lower priority directories
+	let mut drop_ins = BTreeMap::<OsString, PathBuf>::new();
+	for dir in CONFIG_DIRS {
+		let Ok(entries) = std::fs::read_dir(Path::new(dir).join(format!("{name}.d"))) else {
+			continue;
+		};
+		for entry in entries.flatten() {
+			let path = entry.path();
+			if path.extension().is_some_and(|extension| extension == "conf") {
+				drop_ins.entry(entry.file_name()).or_insert(path);
+			}
+		}
+	}
+	files.extend(drop_ins.into_values());
+	files
+}
+
+/// Collect the `DefaultLimit*=` settings of the [Manager] section
+fn parse(contents: &str, path: &Path, defaults: &mut BTreeMap<Resource, SystemdDefault>) {
+	let mut in_manager = false;
+	for line in contents.lines().map(str::trim) {
+		if line.is_empty() || line.starts_with(['#', ';']) {
+			continue;
+		}
+		if line.starts_with('[') {
+			in_manager = line == "[Manager]";
+			continue;
+		}
+		let Some((key, value)) = line.split_once('=') else { continue };
+		let Some(directive) = key.trim().strip_prefix("Default") else { continue };
+		if !in_manager {
+			continue;
+		}
+		let Some(resource) = Resource::ALL
+			.iter()
+			.copied()
+			.find(|&resource| crate::remediation::systemd_directive(resource) == Some(directive))
+		else {
+			continue;
+		};
+		let value = value.trim();
+		// An empty value resets the setting to the built-in default
+		if value.is_empty() {
+			defaults.remove(&resource);
+			continue;
+		}
+		let (soft, hard) = value.split_once(':').unwrap_or((value, value));
+		if let (Ok(soft), Ok(hard)) = (soft.parse::<Limit>(), hard.parse::<Limit>()) {
+			defaults
+				.insert(resource, SystemdDefault { resource, soft, hard, path: path.to_owned() });
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn parsed(files: &[(&str, &str)]) -> BTreeMap<Resource, SystemdDefault> {
+		let mut defaults = BTreeMap::new();
+		for (path, contents) in files {
+			parse(contents, Path::new(path), &mut defaults);
+		}
+		defaults
+	}
+
+	fn limits(defaults: &BTreeMap<Resource, SystemdDefault>) -> Vec<(Resource, Limit, Limit)> {
+		defaults.values().map(|default| (default.resource, default.soft, default.hard)).collect()
+	}
+
+	#[test]
+	fn reads_the_manager_section() {
+		let defaults = parsed(&[(
+			"system.conf",
+			"# DefaultLimitNOFILE=1\n\
+			 [Manager]\n\
+			 DefaultLimitNOFILE=1024:524288\n\
+			 DefaultLimitNPROC = 4096\n\
+			 DefaultLimitCORE=0:infinity\n\
+			 DefaultTimeoutStartSec=90s\n\
+			 [Other]\n\
+			 DefaultLimitMEMLOCK=65536\n",
+		)]);
+		assert_eq!(
+			limits(&defaults),
+			[
+				(Resource::NoFile, Limit::Finite(1024), Limit::Finite(524288)),
+				(Resource::NProc, Limit::Finite(4096), Limit::Finite(4096)),
+				(Resource::Core, Limit::Finite(0), Limit::Unlimited),
+			]
+		);
+		assert_eq!(
+			defaults[&Resource::NoFile].to_string(),
+			"DefaultLimitNOFILE=1024:524288 (system.conf)"
+		);
+	}
+
+	#[test]
+	fn later_files_win() {
+		let defaults = parsed(&[
+			("system.conf", "[Manager]\nDefaultLimitNOFILE=1024\nDefaultLimitNPROC=100\n"),
+			("system.conf.d/limits.conf", "[Manager]\nDefaultLimitNOFILE=infinity\n"),
+			("system.conf.d/reset.conf", "[Manager]\nDefaultLimitNPROC=\n"),
+		]);
+		assert_eq!(limits(&defaults), [(Resource::NoFile, Limit::Unlimited, Limit::Unlimited)]);
+		assert_eq!(defaults[&Resource::NoFile].path, Path::new("system.conf.d/limits.conf"));
+	}
+
+	#[test]
+	fn skips_invalid_values() {
+		let defaults = parsed(&[(
+			"system.conf",
+			"[Manager]\nDefaultLimitNOFILE=1024\nDefaultLimitNOFILE=lots\nDefaultLimitFOO=1\n",
+		)]);
+		assert_eq!(
+			limits(&defaults),
+			[(Resource::NoFile, Limit::Finite(1024), Limit::Finite(1024))]
+		);
+	}
+}
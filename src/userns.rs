@@ -0,0 +1,102 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Detection of user namespaces, where even root can't raise hard limits.
+
+use std::fmt;
+
+/// Range of user ids of a user namespace mapped to ids of its parent, a line
+/// of `/proc/self/uid_map`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IdMapping {
+	/// First id inside the namespace
+	pub inside: u32,
+	/// Id it's mapped to in the parent namespace
+	pub outside: u32,
+	/// Number of ids mapped
+	pub count: u32,
+}
+
+/// User namespace of the process other than the initial one, as created by
+/// rootless containers, see [`user_namespace`].
+///
+/// Raising a hard limit needs `CAP_SYS_RESOURCE` in the initial namespace, so
+/// inside this one the hard limits set when it was entered are a ceiling
+/// even for its root user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserNamespace {
+	/// Mapping of the user ids to the parent namespace
+	pub uid_map: Vec<IdMapping>,
+}
+
+impl UserNamespace {
+	/// Id in the parent namespace of a user of this one
+	pub fn outside_uid(&self, uid: u32) -> Option<u32> {
+		self.uid_map.iter().find_map(|mapping| {
+			let offset =
+				uid.checked_sub(mapping.inside).filter(|&offset| offset < mapping.count)?;
+			mapping.outside.checked_add(offset)
+		})
+	}
+}
+
+/// User namespace of the current process, `None` in the initial namespace
+/// and on platforms other than Linux.
+///
+/// Told from `/proc/self/uid_map`, which maps every id to itself in the
+/// initial namespace.
+pub fn user_namespace() -> Option<UserNamespace> {
+	if !cfg!(target_os = "linux") {
+		return None;
+	}
+	let contents = std::fs::read_to_string("/proc/self/uid_map").ok()?;
+	let uid_map = contents
+		.lines()
+		.filter_map(|line| {
+			let mut fields = line.split_whitespace().map(str::parse::<u32>);
+			let inside = fields.next()?.ok()?;
+			let outside = fields.next()?.ok()?;
+			let count = fields.next()?.ok()?;
+			Some(IdMapping { inside, outside, count })
+		})
+		.collect::<Vec<_>>();
+	let initial = IdMapping { inside: 0, outside: 0, count: u32::MAX };
+	(uid_map != [initial]).then_some(UserNamespace { uid_map })
+}
+
+/// For example `uids 0 as 1000, 1-65536 as 100000-165535 outside`
+impl fmt::Display for UserNamespace {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if self.uid_map.is_empty() {
+			return f.write_str("no uid mapped");
+		}
+		f.write_str("uids ")?;
+		for (index, mapping) in self.uid_map.iter().enumerate() {
+			if index > 0 {
+				f.write_str(", ")?;
+			}
+			let IdMapping { inside, outside, count } = *mapping;
+			match count {
+				1 => write!(f, "{inside} as {outside}")?,
+				_ => write!(
+					f,
+					"{inside}-{} as {outside}-{}",
+					inside.saturating_add(count - 1),
+					outside.saturating_add(count - 1)
+				)?,
+			}
+		}
+		f.write_str(" outside")
+	}
+}
@@ -0,0 +1,51 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Startup checks for Substrate and Polkadot validators.
+
+use crate::{preflight, Requirement, Resource, Setting, Summary};
+
+const NOFILE: Requirement =
+	Requirement { setting: Setting::Limit(Resource::NoFile), minimum: 10_000, recommended: 65_536 };
+
+#[cfg(target_os = "linux")]
+const SYSCTLS: &[Requirement] = &[
+	Requirement {
+		setting: Setting::Sysctl("fs.inotify.max_user_watches"),
+		minimum: 8192,
+		recommended: 524_288,
+	},
+	Requirement {
+		setting: Setting::Sysctl("fs.inotify.max_user_instances"),
+		minimum: 128,
+		recommended: 512,
+	},
+	Requirement {
+		setting: Setting::Sysctl("vm.max_map_count"),
+		minimum: 65_530,
+		recommended: 262_144,
+	},
+	Requirement { setting: Setting::Sysctl("net.core.somaxconn"), minimum: 128, recommended: 1024 },
+];
+#[cfg(not(target_os = "linux"))]
+const SYSCTLS: &[Requirement] = &[];
+
+/// Check the settings a validator node needs: the open files limit and, on
+/// Linux, the inotify limits, `vm.max_map_count` and `net.core.somaxconn`.
+///
+/// Meant to be printed at node startup, after the limits were raised.
+pub fn validator_preflight() -> Summary {
+	let requirements: Vec<_> = std::iter::once(NOFILE).chain(SYSCTLS.iter().copied()).collect();
+	Summary { findings: preflight(&requirements) }
+}
@@ -0,0 +1,86 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{Builder, Finding, Report, Requirement, Resource};
+
+/// Limits raised and kernel settings checked by [`ensure_watcher_limits`]
+#[derive(Debug)]
+pub struct WatcherReport {
+	/// Result of raising the open files limit, and the kqueue limit on FreeBSD
+	pub limits: Report,
+	/// Kernel settings after trying to raise them, the inotify limits on Linux
+	/// and `kern.maxfilesperproc` elsewhere
+	pub findings: Vec<Finding>,
+}
+
+impl WatcherReport {
+	/// Whether every limit was raised and every setting meets its
+	/// recommendation
+	pub fn is_ok(&self) -> bool {
+		self.limits.is_ok()
+			&& self.findings.iter().all(|finding| finding.severity == crate::Severity::Ok)
+	}
+}
+
+#[cfg(target_os = "linux")]
+const SETTINGS: &[Requirement] = &[
+	Requirement {
+		setting: crate::Setting::Sysctl("fs.inotify.max_user_watches"),
+		minimum: 8192,
+		recommended: 524_288,
+	},
+	Requirement {
+		setting: crate::Setting::Sysctl("fs.inotify.max_user_instances"),
+		minimum: 128,
+		recommended: 512,
+	},
+];
+
+// kqueue needs a descriptor per watched file
+#[cfg(any(target_vendor = "apple", target_os = "freebsd"))]
+const SETTINGS: &[Requirement] = &[Requirement {
+	setting: crate::Setting::Sysctl("kern.maxfilesperproc"),
+	minimum: 10_240,
+	recommended: 65_536,
+}];
+
+#[cfg(not(any(target_vendor = "apple", target_os = "linux", target_os = "freebsd")))]
+const SETTINGS: &[Requirement] = &[];
+
+/// Prepare the process for watching many files: raise the open files limit,
+/// the kqueue limit on FreeBSD, and on Linux the inotify limits when they're
+/// below the recommendation and the process may change them.
+pub fn ensure_watcher_limits() -> WatcherReport {
+	let mut resources = vec![Resource::NoFile];
+	if Resource::KQueues.is_available() {
+		resources.push(Resource::KQueues);
+	}
+	let results = resources
+		.into_iter()
+		.map(|resource| (resource, Builder::new().resource(resource).raise()))
+		.collect();
+
+	#[cfg(target_os = "linux")]
+	for (requirement, finding) in SETTINGS.iter().zip(crate::preflight(SETTINGS)) {
+		if let (crate::Setting::Sysctl(name), crate::Severity::Warning | crate::Severity::Error) =
+			(requirement.setting, finding.severity)
+		{
+			// Needs CAP_SYS_ADMIN, the findings tell what's left to do otherwise
+			let path = format!("/proc/sys/{}", name.replace('.', "/"));
+			let _ = std::fs::write(path, requirement.recommended.to_string());
+		}
+	}
+
+	WatcherReport { limits: Report { results }, findings: crate::preflight(SETTINGS) }
+}
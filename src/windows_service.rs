@@ -0,0 +1,127 @@
+// Copyright 2016-2020 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Setup of a Windows service's limits at start, reported to the event log.
+
+use crate::{Error, Outcome};
+use std::ffi::c_void;
+use std::io;
+
+/// Ceiling of `_setmaxstdio` in the Universal C runtime, older runtimes
+/// stop at 2048
+const MAX_STDIO: &[i32] = &[8192, 2048];
+
+const EVENTLOG_WARNING_TYPE: u16 = 2;
+const EVENTLOG_INFORMATION_TYPE: u16 = 4;
+
+extern "C" {
+	fn _setmaxstdio(new_max: i32) -> i32;
+}
+
+#[link(name = "kernel32")]
+extern "system" {
+	fn GetCurrentProcess() -> *mut c_void;
+	fn GetProcessHandleCount(process: *mut c_void, count: *mut u32) -> i32;
+}
+
+#[link(name = "advapi32")]
+extern "system" {
+	fn RegisterEventSourceW(server: *const u16, source: *const u16) -> *mut c_void;
+	fn ReportEventW(
+		log: *mut c_void,
+		kind: u16,
+		category: u16,
+		event_id: u32,
+		user_sid: *mut c_void,
+		num_strings: u16,
+		data_size: u32,
+		strings: *const *const u16,
+		data: *mut c_void,
+	) -> i32;
+	fn DeregisterEventSource(log: *mut c_void) -> i32;
+}
+
+/// What [`setup_windows_service`] did
+#[derive(Debug)]
+pub struct ServiceSetup {
+	/// Change of the max number of C runtime streams
+	pub stdio: Outcome,
+	/// Number of handles open in the process, `None` if it couldn't be read
+	pub handles: Option<u64>,
+	/// Result of writing the outcome to the event log
+	pub event_log: io::Result<()>,
+}
+
+/// Raise the max number of C runtime streams (`_setmaxstdio`) as far as the
+/// runtime allows, count the open handles and write both to the Application
+/// event log under `source`, naming the service.
+///
+/// Call it first thing in the service's main function. Failing to write to
+/// the event log doesn't fail the setup, it's reported in
+/// [`ServiceSetup::event_log`].
+pub fn setup_windows_service(source: &str) -> Result<ServiceSetup, Error> {
+	let from = crate::max_stdio().unwrap_or(0);
+	let raised = MAX_STDIO.iter().find_map(|&max| u64::try_from(unsafe { _setmaxstdio(max) }).ok());
+	let Some(to) = raised else {
+		// The runtime sets errno rather than the thread's last error
+		let error = io::Error::new(io::ErrorKind::InvalidInput, "_setmaxstdio rejected the limit");
+		let message = format!("fdlimit: failed to raise the C runtime streams limit from {from}");
+		let _ = report(source, EVENTLOG_WARNING_TYPE, &message);
+		return Err(Error::FailedToSetLimit { from, to: MAX_STDIO[0] as u64, error });
+	};
+
+	let handles = handle_count();
+	let message = match handles {
+		Some(handles) => format!(
+			"fdlimit: C runtime streams limit raised from {from} to {to}, {handles} handles open"
+		),
+		None => format!("fdlimit: C runtime streams limit raised from {from} to {to}"),
+	};
+	let event_log = report(source, EVENTLOG_INFORMATION_TYPE, &message);
+
+	Ok(ServiceSetup { stdio: Outcome::LimitRaised { from, to }, handles, event_log })
+}
+
+fn handle_count() -> Option<u64> {
+	let mut count = 0;
+	(unsafe { GetProcessHandleCount(GetCurrentProcess(), &mut count) } != 0)
+		.then_some(u64::from(count))
+}
+
+/// Write one event with the message to the Application log
+fn report(source: &str, kind: u16, message: &str) -> io::Result<()> {
+	let wide = |s: &str| s.encode_utf16().chain([0]).collect::<Vec<u16>>();
+	let (source, message) = (wide(source), wide(message));
+	unsafe {
+		let log = RegisterEventSourceW(std::ptr::null(), source.as_ptr());
+		if log.is_null() {
+			return Err(io::Error::last_os_error());
+		}
+		let strings = [message.as_ptr()];
+		let reported = ReportEventW(
+			log,
+			kind,
+			0,
+			0,
+			std::ptr::null_mut(),
+			1,
+			0,
+			strings.as_ptr(),
+			std::ptr::null_mut(),
+		);
+		let error = (reported == 0).then(io::Error::last_os_error);
+		DeregisterEventSource(log);
+		error.map_or(Ok(()), Err)
+	}
+}